@@ -1,11 +1,52 @@
 import { utils, writeFile } from "xlsx";
 import moment from "moment";
+import type { Moment } from "moment";
+import { writable } from "svelte/store";
 
 import lagerStartString from "../../lager-start.txt?raw";
 import type { Snippet } from "svelte";
+/** Start of the camp when the website was built, the current dates are in `lager`. */
 export const LAGER_START = moment(lagerStartString, "YYYY-MM-DD");
 export const YEAR = LAGER_START.year();
 
+export interface Lager {
+	name: string;
+	beginn: Moment;
+	ende: Moment;
+	/** Participation fee in Euro */
+	preis: number;
+}
+
+/** The current camp, updated by `loadLager`. */
+export const lager = writable<Lager>({
+	name: `Zeltlager ${YEAR}`,
+	beginn: LAGER_START,
+	ende: LAGER_START.clone().add(11, "days"),
+	preis: 250,
+});
+
+let lagerLoaded: Promise<void> | undefined;
+
+/** Load the dates and price of the current camp from the server, they can change without a rebuild. */
+export function loadLager(): Promise<void> {
+	lagerLoaded ??= (async () => {
+		try {
+			const resp = await fetch("/api/lager");
+			if (!resp.ok) throw new Error(`Status ${resp.status}`);
+			const data = await resp.json();
+			lager.set({
+				name: data.name,
+				beginn: moment(data.beginn, "YYYY-MM-DD"),
+				ende: moment(data.ende, "YYYY-MM-DD"),
+				preis: data.preis,
+			});
+		} catch (e) {
+			console.error("Failed to load camp dates", e);
+		}
+	})();
+	return lagerLoaded;
+}
+
 export type EnumValue = string | { name: string; displayName?: string; };
 
 export interface Column {
@@ -23,6 +64,66 @@ export interface FormError {
 	message: string;
 }
 
+/// Description of a form field from `/api/form-schema`.
+export interface FieldSchema {
+	id: string;
+	label: string;
+	type: "text" | "email" | "date" | "enum" | "freetext" | "checkbox";
+	required: boolean;
+	values?: string[];
+	minLength?: number;
+	lengthByCountry?: [string, number][];
+	digitsOnly?: boolean;
+	needsDigit?: boolean;
+	minDate?: string;
+	maxDate?: string;
+}
+
+export async function loadFormSchema(name: string): Promise<FieldSchema[] | undefined> {
+	try {
+		const response = await fetch(`/api/form-schema/${name}`);
+		if (response.ok) return await response.json();
+	} catch (e) {
+		console.error("Failed to load form schema", e);
+	}
+	return undefined;
+}
+
+/// Check a form with the same rules as the server, returns all errors.
+export function validateForm(schema: FieldSchema[], form: HTMLFormElement): FormError[] {
+	const errors: FormError[] = [];
+	const value = (id: string) => (form[id]?.value ?? "").trim();
+	for (const f of schema) {
+		const v = f.type === "checkbox" ? String(form[f.id]?.checked ?? false) : value(f.id);
+		const error = (message: string) => errors.push({ field: f.id, message });
+		if (v === "") {
+			if (f.required) error("Bitte ausfüllen");
+			continue;
+		}
+		if (f.type === "checkbox" && v !== "true") {
+			error("Bitte akzeptieren");
+		} else if (f.values && !f.values.includes(v)) {
+			error(`Muss eines von ${f.values.join(", ")} sein`);
+		} else if (f.digitsOnly && !/^[0-9]*$/.test(v)) {
+			error("Darf nur Ziffern enthalten");
+		} else if (f.needsDigit && !/[0-9]/.test(v)) {
+			error("Muss mindestens eine Ziffer enthalten");
+		} else if (f.type === "email" && (v.split("@").length !== 2 || v.includes(" "))) {
+			error("Ungültige E-Mail-Adresse");
+		} else if (f.type === "date") {
+			const date = moment(v, ["DD.MM.YYYY", "D.M.YYYY", "YYYY-MM-DD"], true);
+			if (!date.isValid()) error("Bitte im Format TT.MM.JJJJ angeben");
+			else if (f.minDate && date.isBefore(f.minDate))
+				error(`Muss nach dem ${moment(f.minDate).format("DD.MM.YYYY")} sein`);
+			else if (f.maxDate && date.isAfter(f.maxDate))
+				error(`Muss vor dem ${moment(f.maxDate).format("DD.MM.YYYY")} sein`);
+		}
+		const length = f.lengthByCountry?.find(([c]) => c === value("land"))?.[1];
+		if (length !== undefined && v.length !== length) error(`Muss ${length} Stellen haben`);
+	}
+	return errors;
+}
+
 export async function sleep(timeout: number): Promise<void> {
 	return new Promise((resolve) => setTimeout(resolve, timeout));
 }
@@ -0,0 +1,16 @@
+CREATE TABLE stornierung (
+	id SERIAL PRIMARY KEY,
+	vorname TEXT NOT NULL,
+	nachname TEXT NOT NULL,
+	geburtsdatum DATE NOT NULL,
+	eltern_name TEXT NOT NULL,
+	eltern_mail TEXT NOT NULL,
+	anmeldedatum TIMESTAMPTZ NOT NULL,
+	stornodatum TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+	bezahlt BOOLEAN NOT NULL,
+	-- Refund in percent of the fee according to the AGB
+	erstattung_prozent INTEGER NOT NULL,
+	-- Refund in Euro
+	erstattung INTEGER NOT NULL,
+	erstattet BOOLEAN NOT NULL DEFAULT FALSE
+);
@@ -0,0 +1 @@
+DROP TABLE anmeldung_entwurf;
@@ -0,0 +1,8 @@
+-- Partially filled signup forms, which can be continued later
+CREATE TABLE anmeldung_entwurf (
+	token TEXT PRIMARY KEY,
+	eltern_mail TEXT NOT NULL,
+	-- Unvalidated form content as json object
+	daten TEXT NOT NULL,
+	geaendert TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,2 @@
+DROP TABLE rueckkehrer;
+ALTER TABLE teilnehmer DROP COLUMN daten_behalten;
@@ -0,0 +1,13 @@
+-- Parents can agree to keep their data for the next year
+ALTER TABLE teilnehmer
+	ADD COLUMN daten_behalten BOOLEAN NOT NULL DEFAULT FALSE;
+
+-- Data of last year's participants whose parents agreed to keep it
+CREATE TABLE rueckkehrer (
+	id SERIAL PRIMARY KEY,
+	eltern_mail TEXT NOT NULL,
+	-- Content of the signup form as json object
+	daten TEXT NOT NULL,
+	token TEXT,
+	token_zeit TIMESTAMPTZ
+);
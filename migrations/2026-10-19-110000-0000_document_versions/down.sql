@@ -0,0 +1,2 @@
+DROP TABLE zustimmung;
+DROP TABLE dokument_version;
@@ -0,0 +1,31 @@
+-- Versions of documents that have to be accepted at the signup
+CREATE TABLE dokument_version (
+	id SERIAL PRIMARY KEY,
+	-- agb, datenschutz or selbsterklaerung
+	dokument TEXT NOT NULL,
+	version TEXT NOT NULL,
+	-- Link to the document
+	datei TEXT NOT NULL,
+	-- SHA-256 of the document as hex
+	hash TEXT NOT NULL,
+	gueltig_ab DATE NOT NULL,
+	UNIQUE (dokument, version)
+);
+
+-- Accepted document versions of members and supervisors
+CREATE TABLE zustimmung (
+	id SERIAL PRIMARY KEY,
+	teilnehmer INTEGER REFERENCES teilnehmer(id) ON DELETE CASCADE,
+	betreuer INTEGER REFERENCES betreuer(id) ON DELETE CASCADE,
+	dokument_version INTEGER NOT NULL REFERENCES dokument_version(id),
+	zeit TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+	CHECK ((teilnehmer IS NULL) <> (betreuer IS NULL))
+);
+
+INSERT INTO dokument_version (dokument, version, datei, hash, gueltig_ab) VALUES
+	('agb', '2021', '/files/AgbZeltlager2021.pdf',
+		'3f57986cdcd8d63519fd3b05314d62ae9f8a4f28bd49b29837140417097fb647', '2021-01-01'),
+	('datenschutz', '2018', '/datenschutz',
+		'1b1488b2a56d9ba2ec2e790665a5631ef6be65402699e9acdc7a75761d526c7e', '2018-04-14'),
+	('selbsterklaerung', '2018', '/files/Selbstverpflichtungserklaerung.pdf',
+		'25925a88c5cc9c7875b7e1e3dc555f84693d4ff4a62f9531cd376d7856993017', '2018-04-14');
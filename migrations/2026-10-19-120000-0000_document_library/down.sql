@@ -0,0 +1,2 @@
+DROP TABLE datei_version;
+DROP TABLE datei;
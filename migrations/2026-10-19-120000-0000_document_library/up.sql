@@ -0,0 +1,19 @@
+-- Downloadable documents, managed by admins
+CREATE TABLE datei (
+	id SERIAL PRIMARY KEY,
+	-- Used in the url /dokumente/<name>
+	name TEXT NOT NULL UNIQUE,
+	titel TEXT NOT NULL,
+	-- Role that is needed to download the document, public if NULL
+	rolle TEXT
+);
+
+-- All uploaded versions of a document, the newest one is served
+CREATE TABLE datei_version (
+	id SERIAL PRIMARY KEY,
+	datei INTEGER NOT NULL REFERENCES datei(id) ON DELETE CASCADE,
+	dateiname TEXT NOT NULL,
+	mime TEXT NOT NULL,
+	inhalt BYTEA NOT NULL,
+	hochgeladen TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,2 @@
+DROP TABLE rundmail_empfaenger;
+DROP TABLE rundmail;
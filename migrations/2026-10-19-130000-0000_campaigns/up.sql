@@ -0,0 +1,21 @@
+-- Mails that were sent to many parents or supervisors
+CREATE TABLE rundmail (
+	id SERIAL PRIMARY KEY,
+	betreff TEXT NOT NULL,
+	-- Template of the text with placeholders like {vorname}
+	text TEXT NOT NULL,
+	zielgruppe TEXT NOT NULL,
+	erstellt TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Delivery log, one entry per sent mail
+CREATE TABLE rundmail_empfaenger (
+	id SERIAL PRIMARY KEY,
+	rundmail INTEGER NOT NULL REFERENCES rundmail(id) ON DELETE CASCADE,
+	name TEXT NOT NULL,
+	mail TEXT NOT NULL,
+	gesendet BOOLEAN NOT NULL DEFAULT FALSE,
+	-- Error message if sending failed
+	fehler TEXT,
+	zeit TIMESTAMPTZ
+);
@@ -0,0 +1,6 @@
+ALTER TABLE rundmail_empfaenger
+	DROP COLUMN postausgang,
+	ADD COLUMN gesendet BOOLEAN NOT NULL DEFAULT FALSE,
+	ADD COLUMN zeit TIMESTAMPTZ;
+
+DROP TABLE postausgang;
@@ -0,0 +1,29 @@
+-- Mails that are sent in the background
+CREATE TABLE postausgang (
+	id SERIAL PRIMARY KEY,
+	-- Envelope, the recipients are a json array and include bcc receivers
+	absender TEXT NOT NULL,
+	empfaenger TEXT NOT NULL,
+	-- Headers for the admin view
+	an TEXT NOT NULL,
+	betreff TEXT NOT NULL,
+	-- The formatted mail
+	nachricht BYTEA NOT NULL,
+	erstellt TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+	versuche INTEGER NOT NULL DEFAULT 0,
+	naechster_versuch TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+	-- Error of the last attempt
+	fehler TEXT,
+	gesendet TIMESTAMPTZ,
+	-- Sending failed too often, the mail is only resent manually
+	aufgegeben BOOLEAN NOT NULL DEFAULT FALSE
+);
+
+CREATE INDEX postausgang_offen ON postausgang (naechster_versuch)
+	WHERE gesendet IS NULL AND NOT aufgegeben;
+
+-- Campaign mails are sent through the outbox
+ALTER TABLE rundmail_empfaenger
+	DROP COLUMN gesendet,
+	DROP COLUMN zeit,
+	ADD COLUMN postausgang INTEGER REFERENCES postausgang(id) ON DELETE SET NULL;
@@ -0,0 +1,6 @@
+-- Mail templates changed by admins, the defaults are compiled into the binary
+CREATE TABLE mail_vorlage (
+	name TEXT PRIMARY KEY,
+	inhalt TEXT NOT NULL,
+	geaendert TIMESTAMPTZ NOT NULL DEFAULT now()
+);
@@ -0,0 +1,3 @@
+DROP TABLE unzustellbar;
+DROP INDEX postausgang_message_id;
+ALTER TABLE postausgang DROP COLUMN message_id;
@@ -0,0 +1,12 @@
+-- Match bounces to the sent mail
+ALTER TABLE postausgang ADD COLUMN message_id TEXT;
+CREATE INDEX postausgang_message_id ON postausgang (message_id);
+
+-- Addresses that bounced, members and supervisors with this address are flagged
+CREATE TABLE unzustellbar (
+	-- Lowercase address
+	mail TEXT PRIMARY KEY,
+	-- Status and diagnostic code of the delivery status notification
+	grund TEXT NOT NULL,
+	zeit TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
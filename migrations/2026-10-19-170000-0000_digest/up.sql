@@ -0,0 +1,8 @@
+-- Events for the daily summary mail to the additional receivers
+CREATE TABLE zusammenfassung (
+	id SERIAL PRIMARY KEY,
+	-- anmeldung, bezahlt, abmeldung, betreuer or fehler
+	art TEXT NOT NULL,
+	text TEXT NOT NULL,
+	zeit TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1 @@
+ALTER TABLE teilnehmer DROP COLUMN sprache;
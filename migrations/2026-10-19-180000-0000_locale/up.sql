@@ -0,0 +1,2 @@
+-- Language of the signup, used for mails to the parents
+ALTER TABLE teilnehmer ADD COLUMN sprache TEXT NOT NULL DEFAULT 'de';
@@ -0,0 +1,2 @@
+DROP TABLE protokoll;
+DROP FUNCTION protokoll_append_only;
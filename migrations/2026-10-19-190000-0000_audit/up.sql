@@ -0,0 +1,26 @@
+-- Append-only log of all changes in the admin area
+CREATE TABLE protokoll (
+	id SERIAL PRIMARY KEY,
+	zeit TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+	-- Local user (users.id) or OIDC subject of the acting user
+	benutzer INTEGER,
+	oidc_subjekt TEXT,
+	-- Changed entity, e.g. teilnehmer, and its id
+	objekt TEXT NOT NULL,
+	objekt_id TEXT,
+	aktion TEXT NOT NULL,
+	-- JSON object with the changed fields: { "field": { "alt": …, "neu": … } }
+	aenderung TEXT NOT NULL DEFAULT '{}'
+);
+CREATE INDEX protokoll_objekt ON protokoll (objekt, objekt_id);
+
+CREATE FUNCTION protokoll_append_only() RETURNS trigger AS $$
+BEGIN
+	RAISE EXCEPTION 'protokoll is append-only';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER protokoll_append_only BEFORE UPDATE OR DELETE ON protokoll
+	FOR EACH ROW EXECUTE FUNCTION protokoll_append_only();
+CREATE TRIGGER protokoll_no_truncate BEFORE TRUNCATE ON protokoll
+	FOR EACH STATEMENT EXECUTE FUNCTION protokoll_append_only();
@@ -0,0 +1,4 @@
+DELETE FROM teilnehmer WHERE geloescht IS NOT NULL;
+DELETE FROM betreuer WHERE geloescht IS NOT NULL;
+ALTER TABLE teilnehmer DROP COLUMN geloescht;
+ALTER TABLE betreuer DROP COLUMN geloescht;
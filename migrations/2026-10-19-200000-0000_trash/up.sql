@@ -0,0 +1,3 @@
+-- Deleted entries stay in the trash until they get purged
+ALTER TABLE teilnehmer ADD COLUMN geloescht TIMESTAMPTZ;
+ALTER TABLE betreuer ADD COLUMN geloescht TIMESTAMPTZ;
@@ -0,0 +1,2 @@
+ALTER TABLE teilnehmer DROP COLUMN version;
+ALTER TABLE betreuer DROP COLUMN version;
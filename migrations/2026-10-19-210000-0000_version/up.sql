@@ -0,0 +1,3 @@
+-- Incremented on every change to detect concurrent edits
+ALTER TABLE teilnehmer ADD COLUMN version INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE betreuer ADD COLUMN version INTEGER NOT NULL DEFAULT 0;
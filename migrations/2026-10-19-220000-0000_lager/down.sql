@@ -0,0 +1,4 @@
+DROP TABLE betreuer_teilnahme;
+ALTER TABLE erwischt_game DROP COLUMN lager;
+ALTER TABLE teilnehmer DROP COLUMN lager;
+DROP TABLE lager;
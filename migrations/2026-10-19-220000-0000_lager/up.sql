@@ -0,0 +1,25 @@
+-- One camp per season, the first one is created from the config at startup
+CREATE TABLE lager (
+	id SERIAL PRIMARY KEY,
+	name TEXT NOT NULL,
+	beginn DATE NOT NULL,
+	ende DATE NOT NULL,
+	-- Maximum number of participants
+	kapazitaet INTEGER NOT NULL,
+	-- Participation fee in Euro
+	preis INTEGER NOT NULL,
+	-- Signups and lists use the current camp
+	aktuell BOOLEAN NOT NULL DEFAULT FALSE,
+	CHECK (beginn <= ende)
+);
+CREATE UNIQUE INDEX lager_aktuell ON lager (aktuell) WHERE aktuell;
+
+ALTER TABLE teilnehmer ADD COLUMN lager INTEGER REFERENCES lager (id);
+ALTER TABLE erwischt_game ADD COLUMN lager INTEGER REFERENCES lager (id);
+
+-- Camps in which a supervisor takes part
+CREATE TABLE betreuer_teilnahme (
+	betreuer INTEGER NOT NULL REFERENCES betreuer (id) ON DELETE CASCADE,
+	lager INTEGER NOT NULL REFERENCES lager (id) ON DELETE CASCADE,
+	PRIMARY KEY (betreuer, lager)
+);
@@ -0,0 +1,8 @@
+-- Anonymized numbers of a camp, kept when the personal data is removed
+CREATE TABLE statistik (
+	id SERIAL PRIMARY KEY,
+	lager INTEGER NOT NULL UNIQUE REFERENCES lager (id) ON DELETE CASCADE,
+	erstellt TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+	-- JSON object with counts, see src/statistics.rs
+	daten TEXT NOT NULL
+);
@@ -0,0 +1 @@
+ALTER TABLE stornierung DROP COLUMN lager;
@@ -0,0 +1,3 @@
+-- Cancellations belong to the camp of the signup
+ALTER TABLE stornierung ADD COLUMN lager INTEGER REFERENCES lager (id);
+UPDATE stornierung SET lager = (SELECT id FROM lager WHERE aktuell);
@@ -0,0 +1,9 @@
+UPDATE rundmail SET zielgruppe = CASE zielgruppe
+	WHEN 'Alle Eltern' THEN 'eltern'
+	WHEN 'Eltern, die noch nicht bezahlt haben' THEN 'unbezahlt'
+	WHEN 'Eltern von Busfahrern' THEN 'bus'
+	WHEN 'Betreuer von diesem Jahr' THEN 'betreuer'
+	ELSE zielgruppe
+END;
+
+DROP TABLE zielgruppe;
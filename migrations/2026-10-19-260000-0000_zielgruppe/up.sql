@@ -0,0 +1,28 @@
+-- Saved recipient groups for campaigns
+CREATE TABLE zielgruppe (
+	id SERIAL PRIMARY KEY,
+	name TEXT NOT NULL UNIQUE,
+	-- Supervisors of this year instead of the parents of members
+	betreuer BOOLEAN NOT NULL DEFAULT FALSE,
+	-- Filters for members, NULL matches all
+	bezahlt BOOLEAN,
+	eigenanreise BOOLEAN,
+	-- Age at the start of the camp
+	alter_von INTEGER,
+	alter_bis INTEGER
+);
+
+INSERT INTO zielgruppe (name, betreuer, bezahlt, eigenanreise) VALUES
+	('Alle Eltern', FALSE, NULL, NULL),
+	('Eltern, die noch nicht bezahlt haben', FALSE, FALSE, NULL),
+	('Eltern von Busfahrern', FALSE, NULL, FALSE),
+	('Betreuer von diesem Jahr', TRUE, NULL, NULL);
+
+-- Campaigns store the name of their group
+UPDATE rundmail SET zielgruppe = CASE zielgruppe
+	WHEN 'eltern' THEN 'Alle Eltern'
+	WHEN 'unbezahlt' THEN 'Eltern, die noch nicht bezahlt haben'
+	WHEN 'bus' THEN 'Eltern von Busfahrern'
+	WHEN 'betreuer' THEN 'Betreuer von diesem Jahr'
+	ELSE zielgruppe
+END;
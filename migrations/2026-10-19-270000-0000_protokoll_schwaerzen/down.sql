@@ -0,0 +1,5 @@
+CREATE OR REPLACE FUNCTION protokoll_append_only() RETURNS trigger AS $$
+BEGIN
+	RAISE EXCEPTION 'protokoll is append-only';
+END;
+$$ LANGUAGE plpgsql;
@@ -0,0 +1,20 @@
+-- The only change allowed on the log: when the personal data of a participant, supervisor or
+-- Erwischt player is finally deleted, the values in its entries are blacked out (see
+-- audit::Audit::redact). Only aenderung may change and only in a transaction that sets
+-- zeltlager.protokoll_schwaerzen. Deleting entries is still rejected.
+CREATE OR REPLACE FUNCTION protokoll_append_only() RETURNS trigger AS $$
+BEGIN
+	IF TG_OP = 'UPDATE'
+		AND current_setting('zeltlager.protokoll_schwaerzen', true) = 'on'
+		AND NEW.id = OLD.id
+		AND NEW.zeit = OLD.zeit
+		AND NEW.benutzer IS NOT DISTINCT FROM OLD.benutzer
+		AND NEW.oidc_subjekt IS NOT DISTINCT FROM OLD.oidc_subjekt
+		AND NEW.objekt = OLD.objekt
+		AND NEW.objekt_id IS NOT DISTINCT FROM OLD.objekt_id
+		AND NEW.aktion = OLD.aktion THEN
+		RETURN NEW;
+	END IF;
+	RAISE EXCEPTION 'protokoll is append-only';
+END;
+$$ LANGUAGE plpgsql;
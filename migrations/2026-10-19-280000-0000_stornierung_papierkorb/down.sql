@@ -0,0 +1,2 @@
+DELETE FROM stornierung WHERE geloescht IS NOT NULL;
+ALTER TABLE stornierung DROP COLUMN geloescht;
@@ -0,0 +1,2 @@
+-- Refunded cancellations of a removed camp go into the trash first
+ALTER TABLE stornierung ADD COLUMN geloescht TIMESTAMPTZ;
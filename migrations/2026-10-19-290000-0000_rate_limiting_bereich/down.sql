@@ -0,0 +1,4 @@
+DELETE FROM rate_limiting WHERE bereich <> 'login';
+ALTER TABLE rate_limiting DROP CONSTRAINT rate_limiting_pkey;
+ALTER TABLE rate_limiting DROP COLUMN bereich;
+ALTER TABLE rate_limiting ADD PRIMARY KEY (ip_addr);
@@ -0,0 +1,4 @@
+-- Separate counters per IP address, mails without login must not block the login
+ALTER TABLE rate_limiting ADD COLUMN bereich TEXT NOT NULL DEFAULT 'login';
+ALTER TABLE rate_limiting DROP CONSTRAINT rate_limiting_pkey;
+ALTER TABLE rate_limiting ADD PRIMARY KEY (ip_addr, bereich);
@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::io::ErrorKind;
 use std::sync::Arc;
 use std::{fs, mem};
@@ -6,16 +7,18 @@ use anyhow::{Error, Result, bail};
 use axum::extract::Query;
 use axum::http::StatusCode;
 use axum::response::{IntoResponse, Response};
-use axum::{Json, extract};
+use axum::{Extension, Json, extract};
 use diesel::prelude::*;
-use diesel_async::RunQueryDsl;
+use diesel_async::{AsyncConnection, RunQueryDsl, scoped_futures::ScopedFutureExt};
 use rand::RngExt;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value, json};
 use tracing::{error, warn};
 
-use crate::db::models::{FullSupervisor, FullTeilnehmer, User};
-use crate::{ExtractState, State, WebResult, auth, db, mail, thumbs};
-use time::OffsetDateTime;
+use crate::audit::{self, Audit};
+use crate::db::models::{Cancellation, FullSupervisor, FullTeilnehmer, User};
+use crate::{ExtractState, State, WebResult, auth, bounce, db, mail, thumbs};
 
 type DbResult<T> = anyhow::Result<T>;
 
@@ -24,18 +27,58 @@ pub struct RemoveMemberData {
 	member: i32,
 }
 
+#[derive(Clone, Debug, Deserialize)]
+pub struct MembersQuery {
+	/// Members of an older lager instead of the current one.
+	lager: Option<i32>,
+}
+
 #[derive(Clone, Debug, Deserialize)]
 pub struct RemoveSupervisorData {
 	supervisor: i32,
 }
 
+#[derive(Clone, Debug, Deserialize)]
+pub struct MergeMemberData {
+	/// The entry that is kept.
+	keep: i32,
+	/// The duplicate entry that is merged into `keep` and removed.
+	remove: i32,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct CancellationRefundedData {
+	id: i32,
+	erstattet: bool,
+}
+
 #[derive(Clone, Debug, Serialize)]
 pub struct EditMemberResult {
 	error: Option<String>,
 }
 
+/// A member in the admin list.
+#[derive(Clone, Debug, Serialize)]
+pub struct ListedMember {
+	#[serde(flatten)]
+	member: FullTeilnehmer,
+	/// Reason why mails to the parents bounced.
+	mail_unzustellbar: Option<String>,
+}
+
+/// A supervisor in the admin list.
+#[derive(Clone, Debug, Serialize)]
+pub struct ListedSupervisor {
+	#[serde(flatten)]
+	supervisor: FullSupervisor,
+	/// Reason why mails bounced.
+	mail_unzustellbar: Option<String>,
+}
+
 #[derive(Clone, Debug, Serialize)]
 pub struct LagerInfo {
+	/// Name of the current lager.
+	lager: String,
 	teilnehmer_count: i64,
 	old_betreuer_count: i64,
 	erwischt_game_count: i64,
@@ -84,33 +127,230 @@ pub struct ImageLinkEntry {
 	url: String,
 }
 
+/// Result of saving an edited entry.
+enum Saved<T> {
+	/// Contains the entry before the change.
+	Ok(T),
+	/// The entry was changed by someone else in the meantime, contains the current entry.
+	Conflict(T),
+	/// The entry does not exist or is in the trash.
+	NotFound,
+}
+
+/// Overwrite fields of an entry for a partial update.
+///
+/// The fields have to contain the `version` of the entry that is changed.
+fn patch<T: Serialize + DeserializeOwned>(entry: &T, fields: Map<String, Value>) -> Result<T> {
+	if !fields.contains_key("version") {
+		bail!("Die Version des geänderten Eintrags fehlt");
+	}
+	let mut value = audit::value(entry);
+	let Value::Object(entry_fields) = &mut value else {
+		bail!("Eintrag kann nicht geändert werden");
+	};
+	for (name, v) in fields {
+		if name == "id" || !entry_fields.contains_key(&name) {
+			bail!("Unbekanntes Feld {name}");
+		}
+		entry_fields.insert(name, v);
+	}
+	Ok(serde_json::from_value(value)?)
+}
+
 fn err<T>(error: Error, msg: &'static str) -> WebResult<T> {
 	error!(%error, "{msg}");
 	Err((StatusCode::INTERNAL_SERVER_ERROR, msg).into_response())
 }
 
+/// Members which exist and are not in the trash.
+#[diesel::dsl::auto_type]
+fn active_member(member: i32) -> _ {
+	db::schema::teilnehmer::table.filter(
+		db::schema::teilnehmer::id.eq(member).and(db::schema::teilnehmer::geloescht.is_null()),
+	)
+}
+
+/// Supervisors which exist and are not in the trash.
+#[diesel::dsl::auto_type]
+fn active_supervisor(supervisor: i32) -> _ {
+	db::schema::betreuer::table.filter(
+		db::schema::betreuer::id.eq(supervisor).and(db::schema::betreuer::geloescht.is_null()),
+	)
+}
+
+/// Response to a change of an entry, `None` if the entry does not exist or is in the trash.
+fn changed(result: Result<Option<()>>, msg: &'static str, not_found: &'static str) -> Response {
+	match result {
+		Err(error) => {
+			error!(%error, "{msg}");
+			(StatusCode::INTERNAL_SERVER_ERROR, msg).into_response()
+		}
+		Ok(None) => (StatusCode::NOT_FOUND, not_found).into_response(),
+		Ok(Some(())) => "Success".into_response(),
+	}
+}
+
 pub(crate) async fn remove_member(
-	extract::State(state): ExtractState, Json(data): Json<RemoveMemberData>,
-) -> WebResult<&'static str> {
-	match async {
+	extract::State(state): ExtractState, Extension(audit): Extension<Audit>,
+	Json(data): Json<RemoveMemberData>,
+) -> Response {
+	let result = async {
 		use db::schema::teilnehmer;
 		use db::schema::teilnehmer::columns::*;
 
-		let r = diesel::delete(teilnehmer::table.filter(id.eq(data.member)))
-			.execute(&mut state.db.get().await?)
+		let audit = &audit;
+		state
+			.db
+			.get()
+			.await?
+			.transaction::<_, Error, _>(|con| {
+				async move {
+					let Some(member) = active_member(data.member)
+						.select(id)
+						.get_result::<i32>(con)
+						.await
+						.optional()?
+					else {
+						return Ok(None);
+					};
+					diesel::update(teilnehmer::table.filter(id.eq(member)))
+						.set(geloescht.eq(diesel::dsl::now))
+						.execute(con)
+						.await?;
+					audit
+						.log(
+							con,
+							"teilnehmer",
+							Some(member.to_string()),
+							"papierkorb",
+							json!({ "geloescht": false }),
+							json!({ "geloescht": true }),
+						)
+						.await?;
+					Ok(Some(()))
+				}
+				.scope_boxed()
+			})
+			.await
+	}
+	.await;
+	changed(result, "Failed to remove member", "Member not found")
+}
+
+/// List groups of members which are probably the same child.
+///
+/// Entries are grouped by normalized name, birth date and parent mail, like duplicates are
+/// detected at the signup.
+pub async fn list_duplicate_members(
+	extract::State(state): ExtractState,
+) -> WebResult<Json<Vec<Vec<FullTeilnehmer>>>> {
+	match async {
+		use db::schema::teilnehmer;
+
+		let members = teilnehmer::table
+			.filter(
+				teilnehmer::lager
+					.eq(crate::season::current().id)
+					.and(teilnehmer::geloescht.is_null()),
+			)
+			.order(teilnehmer::anmeldedatum)
+			.load::<FullTeilnehmer>(&mut state.db.get().await?)
 			.await?;
-		if r == 0 {
-			bail!("Member not found");
+		let mut groups = HashMap::<_, Vec<_>>::new();
+		for m in members {
+			groups.entry(m.duplicate_key()).or_default().push(m);
 		}
-		Ok(())
+		let mut duplicates = groups.into_values().filter(|g| g.len() > 1).collect::<Vec<_>>();
+		duplicates.sort_by_key(|g| g[0].anmeldedatum);
+		DbResult::Ok(duplicates)
 	}
 	.await
 	{
-		Err(error) => err(error, "Failed to remove member"),
-		Ok(()) => Ok("Success"),
+		Err(error) => err(error, "Failed to list duplicate members"),
+		Ok(r) => Ok(Json(r)),
 	}
 }
 
+/// Merge a duplicate member into another entry and remove the duplicate.
+pub(crate) async fn merge_members(
+	extract::State(state): ExtractState, Extension(audit): Extension<Audit>,
+	Json(data): Json<MergeMemberData>,
+) -> Response {
+	let result = async {
+		use db::schema::{teilnehmer, zustimmung};
+
+		if data.keep == data.remove {
+			bail!("Cannot merge member with itself");
+		}
+
+		let audit = &audit;
+		state
+			.db
+			.get()
+			.await?
+			.transaction::<_, Error, _>(|con| {
+				async move {
+					let Some(mut keep) = active_member(data.keep)
+						.get_result::<FullTeilnehmer>(con)
+						.await
+						.optional()?
+					else {
+						return Ok(None);
+					};
+					let Some(remove) = active_member(data.remove)
+						.get_result::<FullTeilnehmer>(con)
+						.await
+						.optional()?
+					else {
+						return Ok(None);
+					};
+
+					let old_keep = audit::value(&keep);
+					let old_remove = audit::value(&remove);
+					keep.merge(remove);
+					diesel::update(&keep)
+						.set((&keep, teilnehmer::version.eq(teilnehmer::version + 1)))
+						.execute(con)
+						.await?;
+					diesel::update(
+						zustimmung::table.filter(zustimmung::teilnehmer.eq(data.remove)),
+					)
+					.set(zustimmung::teilnehmer.eq(data.keep))
+					.execute(con)
+					.await?;
+					diesel::delete(teilnehmer::table.filter(teilnehmer::id.eq(data.remove)))
+						.execute(con)
+						.await?;
+					audit
+						.log(
+							con,
+							"teilnehmer",
+							Some(data.keep.to_string()),
+							"zusammengefuehrt",
+							old_keep,
+							audit::value(&keep),
+						)
+						.await?;
+					audit
+						.log(
+							con,
+							"teilnehmer",
+							Some(data.remove.to_string()),
+							"zusammengefuehrt",
+							old_remove,
+							Value::Null,
+						)
+						.await?;
+					Ok(Some(()))
+				}
+				.scope_boxed()
+			})
+			.await
+	}
+	.await;
+	changed(result, "Failed to merge members", "Member not found")
+}
+
 /// Write mail to confirm payment
 async fn payed_mail(
 	mail: &mail::Mail, member: db::models::FullTeilnehmer,
@@ -133,23 +373,61 @@ async fn payed_mail(
 	(StatusCode::INTERNAL_SERVER_ERROR, EditMemberResult { error: Some(error) })
 }
 
-pub(crate) async fn edit_member(
-	extract::State(state): ExtractState, Json(data): Json<FullTeilnehmer>,
-) -> Response {
+/// Save an edited member, `data.version` has to match the stored version.
+async fn save_member(state: &State, audit: &Audit, data: FullTeilnehmer) -> Response {
 	match async {
 		use db::schema::teilnehmer;
 		use db::schema::teilnehmer::columns::*;
 
-		let mut connection = state.db.get().await?;
-
-		let member = teilnehmer::table
-			.filter(id.eq(data.id))
-			.get_result::<db::models::FullTeilnehmer>(&mut connection)
+		let data = &data;
+		let saved = state
+			.db
+			.get()
+			.await?
+			.transaction::<_, Error, _>(|con| {
+				async move {
+					let Some(member) = teilnehmer::table
+						.filter(id.eq(data.id).and(geloescht.is_null()))
+						.get_result::<FullTeilnehmer>(con)
+						.await
+						.optional()?
+					else {
+						return Ok(Saved::NotFound);
+					};
+					if member.version != data.version {
+						return Ok(Saved::Conflict(member));
+					}
+					let updated = diesel::update(teilnehmer::table.filter(
+						id.eq(data.id).and(version.eq(data.version)).and(geloescht.is_null()),
+					))
+					.set((data, version.eq(version + 1)))
+					.execute(con)
+					.await?;
+					if updated == 0 {
+						// Changed after it was loaded
+						let current = teilnehmer::table
+							.filter(id.eq(data.id).and(geloescht.is_null()))
+							.get_result::<FullTeilnehmer>(con)
+							.await
+							.optional()?;
+						return Ok(current.map(Saved::Conflict).unwrap_or(Saved::NotFound));
+					}
+					audit
+						.log(
+							con,
+							"teilnehmer",
+							Some(data.id.to_string()),
+							"bearbeitet",
+							audit::value(&member),
+							audit::value(data),
+						)
+						.await?;
+					Ok(Saved::Ok(member))
+				}
+				.scope_boxed()
+			})
 			.await?;
-		let new_payed = data.bezahlt && !member.bezahlt;
-
-		diesel::update(&data).set(&data).execute(&mut connection).await?;
-		DbResult::Ok((new_payed, member))
+		DbResult::Ok(saved)
 	}
 	.await
 	{
@@ -163,9 +441,15 @@ pub(crate) async fn edit_member(
 			)
 				.into_response()
 		}
-		Ok((new_payed, member)) => {
-			if new_payed {
-				let (status, result) = payed_mail(&state.mail, member).await;
+		Ok(Saved::Conflict(current)) => (StatusCode::CONFLICT, Json(current)).into_response(),
+		Ok(Saved::NotFound) => (
+			StatusCode::NOT_FOUND,
+			Json(EditMemberResult { error: Some("Teilnehmer nicht gefunden".into()) }),
+		)
+			.into_response(),
+		Ok(Saved::Ok(member)) => {
+			if data.bezahlt && !member.bezahlt {
+				let (status, result) = payed_mail(&state.mail, data).await;
 				(status, Json(result)).into_response()
 			} else {
 				Json(EditMemberResult { error: None }).into_response()
@@ -174,56 +458,221 @@ pub(crate) async fn edit_member(
 	}
 }
 
+pub(crate) async fn edit_member(
+	extract::State(state): ExtractState, Extension(audit): Extension<Audit>,
+	Json(data): Json<FullTeilnehmer>,
+) -> Response {
+	save_member(&state, &audit, data).await
+}
+
+/// Change single fields of a member.
+pub(crate) async fn patch_member(
+	extract::State(state): ExtractState, Extension(audit): Extension<Audit>,
+	extract::Path(member_id): extract::Path<i32>, Json(fields): Json<Map<String, Value>>,
+) -> Response {
+	let member = match async {
+		use db::schema::teilnehmer;
+
+		DbResult::Ok(
+			teilnehmer::table
+				.filter(teilnehmer::id.eq(member_id).and(teilnehmer::geloescht.is_null()))
+				.get_result::<FullTeilnehmer>(&mut state.db.get().await?)
+				.await
+				.optional()?,
+		)
+	}
+	.await
+	{
+		Err(error) => {
+			error!(%error, "Failed to load member");
+			return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to load member").into_response();
+		}
+		Ok(None) => return (StatusCode::NOT_FOUND, "Member not found").into_response(),
+		Ok(Some(member)) => member,
+	};
+	match patch(&member, fields) {
+		Err(error) => (StatusCode::BAD_REQUEST, error.to_string()).into_response(),
+		Ok(data) => save_member(&state, &audit, data).await,
+	}
+}
 // TODO Use delete("/betreuer/{id}") here and for teilnehmer
 pub(crate) async fn remove_supervisor(
-	extract::State(state): ExtractState, Json(data): Json<RemoveSupervisorData>,
-) -> WebResult<&'static str> {
+	extract::State(state): ExtractState, Extension(audit): Extension<Audit>,
+	Json(data): Json<RemoveSupervisorData>,
+) -> Response {
+	let result = async {
+		use db::schema::betreuer;
+		use db::schema::betreuer::columns::*;
+
+		let audit = &audit;
+		state
+			.db
+			.get()
+			.await?
+			.transaction::<_, Error, _>(|con| {
+				async move {
+					let Some(supervisor) = active_supervisor(data.supervisor)
+						.select(id)
+						.get_result::<i32>(con)
+						.await
+						.optional()?
+					else {
+						return Ok(None);
+					};
+					diesel::update(betreuer::table.filter(id.eq(supervisor)))
+						.set(geloescht.eq(diesel::dsl::now))
+						.execute(con)
+						.await?;
+					audit
+						.log(
+							con,
+							"betreuer",
+							Some(supervisor.to_string()),
+							"papierkorb",
+							json!({ "geloescht": false }),
+							json!({ "geloescht": true }),
+						)
+						.await?;
+					Ok(Some(()))
+				}
+				.scope_boxed()
+			})
+			.await
+	}
+	.await;
+	changed(result, "Failed to remove supervisor", "Supervisor not found")
+}
+
+/// Save an edited supervisor, `data.version` has to match the stored version.
+async fn save_supervisor(state: &State, audit: &Audit, data: FullSupervisor) -> Response {
 	match async {
 		use db::schema::betreuer;
 		use db::schema::betreuer::columns::*;
 
-		let r = diesel::delete(betreuer::table.filter(id.eq(data.supervisor)))
-			.execute(&mut state.db.get().await?)
-			.await?;
-		if r == 0 {
-			bail!("Supervisor not found");
-		}
-		Ok(())
+		let data = &data;
+		state
+			.db
+			.get()
+			.await?
+			.transaction::<_, Error, _>(|con| {
+				async move {
+					let Some(supervisor) = betreuer::table
+						.filter(id.eq(data.id).and(geloescht.is_null()))
+						.get_result::<FullSupervisor>(con)
+						.await
+						.optional()?
+					else {
+						return Ok(Saved::NotFound);
+					};
+					if supervisor.version != data.version {
+						return Ok(Saved::Conflict(supervisor));
+					}
+					let updated = diesel::update(betreuer::table.filter(
+						id.eq(data.id).and(version.eq(data.version)).and(geloescht.is_null()),
+					))
+					.set((data, version.eq(version + 1)))
+					.execute(con)
+					.await?;
+					if updated == 0 {
+						// Changed after it was loaded
+						let current = betreuer::table
+							.filter(id.eq(data.id).and(geloescht.is_null()))
+							.get_result::<FullSupervisor>(con)
+							.await
+							.optional()?;
+						return Ok(current.map(Saved::Conflict).unwrap_or(Saved::NotFound));
+					}
+					audit
+						.log(
+							con,
+							"betreuer",
+							Some(data.id.to_string()),
+							"bearbeitet",
+							audit::value(&supervisor),
+							audit::value(data),
+						)
+						.await?;
+					Ok(Saved::Ok(supervisor))
+				}
+				.scope_boxed()
+			})
+			.await
 	}
 	.await
 	{
-		Err(error) => err(error, "Failed to remove supervisor"),
-		Ok(()) => Ok("Success"),
+		Err(error) => {
+			error!(%error, "Failed to edit supervisor");
+			(StatusCode::INTERNAL_SERVER_ERROR, "Failed to edit supervisor").into_response()
+		}
+		Ok(Saved::Conflict(current)) => (StatusCode::CONFLICT, Json(current)).into_response(),
+		Ok(Saved::NotFound) => (StatusCode::NOT_FOUND, "Supervisor not found").into_response(),
+		Ok(Saved::Ok(_)) => "Success".into_response(),
 	}
 }
 
 pub(crate) async fn edit_supervisor(
-	extract::State(state): ExtractState, Json(data): Json<FullSupervisor>,
-) -> WebResult<&'static str> {
-	match async {
-		diesel::update(&data).set(&data).execute(&mut state.db.get().await?).await?;
-		DbResult::Ok(())
+	extract::State(state): ExtractState, Extension(audit): Extension<Audit>,
+	Json(data): Json<FullSupervisor>,
+) -> Response {
+	save_supervisor(&state, &audit, data).await
+}
+
+/// Change single fields of a supervisor.
+pub(crate) async fn patch_supervisor(
+	extract::State(state): ExtractState, Extension(audit): Extension<Audit>,
+	extract::Path(supervisor_id): extract::Path<i32>, Json(fields): Json<Map<String, Value>>,
+) -> Response {
+	let supervisor = match async {
+		use db::schema::betreuer;
+
+		DbResult::Ok(
+			betreuer::table
+				.filter(betreuer::id.eq(supervisor_id).and(betreuer::geloescht.is_null()))
+				.get_result::<FullSupervisor>(&mut state.db.get().await?)
+				.await
+				.optional()?,
+		)
 	}
 	.await
 	{
-		Err(error) => err(error, "Failed to edit supervisor"),
-		Ok(()) => Ok("Success"),
+		Err(error) => {
+			error!(%error, "Failed to load supervisor");
+			return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to load supervisor")
+				.into_response();
+		}
+		Ok(None) => return (StatusCode::NOT_FOUND, "Supervisor not found").into_response(),
+		Ok(Some(supervisor)) => supervisor,
+	};
+	match patch(&supervisor, fields) {
+		Err(error) => (StatusCode::BAD_REQUEST, error.to_string()).into_response(),
+		Ok(data) => save_supervisor(&state, &audit, data).await,
 	}
 }
-
 /// Return all current members as json.
 pub async fn download_members(
-	extract::State(state): ExtractState,
-) -> WebResult<Json<Vec<FullTeilnehmer>>> {
-	let mut connection = match state.db.get().await {
-		Ok(c) => c,
-		Err(error) => {
-			warn!(%error, "Error getting database connection");
-			return crate::error_response(&state);
-		}
-	};
+	extract::State(state): ExtractState, Query(query): Query<MembersQuery>,
+) -> WebResult<Json<Vec<ListedMember>>> {
+	match async {
+		use db::schema::teilnehmer;
 
-	match db::schema::teilnehmer::table.load::<FullTeilnehmer>(&mut connection).await {
+		let lager = query.lager.unwrap_or_else(|| crate::season::current().id);
+		let members = teilnehmer::table
+			.filter(teilnehmer::lager.eq(lager).and(teilnehmer::geloescht.is_null()))
+			.load::<FullTeilnehmer>(&mut state.db.get().await?)
+			.await?;
+		let unreachable = bounce::unreachable(&state.db).await?;
+		DbResult::Ok(
+			members
+				.into_iter()
+				.map(|member| ListedMember {
+					mail_unzustellbar: unreachable.get(&member.eltern_mail.to_lowercase()).cloned(),
+					member,
+				})
+				.collect(),
+		)
+	}
+	.await
+	{
 		Err(error) => {
 			warn!(%error, "Error fetching from database");
 			crate::error_response(&state)
@@ -235,16 +684,27 @@ pub async fn download_members(
 /// Return all supervisors as json.
 pub async fn download_supervisors(
 	extract::State(state): ExtractState,
-) -> WebResult<Json<Vec<FullSupervisor>>> {
-	let mut connection = match state.db.get().await {
-		Ok(c) => c,
-		Err(error) => {
-			warn!(%error, "Error getting database connection");
-			return crate::error_response(&state);
-		}
-	};
+) -> WebResult<Json<Vec<ListedSupervisor>>> {
+	match async {
+		use db::schema::betreuer;
 
-	match db::schema::betreuer::table.load::<FullSupervisor>(&mut connection).await {
+		let supervisors = betreuer::table
+			.filter(betreuer::geloescht.is_null())
+			.load::<FullSupervisor>(&mut state.db.get().await?)
+			.await?;
+		let unreachable = bounce::unreachable(&state.db).await?;
+		DbResult::Ok(
+			supervisors
+				.into_iter()
+				.map(|supervisor| ListedSupervisor {
+					mail_unzustellbar: unreachable.get(&supervisor.mail.to_lowercase()).cloned(),
+					supervisor,
+				})
+				.collect(),
+		)
+	}
+	.await
+	{
 		Err(error) => {
 			warn!(%error, "Error fetching from database");
 			crate::error_response(&state)
@@ -253,6 +713,73 @@ pub async fn download_supervisors(
 	}
 }
 
+/// Return all cancellations of the current camp and open refunds of earlier camps as json.
+pub async fn download_cancellations(
+	extract::State(state): ExtractState,
+) -> WebResult<Json<Vec<Cancellation>>> {
+	match async {
+		use crate::db::schema::stornierung;
+
+		DbResult::Ok(
+			stornierung::table
+				.filter(stornierung::geloescht.is_null())
+				.filter(
+					stornierung::lager
+						.eq(crate::season::current().id)
+						.or(stornierung::erstattet.eq(false)),
+				)
+				.order(stornierung::stornodatum)
+				.load::<Cancellation>(&mut state.db.get().await?)
+				.await?,
+		)
+	}
+	.await
+	{
+		Err(error) => err(error, "Failed to get cancellations"),
+		Ok(r) => Ok(Json(r)),
+	}
+}
+
+/// Mark the refund of a cancellation as transferred.
+pub(crate) async fn set_cancellation_refunded(
+	extract::State(state): ExtractState, Extension(audit): Extension<Audit>,
+	Json(data): Json<CancellationRefundedData>,
+) -> WebResult<&'static str> {
+	match async {
+		use db::schema::stornierung;
+
+		let mut connection = state.db.get().await?;
+		let Some(old) = stornierung::table
+			.filter(stornierung::id.eq(data.id).and(stornierung::geloescht.is_null()))
+			.select(stornierung::erstattet)
+			.first::<bool>(&mut connection)
+			.await
+			.optional()?
+		else {
+			bail!("Cancellation not found");
+		};
+		diesel::update(stornierung::table.filter(stornierung::id.eq(data.id)))
+			.set(stornierung::erstattet.eq(data.erstattet))
+			.execute(&mut connection)
+			.await?;
+		audit
+			.log(
+				&mut connection,
+				"stornierung",
+				Some(data.id.to_string()),
+				"bearbeitet",
+				json!({ "erstattet": old }),
+				json!({ "erstattet": data.erstattet }),
+			)
+			.await
+	}
+	.await
+	{
+		Err(error) => err(error, "Failed to edit cancellation"),
+		Ok(()) => Ok("Success"),
+	}
+}
+
 pub async fn download_mails(
 	extract::State(state): ExtractState,
 ) -> Result<Json<Vec<String>>, Response> {
@@ -260,6 +787,11 @@ pub async fn download_mails(
 		use crate::db::schema::teilnehmer;
 
 		let mut mails = teilnehmer::table
+			.filter(
+				teilnehmer::lager
+					.eq(crate::season::current().id)
+					.and(teilnehmer::geloescht.is_null()),
+			)
 			.select(teilnehmer::eltern_mail)
 			.load::<String>(&mut state.db.get().await?)
 			.await?;
@@ -278,19 +810,6 @@ pub async fn download_mails(
 	}
 }
 
-/// The date at which betreuer need to be signed up to count towards the current or last year.
-pub fn betreuer_signup_date_last_year() -> OffsetDateTime {
-	let mut date = crate::LAGER_START.midnight().assume_utc();
-	let now = OffsetDateTime::now_utc();
-	if date > now {
-		// Start from next time, subtract a year to allow signups from last time.
-		date -= time::Duration::days(365);
-	}
-
-	// Add two weeks to get the end of the lager, subtract one year to get the date of last year.
-	date - time::Duration::days(365) + time::Duration::weeks(2)
-}
-
 /// Get overview info of the current lager.
 pub async fn lager_info(extract::State(state): ExtractState) -> WebResult<Json<LagerInfo>> {
 	match async {
@@ -298,20 +817,32 @@ pub async fn lager_info(extract::State(state): ExtractState) -> WebResult<Json<L
 		use diesel::dsl;
 
 		let mut connection = state.db.get().await?;
+		let lager = crate::season::current();
 
-		let teilnehmer_count = teilnehmer::table.count().get_result(&mut connection).await?;
+		let teilnehmer_count = teilnehmer::table
+			.filter(teilnehmer::lager.eq(lager.id).and(teilnehmer::geloescht.is_null()))
+			.count()
+			.get_result(&mut connection)
+			.await?;
+		let active = crate::season::active_supervisors(&mut connection, &lager).await?;
 		let old_betreuer_count = betreuer::table
-			.filter(
-				betreuer::anmeldedatum
-					.lt(betreuer_signup_date_last_year())
-					.or(dsl::not(betreuer::selbsterklaerung)),
-			)
+			.filter(betreuer::geloescht.is_null())
+			.filter(dsl::not(betreuer::id.eq_any(active)).or(dsl::not(betreuer::selbsterklaerung)))
+			.count()
+			.get_result(&mut connection)
+			.await?;
+		let erwischt_game_count = erwischt_game::table
+			.filter(erwischt_game::lager.eq(lager.id))
 			.count()
 			.get_result(&mut connection)
 			.await?;
-		let erwischt_game_count = erwischt_game::table.count().get_result(&mut connection).await?;
 
-		DbResult::Ok(LagerInfo { teilnehmer_count, old_betreuer_count, erwischt_game_count })
+		DbResult::Ok(LagerInfo {
+			lager: lager.name,
+			teilnehmer_count,
+			old_betreuer_count,
+			erwischt_game_count,
+		})
 	}
 	.await
 	{
@@ -323,37 +854,109 @@ pub async fn lager_info(extract::State(state): ExtractState) -> WebResult<Json<L
 	}
 }
 
-/// Remove all data for the current lager.
-pub async fn remove_lager(extract::State(state): ExtractState) -> WebResult<&'static str> {
-	// Remove log file
-	if let Some(log_file) = &state.config.log_file {
-		if let Err(error) = std::fs::remove_file(log_file) {
-			if error.kind() != std::io::ErrorKind::NotFound {
-				error!(file = %log_file.display(), %error, "Failed to remove log file");
-			}
-		}
-	}
-
+/// Remove the personal data of the current lager.
+///
+/// The lager itself, the participations of supervisors and anonymized statistics are kept as
+/// archive. Cancellations with an open refund are kept until the refund is transferred.
+pub async fn remove_lager(
+	extract::State(state): ExtractState, Extension(audit): Extension<Audit>,
+) -> WebResult<&'static str> {
 	match async {
-		use crate::db::schema::{betreuer, erwischt_game, erwischt_member, teilnehmer};
+		use crate::db::schema::{
+			anmeldung_entwurf, betreuer, erwischt_game, erwischt_member, postausgang, rundmail,
+			stornierung, teilnehmer,
+		};
 		use diesel::dsl;
 
-		let mut connection = state.db.get().await?;
-
-		diesel::delete(teilnehmer::table).execute(&mut connection).await?;
-		diesel::delete(erwischt_member::table).execute(&mut connection).await?;
-		diesel::delete(erwischt_game::table).execute(&mut connection).await?;
-		diesel::delete(
-			betreuer::table.filter(
-				betreuer::anmeldedatum
-					.lt(betreuer_signup_date_last_year())
-					.or(dsl::not(betreuer::selbsterklaerung)),
-			),
-		)
-		.execute(&mut connection)
-		.await?;
-
-		DbResult::Ok(())
+		let (lager, audit) = (&crate::season::current(), &audit);
+		// Everything or nothing, a failure must not leave a half cleaned season
+		state
+			.db
+			.get()
+			.await?
+			.transaction::<_, Error, _>(|con| {
+				async move {
+					// Anonymized numbers stay for the next years
+					crate::statistics::snapshot(con, lager).await?;
+					// Keep data of families that agreed to it, delete the rest
+					crate::returning::keep_members(con, lager.id).await?;
+					// Members, supervisors and cancellations go into the trash first, they get
+					// purged later
+					let teilnehmer = diesel::update(teilnehmer::table.filter(
+						teilnehmer::lager.eq(lager.id).and(teilnehmer::geloescht.is_null()),
+					))
+					.set(teilnehmer::geloescht.eq(dsl::now))
+					.execute(con)
+					.await?;
+					let stornierungen = diesel::update(
+						stornierung::table.filter(
+							stornierung::lager
+								.eq(lager.id)
+								.and(stornierung::erstattet)
+								.and(stornierung::geloescht.is_null()),
+						),
+					)
+					.set(stornierung::geloescht.eq(dsl::now))
+					.execute(con)
+					.await?;
+					let entwuerfe = diesel::delete(anmeldung_entwurf::table).execute(con).await?;
+					let rundmails = diesel::delete(rundmail::table).execute(con).await?;
+					let mails = diesel::delete(
+						postausgang::table.filter(postausgang::gesendet.is_not_null()),
+					)
+					.execute(con)
+					.await?;
+					let games = erwischt_game::table.filter(erwischt_game::lager.eq(lager.id));
+					let players = diesel::delete(
+						erwischt_member::table
+							.filter(erwischt_member::game.eq_any(games.select(erwischt_game::id))),
+					)
+					.returning((erwischt_member::game, erwischt_member::id))
+					.get_results::<(i32, i32)>(con)
+					.await?;
+					// Same id as in crate::erwischt
+					let players = players
+						.into_iter()
+						.map(|(game, id)| format!("{game}/{id}"))
+						.collect::<Vec<_>>();
+					audit.redact(con, "erwischt_member", &players).await?;
+					let erwischt_spiele = diesel::delete(games).execute(con).await?;
+					let active = crate::season::active_supervisors(con, lager).await?;
+					let betreuer = diesel::update(
+						betreuer::table.filter(betreuer::geloescht.is_null()).filter(
+							dsl::not(betreuer::id.eq_any(active))
+								.or(dsl::not(betreuer::selbsterklaerung)),
+						),
+					)
+					.set(betreuer::geloescht.eq(dsl::now))
+					.execute(con)
+					.await?;
+					let unzustellbar = bounce::remove_unused(con).await?;
+
+					// Only the number of deleted entries
+					audit
+						.log(
+							con,
+							"lager",
+							Some(lager.id.to_string()),
+							"geloescht",
+							json!({
+								"teilnehmer": teilnehmer,
+								"stornierungen": stornierungen,
+								"entwuerfe": entwuerfe,
+								"rundmails": rundmails,
+								"mails": mails,
+								"erwischt_spiele": erwischt_spiele,
+								"betreuer": betreuer,
+								"unzustellbar": unzustellbar,
+							}),
+							Value::Null,
+						)
+						.await
+				}
+				.scope_boxed()
+			})
+			.await
 	}
 	.await
 	{
@@ -361,7 +964,17 @@ pub async fn remove_lager(extract::State(state): ExtractState) -> WebResult<&'st
 			warn!(%error, "Error deleting lager");
 			crate::error_response(&state)
 		}
-		Ok(()) => Ok("Success"),
+		Ok(()) => {
+			// Remove log file
+			if let Some(log_file) = &state.config.log_file {
+				if let Err(error) = std::fs::remove_file(log_file) {
+					if error.kind() != std::io::ErrorKind::NotFound {
+						error!(file = %log_file.display(), %error, "Failed to remove log file");
+					}
+				}
+			}
+			Ok("Success")
+		}
 	}
 }
 
@@ -604,3 +1217,44 @@ pub async fn list_image_links(
 		Ok(r) => Ok(Json(r)),
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[derive(Debug, Deserialize, PartialEq, Serialize)]
+	struct Entry {
+		id: i32,
+		bezahlt: bool,
+		allergien: String,
+		version: i32,
+	}
+
+	#[test]
+	fn patch_fields() {
+		let entry = Entry { id: 1, bezahlt: false, allergien: "Nüsse".into(), version: 3 };
+		let fields = |v: Value| v.as_object().unwrap().clone();
+		assert_eq!(
+			patch(&entry, fields(json!({ "bezahlt": true, "version": 2 }))).unwrap(),
+			Entry { id: 1, bezahlt: true, allergien: "Nüsse".into(), version: 2 }
+		);
+		assert!(patch(&entry, fields(json!({ "bezahlt": true }))).is_err());
+		assert!(patch(&entry, fields(json!({ "id": 2, "version": 3 }))).is_err());
+		assert!(patch(&entry, fields(json!({ "unbekannt": 1, "version": 3 }))).is_err());
+		assert!(patch(&entry, fields(json!({ "bezahlt": "ja", "version": 3 }))).is_err());
+	}
+
+	#[test]
+	fn trashed_not_found() {
+		let sql = diesel::debug_query::<diesel::pg::Pg, _>(&active_member(1)).to_string();
+		assert!(sql.contains(r#""teilnehmer"."geloescht" IS NULL"#));
+		let sql = diesel::debug_query::<diesel::pg::Pg, _>(&active_supervisor(1)).to_string();
+		assert!(sql.contains(r#""betreuer"."geloescht" IS NULL"#));
+
+		let response = changed(Ok(None), "Failed to remove member", "Member not found");
+		assert_eq!(response.status(), StatusCode::NOT_FOUND);
+		assert_eq!(changed(Ok(Some(())), "", "").status(), StatusCode::OK);
+		let response = changed(Err(anyhow::anyhow!("db")), "Failed to remove member", "");
+		assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+	}
+}
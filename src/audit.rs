@@ -0,0 +1,317 @@
+//! Append-only log of all changes in the admin area.
+//!
+//! The [`record`] middleware gives every mutating admin and Erwischt request an [`Audit`] handle
+//! with the acting user. Handlers log their changes with the old and new values, the entry only
+//! stores the changed fields. Requests that change something without logging it themselves, e.g.
+//! saving a mail template, get an entry with the path of the request. A trigger in the database
+//! rejects changing or deleting entries.
+//!
+//! The only exception is [`Audit::redact`]: when the data of a participant, supervisor or Erwischt
+//! player is finally deleted, the values in its entries are blacked out. The names of the changed
+//! fields and the values of [`VALUE_FIELDS`] stay, and the redaction is logged itself.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use axum::extract::{OriginalUri, Query, Request};
+use axum::http::Method;
+use axum::middleware::Next;
+use axum::response::Response;
+use axum::{Json, extract};
+use diesel::prelude::*;
+use diesel_async::{AsyncPgConnection, RunQueryDsl};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value, json};
+use time::PrimitiveDateTime;
+use tower_sessions::Session;
+use tracing::{error, warn};
+
+use crate::db::models::primitive_datetime;
+use crate::{ExtractState, OidcClaims, WebResult, auth, db};
+
+type DbResult<T> = anyhow::Result<T>;
+
+/// Return at most this many entries when searching.
+const MAX_ENTRIES: i64 = 1000;
+
+/// Fields that contain no personal data, their values are kept by [`Audit::redact`].
+const VALUE_FIELDS: &[&str] = &[
+	"id",
+	"version",
+	"lager",
+	"bezahlt",
+	"anwesend",
+	"geloescht",
+	"anmeldedatum",
+	"agb",
+	"selbsterklaerung",
+	"catcher",
+	"target",
+];
+
+/// Handle to log changes of the current request.
+#[derive(Clone, Debug)]
+pub struct Audit {
+	/// Local user (`users.id`)
+	benutzer: Option<i32>,
+	oidc_subjekt: Option<String>,
+	/// Set when the handler logged something.
+	logged: Arc<AtomicBool>,
+}
+
+#[derive(Clone, Debug, Queryable, Serialize)]
+pub struct Entry {
+	id: i32,
+	#[serde(with = "primitive_datetime")]
+	zeit: PrimitiveDateTime,
+	benutzer: Option<i32>,
+	oidc_subjekt: Option<String>,
+	objekt: String,
+	objekt_id: Option<String>,
+	aktion: String,
+	aenderung: String,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct SearchData {
+	objekt: Option<String>,
+	objekt_id: Option<String>,
+}
+
+/// Convert an entity for [`Audit::log`].
+pub fn value(v: &impl Serialize) -> Value { serde_json::to_value(v).unwrap_or_default() }
+
+/// The changed fields, `{ "field": { "alt": …, "neu": … } }`.
+///
+/// `null` stands for an entity that did not exist before or does not exist anymore, then all
+/// fields are listed.
+pub fn diff(alt: &Value, neu: &Value) -> Value {
+	fn fields<'a>(v: &'a Value, empty: &'a Map<String, Value>) -> Option<&'a Map<String, Value>> {
+		match v {
+			Value::Object(o) => Some(o),
+			Value::Null => Some(empty),
+			_ => None,
+		}
+	}
+
+	let empty = Map::new();
+	let (Some(alt_fields), Some(neu_fields)) = (fields(alt, &empty), fields(neu, &empty)) else {
+		// No entities, compare the whole value
+		if alt == neu {
+			return json!({});
+		}
+		return json!({ "wert": { "alt": alt, "neu": neu } });
+	};
+
+	let mut res = Map::new();
+	for name in alt_fields.keys().chain(neu_fields.keys().filter(|k| !alt_fields.contains_key(*k)))
+	{
+		let a = alt_fields.get(name).unwrap_or(&Value::Null);
+		let n = neu_fields.get(name).unwrap_or(&Value::Null);
+		if a != n {
+			res.insert(name.clone(), json!({ "alt": a, "neu": n }));
+		}
+	}
+	Value::Object(res)
+}
+
+/// Replace the values of personal fields in a [`diff`] by `{}`.
+fn redact_fields(aenderung: Value) -> Value {
+	match aenderung {
+		Value::Object(fields) => {
+			fields
+				.into_iter()
+				.map(|(name, v)| {
+					if VALUE_FIELDS.contains(&name.as_str()) {
+						(name, v)
+					} else {
+						(name, json!({}))
+					}
+				})
+				.collect()
+		}
+		_ => json!({}),
+	}
+}
+
+impl Audit {
+	/// Changes without a user, e.g. from background tasks.
+	pub fn system() -> Self {
+		Self { benutzer: None, oidc_subjekt: None, logged: Default::default() }
+	}
+
+	/// Log a change of an entity, use `Value::Null` for `alt` or `neu` if it was created or
+	/// deleted.
+	pub async fn log(
+		&self, connection: &mut AsyncPgConnection, objekt: &str, objekt_id: Option<String>,
+		aktion: &str, alt: Value, neu: Value,
+	) -> DbResult<()> {
+		use db::schema::protokoll;
+
+		diesel::insert_into(protokoll::table)
+			.values((
+				protokoll::benutzer.eq(self.benutzer),
+				protokoll::oidc_subjekt.eq(&self.oidc_subjekt),
+				protokoll::objekt.eq(objekt),
+				protokoll::objekt_id.eq(objekt_id),
+				protokoll::aktion.eq(aktion),
+				protokoll::aenderung.eq(diff(&alt, &neu).to_string()),
+			))
+			.execute(connection)
+			.await?;
+		self.logged.store(true, Ordering::Relaxed);
+		Ok(())
+	}
+
+	/// Black out the personal values in the entries of entities that are finally deleted.
+	///
+	/// Has to run in the transaction that deletes the entities, the database only allows changing
+	/// entries while `zeltlager.protokoll_schwaerzen` is set for the transaction.
+	pub async fn redact(
+		&self, connection: &mut AsyncPgConnection, objekt: &str, ids: &[String],
+	) -> DbResult<()> {
+		use db::schema::protokoll;
+
+		if ids.is_empty() {
+			return Ok(());
+		}
+		let entries = protokoll::table
+			.filter(protokoll::objekt.eq(objekt).and(protokoll::objekt_id.eq_any(ids)))
+			.select((protokoll::id, protokoll::aenderung))
+			.load::<(i32, String)>(connection)
+			.await?;
+
+		diesel::sql_query("SELECT set_config('zeltlager.protokoll_schwaerzen', 'on', true)")
+			.execute(connection)
+			.await?;
+		let mut count = 0;
+		for (id, aenderung) in entries {
+			let redacted = redact_fields(serde_json::from_str(&aenderung)?).to_string();
+			if redacted != aenderung {
+				diesel::update(protokoll::table.find(id))
+					.set(protokoll::aenderung.eq(redacted))
+					.execute(connection)
+					.await?;
+				count += 1;
+			}
+		}
+		diesel::sql_query("SELECT set_config('zeltlager.protokoll_schwaerzen', 'off', true)")
+			.execute(connection)
+			.await?;
+
+		self.log(
+			connection,
+			"protokoll",
+			Some(objekt.to_string()),
+			"geschwaerzt",
+			Value::Null,
+			json!({ "objekte": ids.len(), "eintraege": count }),
+		)
+		.await
+	}
+}
+
+/// Provide an [`Audit`] handle to mutating requests and log requests that did not log anything.
+pub async fn record(extract::State(state): ExtractState, mut req: Request, next: Next) -> Response {
+	if [Method::GET, Method::HEAD, Method::OPTIONS].contains(req.method()) {
+		return next.run(req).await;
+	}
+
+	let benutzer = match req.extensions().get::<Session>() {
+		Some(session) => auth::logged_in_user(session).await,
+		None => None,
+	};
+	let oidc_subjekt =
+		req.extensions().get::<OidcClaims>().map(|c| c.0.subject().as_str().to_string());
+	let audit = Audit { benutzer, oidc_subjekt, logged: Default::default() };
+	req.extensions_mut().insert(audit.clone());
+	let path = match req.extensions().get::<OriginalUri>() {
+		Some(uri) => uri.path().to_string(),
+		None => req.uri().path().to_string(),
+	};
+	let method = req.method().to_string();
+
+	let response = next.run(req).await;
+	if response.status().is_success() && !AtomicBool::load(&audit.logged, Ordering::Relaxed) {
+		if let Err(error) = async {
+			let mut connection = state.db.get().await?;
+			audit
+				.log(&mut connection, "anfrage", Some(path), &method, Value::Null, Value::Null)
+				.await
+		}
+		.await
+		{
+			error!(%error, "Failed to write audit log");
+		}
+	}
+	response
+}
+
+/// Search the log, optionally for one entity, newest entries first.
+pub async fn search(
+	extract::State(state): ExtractState, Query(data): Query<SearchData>,
+) -> WebResult<Json<Vec<Entry>>> {
+	use db::schema::protokoll;
+
+	match async {
+		let mut query = protokoll::table.into_boxed();
+		if let Some(objekt) = data.objekt.filter(|o| !o.is_empty()) {
+			query = query.filter(protokoll::objekt.eq(objekt));
+		}
+		if let Some(objekt_id) = data.objekt_id.filter(|i| !i.is_empty()) {
+			query = query.filter(protokoll::objekt_id.eq(objekt_id));
+		}
+		DbResult::Ok(
+			query
+				.order(protokoll::id.desc())
+				.limit(MAX_ENTRIES)
+				.load::<Entry>(&mut state.db.get().await?)
+				.await?,
+		)
+	}
+	.await
+	{
+		Err(error) => {
+			warn!(%error, "Failed to search audit log");
+			crate::error_response(&state)
+		}
+		Ok(r) => Ok(Json(r)),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn diff_fields() {
+		let alt = json!({ "id": 1, "bezahlt": false, "allergien": "" });
+		let neu = json!({ "id": 1, "bezahlt": true, "allergien": "" });
+		assert_eq!(diff(&alt, &neu), json!({ "bezahlt": { "alt": false, "neu": true } }));
+		assert_eq!(
+			diff(&json!({ "id": 1 }), &Value::Null),
+			json!({ "id": { "alt": 1, "neu": null } })
+		);
+		assert_eq!(diff(&json!(3), &json!(3)), json!({}));
+		assert_eq!(diff(&json!(3), &json!(4)), json!({ "wert": { "alt": 3, "neu": 4 } }));
+	}
+
+	#[test]
+	fn redact_personal_values() {
+		let alt = json!({ "id": 1, "bezahlt": false, "vorname": "Max", "allergien": "" });
+		let neu = json!({ "id": 1, "bezahlt": true, "vorname": "Moritz", "allergien": "" });
+		assert_eq!(
+			redact_fields(diff(&alt, &neu)),
+			json!({ "bezahlt": { "alt": false, "neu": true }, "vorname": {} })
+		);
+		assert_eq!(
+			redact_fields(diff(&alt, &Value::Null)),
+			json!({
+				"id": { "alt": 1, "neu": null },
+				"bezahlt": { "alt": false, "neu": null },
+				"vorname": {},
+				"allergien": {},
+			})
+		);
+	}
+}
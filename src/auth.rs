@@ -74,9 +74,17 @@ fn get_ip(headers: &HeaderMap, addr: SocketAddr) -> Result<IpAddr> {
 	.ok_or_else(|| format_err!("no ip detected"))
 }
 
-async fn rate_limit(state: &State, headers: &HeaderMap, addr: SocketAddr) -> Result<()> {
+/// Counter of [`rate_limit`] for logins.
+const LOGIN: &str = "login";
+/// Counter of [`rate_limit`] for mails that are sent without a login.
+pub(crate) const MAIL: &str = "mail";
+
+/// Count a request of the client, fails if it sent too many requests of this `kind`.
+pub(crate) async fn rate_limit(
+	state: &State, headers: &HeaderMap, addr: SocketAddr, kind: &str,
+) -> Result<()> {
 	let ip = get_ip(headers, addr)?.to_string();
-	match state.db.check_rate(&ip).await {
+	match state.db.check_rate(&ip, kind).await {
 		Ok(true) => Ok(()),
 		Ok(false) => bail!("Rate limit exceeded"),
 		Err(msg) => bail!(msg),
@@ -94,7 +102,7 @@ async fn login_internal(
 	session: &Session,
 ) -> (StatusCode, LoginResult) {
 	// Check rate limit
-	if let Err(error) = rate_limit(state, headers, addr).await {
+	if let Err(error) = rate_limit(state, headers, addr, LOGIN).await {
 		info!(%error, "Rate limit exceeded");
 		return (StatusCode::FORBIDDEN, LoginResult {
 			error: Some("Zu viele Login Anfragen. Probieren Sie es später noch einmal.".into()),
@@ -148,7 +156,7 @@ async fn login_internal(
 					});
 				}
 			};
-			if let Err(error) = state.db.decrease_rate_counter(&ip).await {
+			if let Err(error) = state.db.decrease_rate_counter(&ip, LOGIN).await {
 				error!(%error, "Failed to decrease rate limiting counter");
 			}
 			(StatusCode::OK, LoginResult { error: None })
@@ -167,7 +175,7 @@ pub async fn token_login(
 	state: &State, headers: &HeaderMap, addr: SocketAddr, token: &str, session: &Session,
 ) -> Result<()> {
 	// Check rate limit
-	if let Err(error) = rate_limit(state, headers, addr).await {
+	if let Err(error) = rate_limit(state, headers, addr, LOGIN).await {
 		bail!("Rate limit exceeded ({error})");
 	}
 
@@ -185,7 +193,7 @@ pub async fn token_login(
 		Ok(r) => r.to_string(),
 		Err(error) => bail!("Failed to get ip ({error})"),
 	};
-	if let Err(error) = state.db.decrease_rate_counter(&ip).await {
+	if let Err(error) = state.db.decrease_rate_counter(&ip, LOGIN).await {
 		error!(%error, "Failed to decrease rate limiting counter");
 	}
 	Ok(())
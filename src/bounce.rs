@@ -0,0 +1,376 @@
+//! Process bounces from the sender mailbox.
+//!
+//! Delivery status notifications (RFC 3464) in the configured maildir are matched to the sent mail
+//! by the Message-ID that is recorded in the outbox. Failed addresses are stored in the
+//! `unzustellbar` table and members and supervisors with such an address are flagged in the
+//! admin lists. Notifications for mails that are not in the outbox and notifications without a
+//! failed recipient do not flag anything. Processed notifications are marked as seen, other mails
+//! are not touched.
+//!
+//! Flags of addresses that no entry uses anymore are deleted with the personal data of a camp and
+//! when the trash is purged.
+
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::Result;
+use axum::{Json, extract};
+use diesel::prelude::*;
+use diesel::upsert::excluded;
+use diesel_async::{AsyncPgConnection, RunQueryDsl};
+use lettre::message::Mailboxes;
+use mail_parser::{MessageParser, MimeHeaders};
+use serde::Deserialize;
+use time::{OffsetDateTime, PrimitiveDateTime};
+use tracing::{error, info, warn};
+
+use crate::{ExtractState, State, WebResult, db};
+
+type DbResult<T> = anyhow::Result<T>;
+
+/// Check the maildir this often.
+const POLL_INTERVAL: std::time::Duration = std::time::Duration::from_secs(10 * 60);
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Failure {
+	mail: String,
+	grund: String,
+}
+
+/// The content of a delivery status notification.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+struct Report {
+	/// Message-ID of the original mail, without angle brackets
+	message_id: Option<String>,
+	/// Recipients where delivery failed
+	failed: Vec<Failure>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct RemoveData {
+	mail: String,
+}
+
+fn now() -> PrimitiveDateTime {
+	let now = OffsetDateTime::now_utc();
+	PrimitiveDateTime::new(now.date(), now.time())
+}
+
+/// Parse the header fields of one block in a delivery status, folded lines are joined.
+fn fields(block: &str) -> Vec<(String, String)> {
+	let mut res: Vec<(String, String)> = Vec::new();
+	for line in block.lines() {
+		if line.starts_with([' ', '\t']) {
+			if let Some((_, v)) = res.last_mut() {
+				v.push(' ');
+				v.push_str(line.trim());
+			}
+		} else if let Some((n, v)) = line.split_once(':') {
+			res.push((n.trim().to_string(), v.trim().to_string()));
+		}
+	}
+	res
+}
+
+/// Parse the recipients of a `message/delivery-status` part.
+fn parse_status(status: &str, report: &mut Report) {
+	let status = status.replace("\r\n", "\n");
+	for block in status.split("\n\n") {
+		let fields = fields(block);
+		let get = |name: &str| {
+			fields.iter().find(|(n, _)| n.eq_ignore_ascii_case(name)).map(|(_, v)| v.as_str())
+		};
+		// Values are prefixed with the type, e.g. `rfc822; a@example.com` or `smtp; 550 …`
+		let value = |v: &str| v.split_once(';').map(|(_, v)| v).unwrap_or(v).trim().to_string();
+
+		let Some(recipient) = get("Final-Recipient").or_else(|| get("Original-Recipient")) else {
+			continue;
+		};
+		if !get("Action").is_some_and(|a| a.eq_ignore_ascii_case("failed")) {
+			continue;
+		}
+		let grund = [get("Status").map(value), get("Diagnostic-Code").map(value)]
+			.iter()
+			.flatten()
+			.map(String::as_str)
+			.collect::<Vec<_>>()
+			.join(" ");
+		let mail = value(recipient).trim_matches(['<', '>']).to_lowercase();
+		report.failed.push(Failure { mail, grund });
+	}
+}
+
+/// Parse a delivery status notification, returns `None` for other mails.
+fn parse(raw: &[u8]) -> Option<Report> {
+	let message = MessageParser::default().parse(raw)?;
+	let mut report = Report::default();
+	let mut is_report = false;
+	for part in &message.parts {
+		if part.is_content_type("message", "delivery-status")
+			|| part.is_content_type("message", "global-delivery-status")
+		{
+			is_report = true;
+			parse_status(&String::from_utf8_lossy(part.contents()), &mut report);
+		} else if part.is_content_type("message", "rfc822")
+			|| part.is_content_type("message", "global")
+		{
+			if let Some(id) = part.message().and_then(|m| m.message_id()) {
+				report.message_id = Some(id.to_string());
+			}
+		} else if part.is_content_type("text", "rfc822-headers")
+			|| part.is_content_type("message", "global-headers")
+		{
+			// The last header may miss the line break
+			let headers = [part.contents(), b"\r\n\r\n"].concat();
+			if let Some(id) = MessageParser::default()
+				.parse_headers(&headers)
+				.and_then(|m| m.message_id().map(String::from))
+			{
+				report.message_id = Some(id);
+			}
+		}
+	}
+	is_report.then_some(report)
+}
+
+/// The addresses to flag for the failures of a sent mail to `to`.
+///
+/// Only recipients of the mail are flagged. If none of the failed addresses is a recipient, the
+/// address was rewritten on the way, e.g. by forwarding, and the recipients in `to` are flagged.
+fn flagged(failed: &[Failure], to: Vec<String>) -> Vec<Failure> {
+	let Some(first) = failed.first() else { return Vec::new() };
+	let res = failed.iter().filter(|f| to.contains(&f.mail)).cloned().collect::<Vec<_>>();
+	if !res.is_empty() {
+		return res;
+	}
+	to.into_iter().map(|mail| Failure { mail, grund: first.grund.clone() }).collect()
+}
+
+/// Flag the failed addresses of a report.
+async fn process(db: &db::Database, report: &Report) -> DbResult<()> {
+	use db::schema::{postausgang, unzustellbar};
+
+	if report.failed.is_empty() {
+		// E.g. a delayed or relayed notification
+		info!(message_id = ?report.message_id, "Delivery status without failed recipients");
+		return Ok(());
+	}
+
+	let mut connection = db.get().await?;
+	let sent = match &report.message_id {
+		Some(id) => postausgang::table
+			.filter(postausgang::message_id.eq(format!("<{id}>")))
+			.select((postausgang::id, postausgang::an))
+			.first::<(i32, String)>(&mut connection)
+			.await
+			.optional()?,
+		None => None,
+	};
+	let Some((id, an)) = sent else {
+		// Could be backscatter for a mail we did not send
+		info!(message_id = ?report.message_id, failed = ?report.failed, "Bounce for unknown mail");
+		return Ok(());
+	};
+	let to = an
+		.parse::<Mailboxes>()
+		.map(|m| m.into_iter().map(|m| m.email.to_string().to_lowercase()).collect())
+		.unwrap_or_else(|_| Vec::new());
+	let failed = flagged(&report.failed, to);
+	info!(id, ?failed, "Mail bounced");
+
+	for f in failed {
+		diesel::insert_into(unzustellbar::table)
+			.values((
+				unzustellbar::mail.eq(&f.mail),
+				unzustellbar::grund.eq(&f.grund),
+				unzustellbar::zeit.eq(now()),
+			))
+			.on_conflict(unzustellbar::mail)
+			.do_update()
+			.set((
+				unzustellbar::grund.eq(excluded(unzustellbar::grund)),
+				unzustellbar::zeit.eq(now()),
+			))
+			.execute(&mut connection)
+			.await?;
+	}
+	Ok(())
+}
+
+/// Process new mails in the maildir, `skipped` remembers mails that are no bounces.
+async fn scan(db: &db::Database, maildir: &Path, skipped: &mut HashSet<String>) -> Result<()> {
+	let cur = maildir.join("cur");
+	let mut entries = match tokio::fs::read_dir(maildir.join("new")).await {
+		Ok(e) => e,
+		Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+		Err(error) => return Err(error.into()),
+	};
+	while let Some(entry) = entries.next_entry().await? {
+		let Ok(name) = entry.file_name().into_string() else { continue };
+		if name.starts_with('.') || skipped.contains(&name) {
+			continue;
+		}
+		let Some(report) = parse(&tokio::fs::read(entry.path()).await?) else {
+			skipped.insert(name);
+			continue;
+		};
+		process(db, &report).await?;
+		// Move to cur and mark as seen
+		tokio::fs::create_dir_all(&cur).await?;
+		tokio::fs::rename(entry.path(), cur.join(format!("{name}:2,S"))).await?;
+	}
+	Ok(())
+}
+
+/// Periodically process bounces, if a maildir is configured.
+pub async fn run(state: Arc<State>) {
+	let Some(maildir) = state.config.bounce_maildir.clone() else {
+		return;
+	};
+	info!(maildir = %maildir.display(), "Starting bounce processing");
+	let mut skipped = HashSet::new();
+	loop {
+		if let Err(error) = scan(&state.db, &maildir, &mut skipped).await {
+			error!(%error, "Failed to process bounces");
+		}
+		tokio::time::sleep(POLL_INTERVAL).await;
+	}
+}
+
+/// All unreachable addresses with the reason.
+pub async fn unreachable(db: &db::Database) -> DbResult<HashMap<String, String>> {
+	use db::schema::unzustellbar;
+
+	Ok(unzustellbar::table
+		.select((unzustellbar::mail, unzustellbar::grund))
+		.load::<(String, String)>(&mut db.get().await?)
+		.await?
+		.into_iter()
+		.collect())
+}
+
+/// Delete the flags of addresses that no signup, member, supervisor, cancellation or returning
+/// family uses anymore.
+pub async fn remove_unused(connection: &mut AsyncPgConnection) -> DbResult<usize> {
+	use db::schema::{
+		anmeldung_entwurf, betreuer, rueckkehrer, stornierung, teilnehmer, unzustellbar,
+	};
+	use db::{lower, trim};
+
+	Ok(diesel::delete(
+		unzustellbar::table
+			.filter(unzustellbar::mail.ne_all(
+				anmeldung_entwurf::table.select(lower(trim(anmeldung_entwurf::eltern_mail))),
+			))
+			.filter(
+				unzustellbar::mail
+					.ne_all(teilnehmer::table.select(lower(trim(teilnehmer::eltern_mail)))),
+			)
+			.filter(unzustellbar::mail.ne_all(betreuer::table.select(lower(trim(betreuer::mail)))))
+			.filter(
+				unzustellbar::mail
+					.ne_all(stornierung::table.select(lower(trim(stornierung::eltern_mail)))),
+			)
+			.filter(
+				unzustellbar::mail
+					.ne_all(rueckkehrer::table.select(lower(trim(rueckkehrer::eltern_mail)))),
+			),
+	)
+	.execute(connection)
+	.await?)
+}
+
+/// Remove the flag from an address, e.g. after the problem was fixed.
+pub async fn remove(
+	extract::State(state): ExtractState, Json(data): Json<RemoveData>,
+) -> WebResult<&'static str> {
+	use db::schema::unzustellbar;
+
+	match async {
+		diesel::delete(unzustellbar::table.find(data.mail.to_lowercase()))
+			.execute(&mut state.db.get().await?)
+			.await?;
+		DbResult::Ok(())
+	}
+	.await
+	{
+		Err(error) => {
+			warn!(%error, "Failed to remove unreachable flag");
+			crate::error_response(&state)
+		}
+		Ok(()) => Ok("Success"),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn parse_dsn() {
+		let dsn = "From: MAILER-DAEMON@mail.example.com\r
+To: zeltlager@example.com\r
+Subject: Undelivered Mail Returned to Sender\r
+MIME-Version: 1.0\r
+Content-Type: multipart/report; report-type=delivery-status; boundary=\"b1\"\r
+\r
+--b1\r
+Content-Type: text/plain; charset=us-ascii\r
+\r
+The mail could not be delivered.\r
+--b1\r
+Content-Type: message/delivery-status\r
+\r
+Reporting-MTA: dns; mail.example.com\r
+\r
+Final-Recipient: rfc822; Erika@Example.org\r
+Original-Recipient: rfc822;erika@example.org\r
+Action: failed\r
+Status: 5.1.1\r
+Diagnostic-Code: smtp; 550 5.1.1 <erika@example.org>: Recipient address\r
+ rejected: User unknown\r
+\r
+Final-Recipient: rfc822; max@example.org\r
+Action: delayed\r
+Status: 4.4.1\r
+--b1\r
+Content-Type: text/rfc822-headers\r
+\r
+From: Zeltlager <zeltlager@example.com>\r
+To: \"Erika Mustermann\" <erika@example.org>\r
+Subject: Anmeldung\r
+Message-ID: <abc123@example.com>\r
+--b1--\r
+";
+		let report = parse(dsn.as_bytes()).unwrap();
+		assert_eq!(report, Report {
+			message_id: Some("abc123@example.com".into()),
+			failed: vec![Failure {
+				mail: "erika@example.org".into(),
+				grund: "5.1.1 550 5.1.1 <erika@example.org>: Recipient address rejected: User \
+				        unknown"
+					.into(),
+			}],
+		});
+
+		let mail = "From: a@example.org\r\nSubject: Hallo\r\n\r\nHallo\r\n";
+		assert_eq!(parse(mail.as_bytes()), None);
+	}
+
+	#[test]
+	fn flag_recipients() {
+		let failure = |mail: &str| Failure { mail: mail.into(), grund: "5.1.1".into() };
+		let to = || vec!["erika@example.org".to_string(), "max@example.org".to_string()];
+
+		assert_eq!(flagged(&[], to()), vec![]);
+		assert_eq!(flagged(&[failure("erika@example.org"), failure("x@example.org")], to()), vec![
+			failure("erika@example.org")
+		]);
+		// Rewritten address
+		assert_eq!(flagged(&[failure("erika@forward.example.org")], to()), vec![
+			failure("erika@example.org"),
+			failure("max@example.org")
+		]);
+	}
+}
@@ -0,0 +1,651 @@
+//! Mails to all parents or supervisors of a group.
+//!
+//! The text of a campaign contains placeholders like `{vorname}`, which are replaced for every
+//! recipient. Every mail is sent on its own and logged in `rundmail_empfaenger`.
+//!
+//! The recipients are chosen with an audience that is saved in `zielgruppe`, either the
+//! supervisors of this year or the parents of members that match a filter.
+
+use std::collections::HashMap;
+
+use anyhow::{Result, bail};
+use axum::extract::Path;
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::{Extension, Json, extract};
+use diesel::prelude::*;
+use diesel_async::{AsyncPgConnection, RunQueryDsl};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use time::{Date, PrimitiveDateTime};
+use tracing::{error, info, warn};
+
+use crate::audit::{self, Audit};
+use crate::db::models::{opt_primitive_datetime, primitive_datetime, years_old};
+use crate::{ExtractState, State, WebResult, db};
+
+type DbResult<T> = anyhow::Result<T>;
+
+/// Who gets the mails of a campaign.
+#[derive(Clone, Debug, Queryable, Serialize)]
+pub struct Audience {
+	id: i32,
+	name: String,
+	/// Supervisors that signed up for this year instead of parents
+	betreuer: bool,
+	/// Filters for members, `None` matches all
+	bezahlt: Option<bool>,
+	eigenanreise: Option<bool>,
+	/// Age at the start of the camp
+	alter_von: Option<i32>,
+	alter_bis: Option<i32>,
+}
+
+#[derive(AsChangeset, Clone, Debug, Deserialize, Insertable)]
+#[diesel(treat_none_as_null = true, table_name = db::schema::zielgruppe)]
+pub struct NewAudience {
+	name: String,
+	betreuer: bool,
+	bezahlt: Option<bool>,
+	eigenanreise: Option<bool>,
+	alter_von: Option<i32>,
+	alter_bis: Option<i32>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct SaveAudienceData {
+	/// Create a new audience if not set.
+	id: Option<i32>,
+	#[serde(flatten)]
+	zielgruppe: NewAudience,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct AudienceInfo {
+	#[serde(flatten)]
+	zielgruppe: Audience,
+	platzhalter: &'static [&'static str],
+	anzahl: usize,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct CampaignData {
+	/// Id of the audience
+	zielgruppe: i32,
+	betreff: String,
+	text: String,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Preview {
+	empfaenger: usize,
+	/// Mail of the first recipient
+	beispiel: Option<PreviewMail>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct PreviewMail {
+	name: String,
+	mail: String,
+	betreff: String,
+	text: String,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Campaign {
+	id: i32,
+	betreff: String,
+	text: String,
+	zielgruppe: String,
+	#[serde(with = "primitive_datetime")]
+	erstellt: PrimitiveDateTime,
+	empfaenger: Vec<CampaignRecipient>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct CampaignRecipient {
+	#[serde(skip)]
+	rundmail: i32,
+	name: String,
+	mail: String,
+	/// Time when the mail was sent from the outbox
+	#[serde(with = "opt_primitive_datetime")]
+	gesendet: Option<PrimitiveDateTime>,
+	/// Error when queueing or the last error when sending
+	fehler: Option<String>,
+	/// The outbox gave up sending the mail
+	aufgegeben: bool,
+}
+
+struct Recipient {
+	name: String,
+	mail: String,
+	values: HashMap<&'static str, String>,
+}
+
+const MEMBER_PLACEHOLDERS: &[&str] = &["eltern_name", "vorname", "nachname", "betrag"];
+const SUPERVISOR_PLACEHOLDERS: &[&str] = &["vorname", "nachname"];
+
+impl NewAudience {
+	fn check(&self) -> Result<()> {
+		if self.name.trim().is_empty() {
+			bail!("Der Name darf nicht leer sein");
+		}
+		if self.betreuer
+			&& (self.bezahlt.is_some()
+				|| self.eigenanreise.is_some()
+				|| self.alter_von.is_some()
+				|| self.alter_bis.is_some())
+		{
+			bail!("Betreuer können nicht nach Teilnehmern gefiltert werden");
+		}
+		Ok(())
+	}
+}
+
+impl Audience {
+	async fn load(connection: &mut AsyncPgConnection, id: i32) -> Result<Option<Self>> {
+		use db::schema::zielgruppe;
+
+		Ok(zielgruppe::table.find(id).get_result::<Audience>(connection).await.optional()?)
+	}
+
+	fn placeholders(&self) -> &'static [&'static str] {
+		if self.betreuer { SUPERVISOR_PLACEHOLDERS } else { MEMBER_PLACEHOLDERS }
+	}
+
+	/// If the parents of a member get the mail.
+	fn includes(&self, bezahlt: bool, eigenanreise: bool, alter: i32) -> bool {
+		self.bezahlt.is_none_or(|b| b == bezahlt)
+			&& self.eigenanreise.is_none_or(|e| e == eigenanreise)
+			&& self.alter_von.is_none_or(|a| a <= alter)
+			&& self.alter_bis.is_none_or(|a| alter <= a)
+	}
+
+	async fn recipients(&self, connection: &mut AsyncPgConnection) -> Result<Vec<Recipient>> {
+		use db::schema::{betreuer, teilnehmer};
+
+		let lager = crate::season::current();
+		if self.betreuer {
+			let active = crate::season::active_supervisors(connection, &lager).await?;
+			let supervisors = betreuer::table
+				.filter(
+					betreuer::id
+						.eq_any(active)
+						.and(betreuer::selbsterklaerung)
+						.and(betreuer::geloescht.is_null()),
+				)
+				.order((betreuer::nachname, betreuer::vorname))
+				.select((betreuer::vorname, betreuer::nachname, betreuer::mail))
+				.load::<(String, String, String)>(connection)
+				.await?;
+			return Ok(supervisors
+				.into_iter()
+				.map(|(vorname, nachname, mail)| Recipient {
+					name: format!("{vorname} {nachname}"),
+					mail,
+					values: HashMap::from([("vorname", vorname), ("nachname", nachname)]),
+				})
+				.collect());
+		}
+
+		let members = teilnehmer::table
+			.filter(teilnehmer::lager.eq(lager.id).and(teilnehmer::geloescht.is_null()))
+			.order((teilnehmer::nachname, teilnehmer::vorname))
+			.select((
+				teilnehmer::vorname,
+				teilnehmer::nachname,
+				teilnehmer::eltern_name,
+				teilnehmer::eltern_mail,
+				teilnehmer::bezahlt,
+				teilnehmer::eigenanreise,
+				teilnehmer::geburtsdatum,
+			))
+			.load::<(String, String, String, String, bool, bool, Date)>(connection)
+			.await?;
+		let fee = lager.preis;
+		Ok(members
+			.into_iter()
+			.filter(|(_, _, _, _, bezahlt, eigenanreise, geburtsdatum)| {
+				self.includes(*bezahlt, *eigenanreise, years_old(*geburtsdatum, &lager.beginn))
+			})
+			.map(|(vorname, nachname, eltern_name, mail, bezahlt, _, _)| Recipient {
+				name: eltern_name.clone(),
+				mail,
+				values: HashMap::from([
+					("eltern_name", eltern_name),
+					("vorname", vorname),
+					("nachname", nachname),
+					("betrag", format!("{} Euro", if bezahlt { 0 } else { fee })),
+				]),
+			})
+			.collect())
+	}
+}
+
+/// Replace all `{placeholder}` in the text.
+///
+/// Fails for placeholders that are not in `values`.
+fn render(template: &str, values: &HashMap<&str, String>) -> Result<String> {
+	let mut res = String::new();
+	let mut rest = template;
+	while let Some(start) = rest.find('{') {
+		res.push_str(&rest[..start]);
+		rest = &rest[start..];
+		let end = rest.find('}').filter(|&end| {
+			rest[1..end].chars().all(|c| c.is_ascii_lowercase() || c == '_') && end > 1
+		});
+		if let Some(end) = end {
+			let name = &rest[1..end];
+			let Some(value) = values.get(name) else {
+				bail!("Unbekannter Platzhalter {{{name}}}");
+			};
+			res.push_str(value);
+			rest = &rest[end + 1..];
+		} else {
+			res.push('{');
+			rest = &rest[1..];
+		}
+	}
+	res.push_str(rest);
+	Ok(res)
+}
+
+/// Check that all placeholders of a campaign can be filled.
+fn check_placeholders(data: &CampaignData, audience: &Audience) -> Result<()> {
+	let values = audience.placeholders().iter().map(|p| (*p, String::new())).collect();
+	render(&data.betreff, &values)?;
+	render(&data.text, &values)?;
+	if data.betreff.trim().is_empty() || data.text.trim().is_empty() {
+		bail!("Betreff und Text dürfen nicht leer sein");
+	}
+	Ok(())
+}
+
+/// If saving failed because an audience with the same name exists.
+fn is_duplicate(error: &anyhow::Error) -> bool {
+	matches!(
+		error.downcast_ref(),
+		Some(diesel::result::Error::DatabaseError(
+			diesel::result::DatabaseErrorKind::UniqueViolation,
+			_
+		))
+	)
+}
+
+/// Load the audience of a campaign and check the placeholders.
+async fn load_checked(state: &State, data: &CampaignData) -> WebResult<Audience> {
+	let audience = match async {
+		let mut connection = state.db.get().await?;
+		Audience::load(&mut connection, data.zielgruppe).await
+	}
+	.await
+	{
+		Err(error) => {
+			warn!(%error, "Failed to load audience");
+			return crate::error_response(state);
+		}
+		Ok(None) => return Err((StatusCode::BAD_REQUEST, "Unbekannte Zielgruppe").into_response()),
+		Ok(Some(audience)) => audience,
+	};
+	if let Err(error) = check_placeholders(data, &audience) {
+		return Err((StatusCode::BAD_REQUEST, error.to_string()).into_response());
+	}
+	Ok(audience)
+}
+
+/// List all audiences with the number of recipients.
+pub async fn list_audiences(
+	extract::State(state): ExtractState,
+) -> WebResult<Json<Vec<AudienceInfo>>> {
+	match async {
+		use db::schema::zielgruppe;
+
+		let mut connection = state.db.get().await?;
+		let audiences =
+			zielgruppe::table.order(zielgruppe::id).load::<Audience>(&mut connection).await?;
+		let mut res = Vec::new();
+		for audience in audiences {
+			res.push(AudienceInfo {
+				platzhalter: audience.placeholders(),
+				anzahl: audience.recipients(&mut connection).await?.len(),
+				zielgruppe: audience,
+			});
+		}
+		DbResult::Ok(res)
+	}
+	.await
+	{
+		Err(error) => {
+			warn!(%error, "Failed to list audiences");
+			crate::error_response(&state)
+		}
+		Ok(r) => Ok(Json(r)),
+	}
+}
+
+/// Create or change an audience.
+pub async fn save_audience(
+	extract::State(state): ExtractState, Extension(audit): Extension<Audit>,
+	Json(data): Json<SaveAudienceData>,
+) -> WebResult<Json<i32>> {
+	if let Err(error) = data.zielgruppe.check() {
+		return Err((StatusCode::BAD_REQUEST, error.to_string()).into_response());
+	}
+
+	match async {
+		use db::schema::zielgruppe;
+
+		let mut connection = state.db.get().await?;
+		let (old, saved) = if let Some(id) = data.id {
+			let Some(old) = Audience::load(&mut connection, id).await? else {
+				return Ok(None);
+			};
+			let saved = diesel::update(zielgruppe::table.find(id))
+				.set(&data.zielgruppe)
+				.get_result::<Audience>(&mut connection)
+				.await?;
+			(audit::value(&old), saved)
+		} else {
+			let saved = diesel::insert_into(zielgruppe::table)
+				.values(&data.zielgruppe)
+				.get_result::<Audience>(&mut connection)
+				.await?;
+			(Value::Null, saved)
+		};
+		audit
+			.log(
+				&mut connection,
+				"zielgruppe",
+				Some(saved.id.to_string()),
+				if data.id.is_some() { "bearbeitet" } else { "erstellt" },
+				old,
+				audit::value(&saved),
+			)
+			.await?;
+		DbResult::Ok(Some(saved.id))
+	}
+	.await
+	{
+		Err(error) if is_duplicate(&error) => {
+			Err((StatusCode::BAD_REQUEST, "Name existiert bereits").into_response())
+		}
+		Err(error) => {
+			warn!(%error, "Failed to save audience");
+			crate::error_response(&state)
+		}
+		Ok(None) => Err((StatusCode::NOT_FOUND, "Unbekannte Zielgruppe").into_response()),
+		Ok(Some(r)) => Ok(Json(r)),
+	}
+}
+
+/// Delete an audience, campaigns keep its name.
+pub async fn delete_audience(
+	extract::State(state): ExtractState, Extension(audit): Extension<Audit>, Path(id): Path<i32>,
+) -> WebResult<&'static str> {
+	match async {
+		use db::schema::zielgruppe;
+
+		let mut connection = state.db.get().await?;
+		let Some(old) = diesel::delete(zielgruppe::table.find(id))
+			.get_result::<Audience>(&mut connection)
+			.await
+			.optional()?
+		else {
+			return DbResult::Ok(None);
+		};
+		audit
+			.log(
+				&mut connection,
+				"zielgruppe",
+				Some(id.to_string()),
+				"geloescht",
+				audit::value(&old),
+				Value::Null,
+			)
+			.await?;
+		Ok(Some(()))
+	}
+	.await
+	{
+		Err(error) => {
+			warn!(%error, "Failed to delete audience");
+			crate::error_response(&state)
+		}
+		Ok(None) => Err((StatusCode::NOT_FOUND, "Unbekannte Zielgruppe").into_response()),
+		Ok(Some(())) => Ok("Success"),
+	}
+}
+
+/// Render the mail for the first recipient.
+pub async fn preview(
+	extract::State(state): ExtractState, Json(data): Json<CampaignData>,
+) -> WebResult<Json<Preview>> {
+	let audience = load_checked(&state, &data).await?;
+
+	match async {
+		let mut connection = state.db.get().await?;
+		let recipients = audience.recipients(&mut connection).await?;
+		let beispiel = match <[_]>::first(&recipients) {
+			Some(r) => Some(PreviewMail {
+				name: r.name.clone(),
+				mail: r.mail.clone(),
+				betreff: render(&data.betreff, &r.values)?,
+				text: render(&data.text, &r.values)?,
+			}),
+			None => None,
+		};
+		DbResult::Ok(Preview { empfaenger: recipients.len(), beispiel })
+	}
+	.await
+	{
+		Err(error) => {
+			warn!(%error, "Failed to preview campaign");
+			crate::error_response(&state)
+		}
+		Ok(r) => Ok(Json(r)),
+	}
+}
+
+/// Create a campaign and queue the mails in the outbox.
+///
+/// Returns the id of the campaign, the delivery log shows the progress.
+pub async fn send(
+	extract::State(state): ExtractState, Json(data): Json<CampaignData>,
+) -> WebResult<Json<i32>> {
+	let audience = load_checked(&state, &data).await?;
+
+	let res = async {
+		use db::schema::{rundmail, rundmail_empfaenger};
+
+		let mut connection = state.db.get().await?;
+		let recipients = audience.recipients(&mut connection).await?;
+		if recipients.is_empty() {
+			bail!("No recipients");
+		}
+		let id = diesel::insert_into(rundmail::table)
+			.values((
+				rundmail::betreff.eq(&data.betreff),
+				rundmail::text.eq(&data.text),
+				rundmail::zielgruppe.eq(&audience.name),
+			))
+			.returning(rundmail::id)
+			.get_result::<i32>(&mut connection)
+			.await?;
+		let ids = diesel::insert_into(rundmail_empfaenger::table)
+			.values(
+				recipients
+					.iter()
+					.map(|r| {
+						(
+							rundmail_empfaenger::rundmail.eq(id),
+							rundmail_empfaenger::name.eq(&r.name),
+							rundmail_empfaenger::mail.eq(&r.mail),
+						)
+					})
+					.collect::<Vec<_>>(),
+			)
+			.returning(rundmail_empfaenger::id)
+			.get_results::<i32>(&mut connection)
+			.await?;
+		DbResult::Ok((id, ids.into_iter().zip(recipients).collect::<Vec<_>>()))
+	}
+	.await;
+
+	match res {
+		Err(error) => {
+			warn!(%error, "Failed to create campaign");
+			crate::error_response(&state)
+		}
+		Ok((id, recipients)) => {
+			queue_all(&state, &data, recipients).await;
+			Ok(Json(id))
+		}
+	}
+}
+
+/// Queue the mails of a campaign in the outbox.
+async fn queue_all(state: &State, data: &CampaignData, recipients: Vec<(i32, Recipient)>) {
+	use db::schema::rundmail_empfaenger;
+
+	let count = recipients.len();
+	let mut failed = 0;
+	for (id, r) in recipients {
+		let res = async {
+			let subject = render(&data.betreff, &r.values)?;
+			let body = render(&data.text, &r.values)?;
+			state.mail.send_campaign(&r.name, &r.mail, &subject, body).await
+		}
+		.await;
+		if let Err(error) = &res {
+			error!(mail = r.mail, %error, "Error queueing campaign e-mail");
+			failed += 1;
+		}
+
+		let update = async {
+			diesel::update(rundmail_empfaenger::table.filter(rundmail_empfaenger::id.eq(id)))
+				.set((
+					rundmail_empfaenger::postausgang.eq(res.as_ref().ok()),
+					rundmail_empfaenger::fehler.eq(res.as_ref().err().map(|e| e.to_string())),
+				))
+				.execute(&mut state.db.get().await?)
+				.await?;
+			DbResult::Ok(())
+		}
+		.await;
+		if let Err(error) = update {
+			error!(%error, "Failed to log campaign e-mail");
+		}
+	}
+	info!(count, failed, "Queued campaign");
+}
+
+/// List all campaigns with their delivery log.
+pub async fn list_campaigns(extract::State(state): ExtractState) -> WebResult<Json<Vec<Campaign>>> {
+	match async {
+		use db::schema::{postausgang, rundmail, rundmail_empfaenger};
+
+		let mut connection = state.db.get().await?;
+		let recipients = rundmail_empfaenger::table
+			.left_join(postausgang::table)
+			.order(rundmail_empfaenger::id)
+			.select((
+				rundmail_empfaenger::rundmail,
+				rundmail_empfaenger::name,
+				rundmail_empfaenger::mail,
+				rundmail_empfaenger::fehler,
+				postausgang::gesendet.nullable(),
+				postausgang::fehler.nullable(),
+				postausgang::aufgegeben.nullable(),
+			))
+			.load::<(
+				i32,
+				String,
+				String,
+				Option<String>,
+				Option<PrimitiveDateTime>,
+				Option<String>,
+				Option<bool>,
+			)>(&mut connection)
+			.await?
+			.into_iter()
+			.map(|(rundmail, name, mail, fehler, gesendet, send_error, aufgegeben)| {
+				CampaignRecipient {
+					rundmail,
+					name,
+					mail,
+					gesendet,
+					fehler: fehler.or(send_error),
+					aufgegeben: aufgegeben.unwrap_or_default(),
+				}
+			})
+			.collect::<Vec<_>>();
+		let campaigns = rundmail::table
+			.order(rundmail::id.desc())
+			.load::<(i32, String, String, String, PrimitiveDateTime)>(&mut connection)
+			.await?;
+		DbResult::Ok(
+			campaigns
+				.into_iter()
+				.map(|(id, betreff, text, zielgruppe, erstellt)| Campaign {
+					id,
+					betreff,
+					text,
+					zielgruppe,
+					erstellt,
+					empfaenger: recipients.iter().filter(|r| r.rundmail == id).cloned().collect(),
+				})
+				.collect(),
+		)
+	}
+	.await
+	{
+		Err(error) => {
+			warn!(%error, "Failed to list campaigns");
+			crate::error_response(&state)
+		}
+		Ok(r) => Ok(Json(r)),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use diesel::result::{DatabaseErrorKind, Error};
+
+	use super::*;
+
+	#[test]
+	fn duplicate_name() {
+		let error =
+			|kind| Error::DatabaseError(kind, Box::new(String::from("zielgruppe_name_key")));
+		assert!(is_duplicate(&error(DatabaseErrorKind::UniqueViolation).into()));
+		assert!(!is_duplicate(&error(DatabaseErrorKind::ForeignKeyViolation).into()));
+		assert!(!is_duplicate(&Error::NotFound.into()));
+		assert!(!is_duplicate(&anyhow::anyhow!("Der Name darf nicht leer sein")));
+	}
+
+	#[test]
+	fn render_placeholders() {
+		let values = HashMap::from([("vorname", "Max".to_string())]);
+		assert_eq!(render("Hallo {vorname}!", &values).unwrap(), "Hallo Max!");
+		assert_eq!(render("{ {} {Vorname} {", &values).unwrap(), "{ {} {Vorname} {");
+		assert!(render("{nachname}", &values).is_err());
+	}
+
+	#[test]
+	fn audience_filter() {
+		let audience = Audience {
+			id: 1,
+			name: "Große Kinder ohne Zahlung".into(),
+			betreuer: false,
+			bezahlt: Some(false),
+			eigenanreise: None,
+			alter_von: Some(12),
+			alter_bis: None,
+		};
+		assert!(audience.includes(false, true, 12));
+		assert!(audience.includes(false, false, 14));
+		assert!(!audience.includes(true, false, 14));
+		assert!(!audience.includes(false, false, 11));
+	}
+}
@@ -0,0 +1,298 @@
+//! Cancellation of a participant signup by the parents.
+//!
+//! The signup mail contains a link with a signed token. A cancellation moves the participant
+//! from `teilnehmer` to `stornierung`, which frees the spot and keeps track of the refund.
+//!
+//! There is no waiting list that could be promoted: signups for a full camp are rejected and not
+//! stored. The freed spot is offered on the signup page again right away.
+
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::{Json, extract};
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use diesel::prelude::*;
+use diesel_async::{AsyncConnection, RunQueryDsl, scoped_futures::ScopedFutureExt};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use time::{OffsetDateTime, PrimitiveDateTime};
+use tracing::{error, warn};
+
+use crate::config::CancellationSettings;
+use crate::db::models::{Cancellation, FullTeilnehmer, Lager, NewCancellation};
+use crate::i18n::Sprache;
+use crate::{ExtractState, State, db, season};
+
+type DbResult<T> = anyhow::Result<T>;
+
+#[derive(Clone, Debug, Deserialize)]
+pub(crate) struct CancelRequest {
+	token: String,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub(crate) struct CancelInfo {
+	vorname: String,
+	nachname: String,
+	bezahlt: bool,
+	erstattung_prozent: i32,
+	erstattung: i32,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub(crate) struct CancelResult {
+	error: Option<String>,
+}
+
+fn link_mac(secret: &str, id: i32, anmeldedatum: &PrimitiveDateTime) -> Hmac<Sha256> {
+	let mut mac =
+		Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+	mac.update(format!("stornierung:{id}:{anmeldedatum}").as_bytes());
+	mac
+}
+
+/// Create the token for the cancellation link of a member.
+pub fn create_token(secret: &str, id: i32, anmeldedatum: &PrimitiveDateTime) -> String {
+	let mac = link_mac(secret, id, anmeldedatum).finalize().into_bytes();
+	format!("{id}-{}", URL_SAFE_NO_PAD.encode(mac))
+}
+
+/// Get the member id from a token.
+///
+/// The token still needs to be checked with [`check_token`].
+fn token_id(token: &str) -> Option<i32> { token.split_once('-')?.0.parse().ok() }
+
+fn check_token(secret: &str, token: &str, member: &FullTeilnehmer) -> bool {
+	let Some(mac) = token.split_once('-').and_then(|(_, m)| URL_SAFE_NO_PAD.decode(m).ok()) else {
+		return false;
+	};
+	link_mac(secret, member.id, &member.anmeldedatum).verify_slice(&mac).is_ok()
+}
+
+/// Get the refund in percent and in Euro for a cancellation `days_before` the camp starts.
+pub fn refund(
+	settings: &CancellationSettings, fee: i64, days_before: i64, bezahlt: bool,
+) -> (i32, i32) {
+	let percent = settings
+		.tiers
+		.iter()
+		.filter(|t| days_before >= t.days_before)
+		.max_by_key(|t| t.days_before)
+		.map(|t| t.refund_percent)
+		.unwrap_or_default();
+	let amount = if bezahlt { fee * percent / 100 } else { 0 };
+	(percent as i32, amount as i32)
+}
+
+/// Get the refund for cancelling a member of `lager` today.
+fn lager_refund(settings: &CancellationSettings, lager: &Lager, bezahlt: bool) -> (i32, i32) {
+	let days_before = (lager.beginn - OffsetDateTime::now_utc().date()).whole_days();
+	refund(settings, lager.preis.into(), days_before, bezahlt)
+}
+
+/// Find the member and its camp for a token and check the signature.
+async fn get_member<'a>(
+	state: &'a State, token: &str,
+) -> Result<(&'a CancellationSettings, FullTeilnehmer, Lager), Response> {
+	let err = |status, msg: &str| {
+		(status, Json(CancelResult { error: Some(msg.into()) })).into_response()
+	};
+
+	let (Some(settings), Some(secret)) = (&state.config.cancellation, &state.config.link_secret)
+	else {
+		warn!("Tried to cancel but cancellations are not configured");
+		return Err(err(StatusCode::NOT_FOUND, "Eine Abmeldung ist leider nicht möglich."));
+	};
+	let invalid_link = || {
+		err(
+			StatusCode::BAD_REQUEST,
+			"Der Link ist ungültig oder das Kind wurde bereits abgemeldet.",
+		)
+	};
+	let Some(member_id) = token_id(token) else {
+		warn!(token, "Failed to parse cancellation token");
+		return Err(invalid_link());
+	};
+
+	let (member, lager) = match async {
+		use db::schema::{lager, teilnehmer};
+
+		match teilnehmer::table
+			.left_join(lager::table)
+			.filter(teilnehmer::id.eq(member_id).and(teilnehmer::geloescht.is_null()))
+			.select((teilnehmer::all_columns, lager::all_columns.nullable()))
+			.first::<(FullTeilnehmer, Option<Lager>)>(&mut state.db.get().await?)
+			.await
+		{
+			Err(diesel::result::Error::NotFound) => DbResult::Ok(None),
+			Err(e) => Err(e.into()),
+			Ok(r) => Ok(Some(r)),
+		}
+	}
+	.await
+	{
+		Err(error) => {
+			error!(%error, "Failed to get member for cancellation");
+			return Err(err(
+				StatusCode::INTERNAL_SERVER_ERROR,
+				&format!(
+					"Es ist ein Datenbank-Fehler aufgetreten.\n{}",
+					state.config.error_message
+				),
+			));
+		}
+		Ok(None) => {
+			warn!(member_id, "Failed to find member for cancellation");
+			return Err(invalid_link());
+		}
+		Ok(Some(r)) => r,
+	};
+
+	if !check_token(secret, token, &member) {
+		warn!(member_id, "Invalid cancellation token");
+		return Err(invalid_link());
+	}
+	// Members from before seasons were introduced belong to the current camp
+	Ok((settings, member, lager.unwrap_or_else(season::current)))
+}
+
+/// Show what a cancellation would refund.
+pub async fn cancel_info(
+	extract::State(state): ExtractState, Json(request): Json<CancelRequest>,
+) -> Response {
+	let (settings, member, lager) = match get_member(&state, &request.token).await {
+		Ok(r) => r,
+		Err(response) => return response,
+	};
+
+	let (erstattung_prozent, erstattung) = lager_refund(settings, &lager, member.bezahlt);
+	Json(CancelInfo {
+		vorname: member.vorname,
+		nachname: member.nachname,
+		bezahlt: member.bezahlt,
+		erstattung_prozent,
+		erstattung,
+	})
+	.into_response()
+}
+
+/// Cancel the signup of a member and send a confirmation mail.
+pub async fn cancel(
+	extract::State(state): ExtractState, Json(request): Json<CancelRequest>,
+) -> Response {
+	let (settings, member, lager) = match get_member(&state, &request.token).await {
+		Ok(r) => r,
+		Err(response) => return response,
+	};
+
+	let (erstattung_prozent, erstattung) = lager_refund(settings, &lager, member.bezahlt);
+	let new_cancellation = NewCancellation {
+		vorname: member.vorname.clone(),
+		nachname: member.nachname.clone(),
+		geburtsdatum: member.geburtsdatum,
+		eltern_name: member.eltern_name.clone(),
+		eltern_mail: member.eltern_mail.clone(),
+		anmeldedatum: member.anmeldedatum,
+		bezahlt: member.bezahlt,
+		erstattung_prozent,
+		erstattung,
+		lager: Some(lager.id),
+	};
+
+	let member_id = member.id;
+	let sprache = Sprache::from_code(&member.sprache);
+	let cancellation = match async {
+		use db::schema::{stornierung, teilnehmer};
+
+		let cancellation = state
+			.db
+			.get()
+			.await?
+			.transaction::<_, diesel::result::Error, _>(|con| {
+				async move {
+					let cancellation = diesel::insert_into(stornierung::table)
+						.values(&new_cancellation)
+						.get_result::<Cancellation>(con)
+						.await?;
+					diesel::delete(teilnehmer::table.filter(teilnehmer::id.eq(member_id)))
+						.execute(con)
+						.await?;
+					Ok(cancellation)
+				}
+				.scope_boxed()
+			})
+			.await?;
+		DbResult::Ok(cancellation)
+	}
+	.await
+	{
+		Err(error) => {
+			error!(%error, member_id, "Failed to cancel member");
+			return (
+				StatusCode::INTERNAL_SERVER_ERROR,
+				Json(CancelResult {
+					error: Some(format!(
+						"Es ist ein Datenbank-Fehler aufgetreten.\n{}",
+						state.config.error_message
+					)),
+				}),
+			)
+				.into_response();
+		}
+		Ok(c) => c,
+	};
+
+	if let Err(error) = state.mail.send_member_cancelled(&cancellation, sprache).await {
+		error!(mail = cancellation.eltern_mail, %error, "Error sending e-mail");
+		return (
+			StatusCode::INTERNAL_SERVER_ERROR,
+			Json(CancelResult {
+				error: Some(format!(
+					"Die Abmeldung wurde erfolgreich gespeichert.\nEs ist leider ein Fehler beim \
+					 E-Mail senden aufgetreten.\n{}",
+					state.config.error_message
+				)),
+			}),
+		)
+			.into_response();
+	}
+
+	Json(CancelResult { error: None }).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+	use time::macros::datetime;
+
+	use super::*;
+	use crate::config::CancellationTier;
+
+	#[test]
+	fn refund_tiers() {
+		let settings = CancellationSettings {
+			fee: 250,
+			tiers: vec![
+				CancellationTier { days_before: 14, refund_percent: 50 },
+				CancellationTier { days_before: 42, refund_percent: 100 },
+			],
+		};
+		assert_eq!(refund(&settings, 250, 100, true), (100, 250));
+		assert_eq!(refund(&settings, 250, 42, true), (100, 250));
+		assert_eq!(refund(&settings, 250, 20, true), (50, 125));
+		assert_eq!(refund(&settings, 250, 20, false), (50, 0));
+		assert_eq!(refund(&settings, 250, 13, true), (0, 0));
+		assert_eq!(refund(&settings, 250, -3, true), (0, 0));
+	}
+
+	#[test]
+	fn token_roundtrip() {
+		let anmeldedatum = datetime!(2026-01-02 10:11:12.123456);
+		let token = create_token("secret", 17, &anmeldedatum);
+		assert_eq!(token_id(&token), Some(17));
+		let mac = URL_SAFE_NO_PAD.decode(token.split_once('-').unwrap().1).unwrap();
+		assert!(link_mac("secret", 17, &anmeldedatum).verify_slice(&mac).is_ok());
+		assert!(link_mac("secret", 18, &anmeldedatum).verify_slice(&mac).is_err());
+		assert!(link_mac("other", 17, &anmeldedatum).verify_slice(&mac).is_err());
+	}
+}
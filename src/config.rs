@@ -1,7 +1,7 @@
 use std::path::PathBuf;
 
 use clap::{Parser, Subcommand};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 #[derive(Parser, Debug)]
 #[command(author, version, about)]
@@ -31,9 +31,28 @@ pub enum Action {
 		/// Name of the user to delete
 		username: Option<String>,
 	},
+	/// Register a new version of a document that is accepted at the signup
+	#[command(name = "add-document")]
+	AddDocument {
+		/// agb, datenschutz or selbsterklaerung
+		document: String,
+		/// Name of the version, e.g. the year
+		version: String,
+		/// File with the content of the document, used to compute its hash
+		file: PathBuf,
+		/// Link to the document on the website, e.g. /files/AgbZeltlager2021.pdf
+		#[arg(long)]
+		url: String,
+		/// Date from which the version is valid as YYYY-MM-DD, defaults to today
+		#[arg(long)]
+		valid_from: Option<String>,
+	},
+	/// Print the DNS record for the DKIM key and check a signed test mail
+	#[command(name = "dkim")]
+	Dkim,
 }
 
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Deserialize, Debug, Clone, Serialize)]
 #[serde(deny_unknown_fields)]
 pub struct MailAddress {
 	pub name: Option<String>,
@@ -51,6 +70,53 @@ pub struct MailAccount {
 	pub password: String,
 }
 
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum MailTransport {
+	/// Send mails with `sender_mail_account`.
+	#[default]
+	Smtp,
+	/// Write mails as `.eml` files into a directory.
+	File { directory: PathBuf },
+	/// Keep the last mails in memory.
+	Memory,
+}
+
+/// How `additional_mail_receivers` get informed.
+#[derive(Deserialize, Debug, Clone, Copy, Default)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum AdditionalMailMode {
+	/// Every mail to parents is sent to them in bcc.
+	#[default]
+	Bcc,
+	/// One summary mail per day at this hour (UTC).
+	Digest {
+		#[serde(default = "default_digest_hour")]
+		hour: u8,
+	},
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum DkimAlgorithm {
+	#[default]
+	Rsa,
+	Ed25519,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct DkimSettings {
+	/// Domain of the signature, defaults to the domain of `sender_mail`.
+	pub domain: Option<String>,
+	/// Name of the key, the DNS record is at `<selector>._domainkey.<domain>`.
+	pub selector: String,
+	/// File with the private key, PKCS#1 PEM for rsa or the base64 encoded key for ed25519.
+	pub private_key: PathBuf,
+	#[serde(default)]
+	pub algorithm: DkimAlgorithm,
+}
+
 #[derive(Deserialize, Debug, Clone)]
 #[serde(deny_unknown_fields)]
 pub struct OidcSettings {
@@ -76,12 +142,42 @@ pub struct KanidmSettings {
 	pub token: String,
 }
 
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct CancellationTier {
+	/// The tier applies if the cancellation happens at least this many days
+	/// before the start of the camp.
+	pub days_before: i64,
+	/// Refunded part of the participation fee in percent.
+	pub refund_percent: i64,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct CancellationSettings {
+	/// Participation fee in Euro of the first camp, later camps have their own price.
+	pub fee: i64,
+	/// Refund tiers from the AGB.
+	///
+	/// Cancellations later than the smallest `days_before` get no refund.
+	#[serde(default)]
+	pub tiers: Vec<CancellationTier>,
+}
+
 #[derive(Deserialize, Debug, Clone)]
 #[serde(deny_unknown_fields)]
 pub struct Config {
 	/// The sender of emails
 	pub sender_mail: MailAddress,
-	pub sender_mail_account: MailAccount,
+	/// Needed for the smtp transport.
+	pub sender_mail_account: Option<MailAccount>,
+	/// How mails are delivered.
+	#[serde(default)]
+	pub mail_transport: MailTransport,
+	/// Sign mails with DKIM.
+	pub dkim: Option<DkimSettings>,
+	/// Maildir of the sender mailbox, delivery status notifications in it are processed.
+	pub bounce_maildir: Option<PathBuf>,
 
 	/// Oidc/OAuth settings
 	pub oidc: Option<OidcSettings>,
@@ -96,6 +192,9 @@ pub struct Config {
 	/// mails.
 	#[serde(default)]
 	pub additional_mail_receivers: Vec<MailAddress>,
+	/// Send mails to the additional receivers in bcc or as a daily summary.
+	#[serde(default)]
+	pub additional_mail_mode: AdditionalMailMode,
 	/// If a member signs up with this mail address, the signup mail will only
 	/// be sent to this address, but not to additional receivers. The member
 	/// will also not be entered into the database.
@@ -104,7 +203,9 @@ pub struct Config {
 	/// Postgresql connection url
 	pub database: String,
 
-	/// The maximum allowed amount of members.
+	/// The maximum allowed amount of members of the first camp.
+	///
+	/// Later camps are set up in the admin area.
 	pub max_members: i64,
 	/// An error message, which will be displayed on generic errors.
 	///
@@ -125,16 +226,47 @@ pub struct Config {
 	/// If set, it restricts the authentication cookie to a domain
 	/// and protects against csrf using the referer and origin header.
 	pub domain: Option<String>,
+	/// Address of the website, used for links in mails.
+	#[serde(default = "default_site_url")]
+	pub site_url: String,
 
 	/// Path to a log file to log signups.
 	pub log_file: Option<PathBuf>,
+	/// Number of days after which an unfinished signup is deleted.
+	#[serde(default = "default_draft_expiry_days")]
+	pub draft_expiry_days: i64,
+	/// Number of days after which deleted members and supervisors are purged from the trash.
+	#[serde(default = "default_trash_retention_days")]
+	pub trash_retention_days: i64,
+
+	/// Secret key to sign links that are sent in mails.
+	pub link_secret: Option<String>,
+	/// Allow parents to cancel a signup through a link in the signup mail.
+	///
+	/// Needs `link_secret` to be set.
+	pub cancellation: Option<CancellationSettings>,
 }
 
 impl Config {
+	/// Participation fee in Euro of the first camp.
+	pub fn fee(&self) -> i64 { self.cancellation.as_ref().map(|c| c.fee).unwrap_or(DEFAULT_FEE) }
+
+	/// Absolute link to a page of the website, e.g. `/anmeldung?entwurf=…`.
+	pub fn link(&self, path: &str) -> String {
+		format!("{}{path}", self.site_url.trim_end_matches('/'))
+	}
+
 	pub fn domain(&self) -> Option<&str> {
 		// Split off port if it exists
 		self.domain.as_deref().map(|d| d.split_once(':').map(|r| r.0).unwrap_or(d))
 	}
 }
 
+/// Fee if no cancellation settings are configured.
+pub const DEFAULT_FEE: i64 = 250;
+
 fn default_bind_address() -> String { String::from("127.0.0.1:8080") }
+fn default_site_url() -> String { String::from("https://meinzeltlager.com") }
+fn default_draft_expiry_days() -> i64 { 14 }
+fn default_trash_retention_days() -> i64 { 30 }
+fn default_digest_hour() -> u8 { 6 }
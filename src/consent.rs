@@ -0,0 +1,225 @@
+//! Record which version of the AGB, the privacy policy and the Selbstverpflichtungserklärung
+//! was accepted at a signup.
+//!
+//! Document versions are registered in `dokument_version` with a hash of their content. The
+//! version of a document that is valid today is the one with the latest `gueltig_ab` date that
+//! is not in the future. At a signup, the ids of the currently valid versions are stored in
+//! `zustimmung`.
+
+use std::collections::{HashMap, HashSet};
+
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::{Json, extract};
+use diesel::prelude::*;
+use diesel_async::{AsyncPgConnection, RunQueryDsl};
+use serde::Serialize;
+use time::OffsetDateTime;
+use tracing::error;
+
+use crate::db::models::DocumentVersion;
+use crate::{ExtractState, WebResult, db};
+
+type DbResult<T> = anyhow::Result<T>;
+
+pub const AGB: &str = "agb";
+pub const DATENSCHUTZ: &str = "datenschutz";
+pub const SELBSTERKLAERUNG: &str = "selbsterklaerung";
+
+/// Documents that are accepted with the AGB checkbox.
+pub const AGB_DOCUMENTS: &[&str] = &[AGB, DATENSCHUTZ];
+
+/// A member or supervisor that accepted documents.
+#[derive(Clone, Copy, Debug)]
+pub enum Signer {
+	Member(i32),
+	Supervisor(i32),
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct MissingConsent {
+	/// `teilnehmer` or `betreuer`
+	art: &'static str,
+	id: i32,
+	vorname: String,
+	nachname: String,
+	mail: String,
+	/// Documents where the current version was not accepted
+	dokumente: Vec<String>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct ConsentInfo {
+	/// All registered versions
+	versionen: Vec<DocumentVersion>,
+	fehlend: Vec<MissingConsent>,
+}
+
+/// The documents a supervisor accepted with their signup.
+///
+/// Pre-signups do not include the Selbstverpflichtungserklärung.
+pub fn supervisor_documents(agb: bool, selbsterklaerung: bool) -> Vec<&'static str> {
+	let mut docs = Vec::new();
+	if agb {
+		docs.extend_from_slice(AGB_DOCUMENTS);
+	}
+	if selbsterklaerung {
+		docs.push(SELBSTERKLAERUNG);
+	}
+	docs
+}
+
+/// Get the versions that are valid today, at most one per document.
+pub async fn current_versions(
+	connection: &mut AsyncPgConnection,
+) -> Result<Vec<DocumentVersion>, diesel::result::Error> {
+	use db::schema::dokument_version;
+
+	let today = OffsetDateTime::now_utc().date();
+	let versions = dokument_version::table
+		.filter(dokument_version::gueltig_ab.le(today))
+		.order((
+			dokument_version::dokument,
+			dokument_version::gueltig_ab.desc(),
+			dokument_version::id.desc(),
+		))
+		.load::<DocumentVersion>(connection)
+		.await?;
+	let mut seen = HashSet::new();
+	Ok(versions.into_iter().filter(|v| seen.insert(v.dokument.clone())).collect())
+}
+
+/// Store that the current versions of `documents` were accepted now.
+pub async fn record(
+	connection: &mut AsyncPgConnection, signer: Signer, documents: &[&str],
+) -> Result<(), diesel::result::Error> {
+	use db::schema::zustimmung;
+
+	let (member, supervisor) = match signer {
+		Signer::Member(id) => (Some(id), None),
+		Signer::Supervisor(id) => (None, Some(id)),
+	};
+	let rows = current_versions(connection)
+		.await?
+		.into_iter()
+		.filter(|v| documents.contains(&v.dokument.as_str()))
+		.map(|v| {
+			(
+				zustimmung::teilnehmer.eq(member),
+				zustimmung::betreuer.eq(supervisor),
+				zustimmung::dokument_version.eq(v.id),
+			)
+		})
+		.collect::<Vec<_>>();
+	if !rows.is_empty() {
+		diesel::insert_into(zustimmung::table).values(&rows).execute(connection).await?;
+	}
+	Ok(())
+}
+
+/// List all registered document versions and everyone who did not accept the current version
+/// of a document.
+pub async fn list_missing(extract::State(state): ExtractState) -> WebResult<Json<ConsentInfo>> {
+	match async {
+		use db::schema::{betreuer, dokument_version, teilnehmer, zustimmung};
+
+		let mut connection = state.db.get().await?;
+		let current = current_versions(&mut connection).await?;
+		let versionen = dokument_version::table
+			.order((dokument_version::dokument, dokument_version::gueltig_ab))
+			.load::<DocumentVersion>(&mut connection)
+			.await?;
+
+		let mut accepted = HashMap::<_, HashSet<i32>>::new();
+		for (member, supervisor, version) in zustimmung::table
+			.select((zustimmung::teilnehmer, zustimmung::betreuer, zustimmung::dokument_version))
+			.load::<(Option<i32>, Option<i32>, i32)>(&mut connection)
+			.await?
+		{
+			let key = match (member, supervisor) {
+				(Some(id), _) => ("teilnehmer", id),
+				(_, Some(id)) => ("betreuer", id),
+				_ => continue,
+			};
+			accepted.entry(key).or_default().insert(version);
+		}
+
+		let missing_docs = |key, documents: &[&str]| {
+			let accepted = accepted.get(&key);
+			current
+				.iter()
+				.filter(|v| documents.contains(&v.dokument.as_str()))
+				.filter(|v| !accepted.map(|a| a.contains(&v.id)).unwrap_or_default())
+				.map(|v| v.dokument.clone())
+				.collect::<Vec<_>>()
+		};
+
+		let mut fehlend = Vec::new();
+		for (id, vorname, nachname, mail) in teilnehmer::table
+			.filter(
+				teilnehmer::lager
+					.eq(crate::season::current().id)
+					.and(teilnehmer::geloescht.is_null()),
+			)
+			.order((teilnehmer::nachname, teilnehmer::vorname))
+			.select((
+				teilnehmer::id,
+				teilnehmer::vorname,
+				teilnehmer::nachname,
+				teilnehmer::eltern_mail,
+			))
+			.load::<(i32, String, String, String)>(&mut connection)
+			.await?
+		{
+			let dokumente = missing_docs(("teilnehmer", id), AGB_DOCUMENTS);
+			if !dokumente.is_empty() {
+				fehlend.push(MissingConsent {
+					art: "teilnehmer",
+					id,
+					vorname,
+					nachname,
+					mail,
+					dokumente,
+				});
+			}
+		}
+		for (id, vorname, nachname, mail, selbsterklaerung) in betreuer::table
+			.filter(betreuer::geloescht.is_null())
+			.order((betreuer::nachname, betreuer::vorname))
+			.select((
+				betreuer::id,
+				betreuer::vorname,
+				betreuer::nachname,
+				betreuer::mail,
+				betreuer::selbsterklaerung,
+			))
+			.load::<(i32, String, String, String, bool)>(&mut connection)
+			.await?
+		{
+			// Pre-signed-up supervisors did not accept the Selbstverpflichtungserklärung yet
+			let documents = supervisor_documents(true, selbsterklaerung);
+			let dokumente = missing_docs(("betreuer", id), &documents);
+			if !dokumente.is_empty() {
+				fehlend.push(MissingConsent {
+					art: "betreuer",
+					id,
+					vorname,
+					nachname,
+					mail,
+					dokumente,
+				});
+			}
+		}
+
+		DbResult::Ok(ConsentInfo { versionen, fehlend })
+	}
+	.await
+	{
+		Err(error) => {
+			error!(%error, "Failed to list missing consents");
+			Err((StatusCode::INTERNAL_SERVER_ERROR, "Failed to list missing consents")
+				.into_response())
+		}
+		Ok(r) => Ok(Json(r)),
+	}
+}
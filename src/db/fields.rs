@@ -0,0 +1,359 @@
+//! The fields of the signup forms.
+//!
+//! The parsers in [`super::models`] check a form against these fields, and
+//! [`crate::form_schema`] sends them to the frontend, so both use the same rules. Checks that need
+//! more than one field or have their own messages, like the age, stay in the parsers.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt;
+
+use heck::ToTitleCase;
+use serde::Serialize;
+use time::{Date, Duration, OffsetDateTime};
+
+use super::models::{
+	MEMBER_MAX_AGE, MEMBER_MIN_AGE, SUPERVISOR_MIN_AGE, check_email, opt_date, try_parse_date,
+	try_parse_gender,
+};
+use super::{FormError, FormErrors};
+use crate::season;
+
+/// Values for the `geschlecht` field, the parser also accepts some more spellings.
+const GENDERS: &[&str] = &["m", "w"];
+const BOOLS: &[&str] = &["true", "false"];
+/// Allowed values for the `krankenversicherung` field.
+const KRANKENVERSICHERUNG: &[&str] = &["gesetzlich", "privat", "anderes"];
+/// Number of digits of a postal code, for countries where we check it.
+const PLZ_LENGTHS: &[(&str, usize)] = &[("Deutschland", 5)];
+const AGB: &str = "Ich habe die Allgemeinen Geschäftsbedingungen und die Datenschutzbestimmungen \
+                   gelesen und akzeptiere sie.";
+const SELBSTERKLAERUNG: &str = "Ich habe die Selbstverpflichtungserklärung zur Prävention von \
+                                sexualisierter Gewalt sowie den Anhang gelesen und verpflichte \
+                                mich, mich daran zu halten.";
+/// Fields of the pre-signup form that are not part of [`super::models::Supervisor`].
+pub const PRESIGNUP_EXTRA: &[&str] = &["grund", "kommentar"];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FieldType {
+	Text,
+	Email,
+	/// A date in the format `TT.MM.JJJJ`.
+	Date,
+	/// One of the `values`.
+	Enum,
+	/// Free text, which can contain line breaks.
+	Freetext,
+	/// A checkbox that must be checked, sent as `true`.
+	Checkbox,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Field {
+	pub id: &'static str,
+	/// Name of the field shown to the user.
+	pub label: Cow<'static, str>,
+	#[serde(rename = "type")]
+	pub kind: FieldType,
+	/// If the field must be filled in.
+	///
+	/// Optional fields still need to be sent, but can be empty.
+	pub required: bool,
+	#[serde(skip_serializing_if = "<[_]>::is_empty")]
+	pub values: &'static [&'static str],
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub min_length: Option<usize>,
+	/// Number of characters, depending on the value of the `land` field.
+	#[serde(skip_serializing_if = "<[_]>::is_empty")]
+	pub length_by_country: &'static [(&'static str, usize)],
+	#[serde(skip_serializing_if = "std::ops::Not::not")]
+	pub digits_only: bool,
+	/// Must contain at least one digit.
+	#[serde(skip_serializing_if = "std::ops::Not::not")]
+	pub needs_digit: bool,
+	#[serde(skip_serializing_if = "Option::is_none", with = "opt_date")]
+	pub min_date: Option<Date>,
+	#[serde(skip_serializing_if = "Option::is_none", with = "opt_date")]
+	pub max_date: Option<Date>,
+}
+
+impl Field {
+	fn new(id: &'static str, label: &'static str, kind: FieldType) -> Self {
+		let required = kind != FieldType::Freetext;
+		Self {
+			id,
+			label: label.into(),
+			kind,
+			required,
+			values: &[],
+			min_length: (required && matches!(kind, FieldType::Text | FieldType::Email))
+				.then_some(1),
+			length_by_country: &[],
+			digits_only: false,
+			needs_digit: false,
+			min_date: None,
+			max_date: None,
+		}
+	}
+
+	fn text(id: &'static str, label: &'static str) -> Self { Self::new(id, label, FieldType::Text) }
+
+	fn freetext(id: &'static str, label: &'static str) -> Self {
+		Self::new(id, label, FieldType::Freetext)
+	}
+
+	fn values(id: &'static str, label: &'static str, values: &'static [&'static str]) -> Self {
+		Self { values, ..Self::new(id, label, FieldType::Enum) }
+	}
+
+	fn bool(id: &'static str, label: &'static str) -> Self { Self::values(id, label, BOOLS) }
+
+	fn optional(self) -> Self { Self { required: false, min_length: None, ..self } }
+
+	fn dates(self, min_date: Option<Date>, max_date: Option<Date>) -> Self {
+		Self { min_date, max_date, ..self }
+	}
+
+	fn invalid(&self, value: &str, reason: impl fmt::Display) -> FormError {
+		FormError {
+			field: Some(self.id.into()),
+			message: format!("Ungültige {} ({value}), {reason}", self.label),
+		}
+	}
+
+	/// Check a sent value, `form` contains the other values for checks that depend on them.
+	fn check(&self, value: &str, form: &HashMap<String, String>) -> Result<(), FormError> {
+		if value.is_empty() && !self.required {
+			return Ok(());
+		}
+		let title = self.id.to_title_case();
+		let is_bool = self.kind == FieldType::Checkbox || self.values == BOOLS;
+		match self.kind {
+			FieldType::Text | FieldType::Email | FieldType::Freetext if value.is_empty() => {
+				return Err(FormError {
+					field: Some(self.id.into()),
+					message: format!("{title} muss ausgefüllt werden"),
+				});
+			}
+			FieldType::Text => {
+				if self.digits_only && !value.chars().all(|c| c.is_ascii_digit()) {
+					return Err(self.invalid(value, "darf nur Zahlen enthalten"));
+				}
+				if self.needs_digit && !value.chars().any(|c| c.is_ascii_digit()) {
+					return Err(self.invalid(value, "muss mindestens eine Ziffer enthalten"));
+				}
+				let land = form.get("land").map(String::as_str).unwrap_or_default();
+				let len = self.length_by_country.iter().find(|(l, _)| *l == land);
+				match len {
+					Some((_, len)) if value.len() != *len => {
+						return Err(self.invalid(value, format!("muss {len} Stellen haben")));
+					}
+					_ => {}
+				}
+			}
+			FieldType::Email => check_email(value, self.id)?,
+			FieldType::Date => {
+				try_parse_date(value, self.id)?;
+			}
+			FieldType::Enum | FieldType::Checkbox if is_bool => {
+				if !BOOLS.contains(&value) {
+					return Err(FormError {
+						field: Some(self.id.into()),
+						message: format!("{title} ({value}) ist kein Wahrheitswert"),
+					});
+				}
+			}
+			// The parser accepts more spellings
+			FieldType::Enum if self.values == GENDERS => {
+				try_parse_gender(value)?;
+			}
+			FieldType::Enum => {
+				if !self.values.contains(&value) {
+					let (last, first) = self.values.split_last().unwrap_or((&"", &[]));
+					return Err(self.invalid(
+						value,
+						format!("muss entweder {} oder {last} sein", first.join(", ")),
+					));
+				}
+			}
+			FieldType::Freetext | FieldType::Checkbox => {}
+		}
+		Ok(())
+	}
+}
+
+/// Check that all fields are sent and valid.
+pub fn check(fields: &[Field], form: &HashMap<String, String>, errors: &mut FormErrors) {
+	for field in fields {
+		let res = match form.get(field.id) {
+			Some(value) => field.check(value, form),
+			None => Err(FormError {
+				field: Some(field.id.into()),
+				message: format!("{} fehlt", field.id.to_title_case()),
+			}),
+		};
+		errors.check(res);
+	}
+}
+
+/// The date `years` before `date`, the 29th February becomes the 28th.
+fn years_before(date: Date, years: i32) -> Date {
+	let year = date.year() - years;
+	date.replace_year(year)
+		.unwrap_or_else(|_| Date::from_calendar_date(year, date.month(), 28).unwrap())
+}
+
+/// Birthdays for someone who is between `min_age` and `max_age` years old when the camp starts.
+fn birthday(min_age: i32, max_age: i32) -> Field {
+	let yesterday = OffsetDateTime::now_utc().date() - Duration::days(1);
+	let beginn = season::beginn();
+	let max_date = years_before(beginn, min_age).min(yesterday);
+	let min_date = years_before(beginn, max_age + 1) + Duration::days(1);
+	Field::new("geburtsdatum", "Geburtsdatum", FieldType::Date)
+		.dates(Some(min_date), Some(max_date))
+}
+
+/// Fields for the address, shared by participants and supervisors.
+fn address() -> [Field; 5] {
+	[
+		Field::text("land", "Land"),
+		Field::text("strasse", "Straße"),
+		Field { needs_digit: true, ..Field::text("hausnummer", "Hausnummer") },
+		Field::text("ort", "Ort"),
+		Field {
+			digits_only: true,
+			length_by_country: PLZ_LENGTHS,
+			..Field::text("plz", "Postleitzahl")
+		},
+	]
+}
+
+/// Fields about health and food, shared by participants and supervisors.
+fn health() -> [Field; 6] {
+	[
+		Field::values("krankenversicherung", "Krankenversicherung", KRANKENVERSICHERUNG),
+		Field::freetext("krankheiten", "Eigenheiten/Krankheiten"),
+		Field::freetext("allergien", "Sonstige Allergien"),
+		Field::freetext("unvertraeglichkeiten", "Lebensmittelunverträglichkeiten/-allergien"),
+		Field::freetext("medikamente", "Medikamente"),
+		Field::freetext("kommentar", "Sonstige Kommentare"),
+	]
+}
+
+/// The fields of [`super::models::Teilnehmer::from_hashmap`].
+pub fn member() -> Vec<Field> {
+	let mut fields = vec![
+		Field::text("vorname", "Vorname"),
+		Field::text("nachname", "Nachname"),
+		birthday(MEMBER_MIN_AGE, MEMBER_MAX_AGE),
+		Field::values("geschlecht", "Geschlecht", GENDERS),
+		Field::bool("schwimmer", "Schwimmer"),
+		Field::bool("vegetarier", "Vegetarisch"),
+		Field::bool("tetanus_impfung", "Tetanusimpfung"),
+		Field::text("eltern_name", "Name eines Erziehungsberechtigten"),
+		Field::new("eltern_mail", "E-Mailadresse des Erziehungsberechtigten", FieldType::Email),
+		Field::text("eltern_handynummer", "Handynummer des Erziehungsberechtigten (für Notfälle)"),
+	];
+	fields.extend(address());
+	fields.extend(health());
+	fields.push(Field::bool("eigenanreise", "Eigene Anreise"));
+	fields
+		.push(Field::bool("daten_behalten", "Daten für die Anmeldung im nächsten Jahr speichern"));
+	fields.push(Field::new("agb", AGB, FieldType::Checkbox));
+	fields
+}
+
+/// The fields of [`super::models::Supervisor::from_hashmap`].
+pub fn supervisor() -> Vec<Field> {
+	let mut fields = vec![
+		Field::text("vorname", "Vorname"),
+		Field::text("nachname", "Nachname"),
+		birthday(SUPERVISOR_MIN_AGE, 99),
+		Field::values("geschlecht", "Geschlecht", GENDERS),
+		Field::bool("vegetarier", "Vegetarisch"),
+		Field::bool("tetanus_impfung", "Tetanusimpfung"),
+		Field { digits_only: true, ..Field::text("juleica_nummer", "Juleica Nummer").optional() },
+		Field::new("juleica_gueltig_bis", "Juleica gültig bis", FieldType::Date)
+			.optional()
+			.dates(Some(season::beginn()), None),
+		Field::new("mail", "E-Mailadresse", FieldType::Email),
+		Field::text("handynummer", "Handynummer"),
+	];
+	fields.extend(address());
+	fields.extend(health());
+	fields.push(
+		Field::new(
+			"fuehrungszeugnis_ausstellung",
+			"Ausstellungsdatum Erweitertes Führungszeugnis",
+			FieldType::Date,
+		)
+		.optional(),
+	);
+	fields.push(Field::new("selbsterklaerung", SELBSTERKLAERUNG, FieldType::Checkbox));
+	fields.push(Field::new("agb", AGB, FieldType::Checkbox));
+	fields
+}
+
+/// The fields of the pre-signup form, [`super::models::Supervisor::from_pre_hashmap`] parses
+/// all but [`PRESIGNUP_EXTRA`].
+pub fn presignup() -> Vec<Field> {
+	vec![
+		Field::text("vorname", "Vorname"),
+		Field::text("nachname", "Nachname"),
+		birthday(SUPERVISOR_MIN_AGE, 99),
+		Field::values("geschlecht", "Geschlecht", GENDERS),
+		// A free text, but must not be empty
+		Field {
+			required: true,
+			..Field::freetext("grund", "Deshalb will ich ins Zeltlager fahren")
+		},
+		Field::new("mail", "E-Mailadresse", FieldType::Email),
+		Field::text("handynummer", "Handynummer"),
+		Field::freetext("kommentar", "Sonstige Kommentare"),
+		Field::new("agb", AGB, FieldType::Checkbox),
+	]
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn errors(fields: &[Field], form: &[(&str, &str)]) -> Vec<String> {
+		let form = form.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+		let mut errors = FormErrors::default();
+		check(fields, &form, &mut errors);
+		errors.into_result(()).err().unwrap_or_default().into_iter().map(|e| e.message).collect()
+	}
+
+	#[test]
+	fn field_messages() {
+		let fields = address();
+		let form = [
+			("land", "Deutschland"),
+			("strasse", ""),
+			("hausnummer", "a"),
+			("ort", "München"),
+			("plz", "8033"),
+		];
+		assert_eq!(errors(&fields, &form), [
+			"Strasse muss ausgefüllt werden",
+			"Ungültige Hausnummer (a), muss mindestens eine Ziffer enthalten",
+			"Ungültige Postleitzahl (8033), muss 5 Stellen haben",
+		]);
+		assert_eq!(errors(&fields[..1], &[]), ["Land fehlt"]);
+
+		let fields = health();
+		let form = [
+			("krankenversicherung", "keine"),
+			("krankheiten", ""),
+			("allergien", ""),
+			("unvertraeglichkeiten", ""),
+			("medikamente", ""),
+			("kommentar", ""),
+		];
+		assert_eq!(errors(&fields, &form), ["Ungültige Krankenversicherung (keine), muss \
+		                                     entweder gesetzlich, privat oder anderes sein"]);
+	}
+}
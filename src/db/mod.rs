@@ -10,7 +10,8 @@ use anyhow::{Result, bail, format_err};
 use diesel::prelude::*;
 use diesel::result::Error;
 use diesel_async::pooled_connection::deadpool::Pool;
-use diesel_async::{AsyncMigrationHarness, AsyncPgConnection, RunQueryDsl};
+use diesel_async::scoped_futures::ScopedFutureExt;
+use diesel_async::{AsyncConnection, AsyncMigrationHarness, AsyncPgConnection, RunQueryDsl};
 use diesel_migrations::MigrationHarness;
 use ipnetwork::IpNetwork;
 use scrypt::Scrypt;
@@ -20,7 +21,7 @@ use serde::Serialize;
 use time::{OffsetDateTime, PrimitiveDateTime};
 use tracing::{info, warn};
 
-use crate::auth;
+use crate::{auth, consent};
 
 #[macro_export]
 macro_rules! get_str {
@@ -46,6 +47,7 @@ macro_rules! get_freetext_str {
 
 pub use get_freetext_str;
 
+pub mod fields;
 pub mod models;
 // Generate with `diesel print-schema > src/db/schema.rs`
 pub mod schema;
@@ -53,6 +55,9 @@ pub mod schema;
 pub const MIGRATIONS: diesel_migrations::EmbeddedMigrations =
 	diesel_migrations::embed_migrations!();
 
+diesel::define_sql_function!(fn lower(x: diesel::sql_types::Text) -> diesel::sql_types::Text);
+diesel::define_sql_function!(fn trim(x: diesel::sql_types::Text) -> diesel::sql_types::Text);
+
 #[derive(Clone, Debug, Serialize)]
 pub struct FormError {
 	#[serde(skip_serializing_if = "Option::is_none")]
@@ -60,6 +65,10 @@ pub struct FormError {
 	pub message: String,
 }
 
+/// Collects all errors of a form, at most one per field.
+#[derive(Clone, Debug, Default)]
+pub struct FormErrors(Vec<FormError>);
+
 #[derive(Clone)]
 pub struct Database {
 	pool: Pool<AsyncPgConnection>,
@@ -92,6 +101,36 @@ impl<'a> From<&'a str> for FormError {
 	fn from(s: &'a str) -> Self { Self { field: None, message: s.into() } }
 }
 
+impl FormErrors {
+	/// Add an error, unless there is already an error for the same field.
+	pub fn push(&mut self, error: FormError) {
+		if error.field.is_none() || !self.has(error.field.as_deref().unwrap()) {
+			self.0.push(error);
+		}
+	}
+
+	/// Add the error of a result and return the value if there was no error.
+	pub fn check<T>(&mut self, res: Result<T, FormError>) -> Option<T> {
+		match res {
+			Ok(r) => Some(r),
+			Err(e) => {
+				self.push(e);
+				None
+			}
+		}
+	}
+
+	/// If there is already an error for a field.
+	pub fn has(&self, field: &str) -> bool {
+		self.0.iter().any(|e| e.field.as_deref() == Some(field))
+	}
+
+	/// Return `value` if no error occurred or a list of all errors.
+	pub fn into_result<T>(self, value: T) -> Result<T, Vec<FormError>> {
+		if self.0.is_empty() { Ok(value) } else { Err(self.0) }
+	}
+}
+
 impl Database {
 	pub fn new(config: &crate::Config) -> Result<Self> {
 		let config = diesel_async::pooled_connection::AsyncDieselConnectionManager::<
@@ -120,7 +159,8 @@ impl Database {
 		Ok(())
 	}
 
-	pub async fn check_rate(&self, ip: &str) -> Result<bool> {
+	/// Count a request of `ip`, every `kind` of request has its own counter.
+	pub async fn check_rate(&self, ip: &str, kind: &str) -> Result<bool> {
 		use self::schema::rate_limiting::dsl::*;
 		use diesel::dsl::insert_into;
 
@@ -131,7 +171,8 @@ impl Database {
 			Ok(result) => result.ip().into(),
 			Err(_) => ip.parse::<IpAddr>()?.into(),
 		};
-		let entry_res = rate_limiting.find(ip).first::<models::RateLimiting>(&mut connection).await;
+		let entry_res =
+			rate_limiting.find((ip, kind)).first::<models::RateLimiting>(&mut connection).await;
 		// check for no entry found
 		match entry_res {
 			Ok(entry) => {
@@ -160,6 +201,7 @@ impl Database {
 				insert_into(rate_limiting)
 					.values((
 						ip_addr.eq(ip),
+						bereich.eq(kind),
 						counter.eq(1),
 						first_count.eq(diesel::dsl::now.at_time_zone("utc")),
 					))
@@ -171,7 +213,7 @@ impl Database {
 		}
 	}
 
-	pub async fn decrease_rate_counter(&self, ip: &str) -> Result<()> {
+	pub async fn decrease_rate_counter(&self, ip: &str, kind: &str) -> Result<()> {
 		use self::schema::rate_limiting::dsl::*;
 
 		let mut connection = self.get().await?;
@@ -181,7 +223,8 @@ impl Database {
 			Ok(result) => result.ip().into(),
 			Err(_) => ip.parse::<IpAddr>()?.into(),
 		};
-		let entry_res = rate_limiting.find(ip).first::<models::RateLimiting>(&mut connection).await;
+		let entry_res =
+			rate_limiting.find((ip, kind)).first::<models::RateLimiting>(&mut connection).await;
 		// check for no entry found
 		match entry_res {
 			Ok(entry) => {
@@ -198,23 +241,57 @@ impl Database {
 		}
 	}
 
-	pub async fn count_members(&self) -> Result<i64> {
+	/// Delete a signup draft after the signup was sent.
+	pub async fn delete_signup_draft(&self, draft_token: &str) -> Result<()> {
+		use self::schema::anmeldung_entwurf::dsl::*;
+
+		diesel::delete(anmeldung_entwurf.filter(token.eq(draft_token)))
+			.execute(&mut self.get().await?)
+			.await?;
+		Ok(())
+	}
+
+	/// Number of members signed up for a camp.
+	pub async fn count_members(&self, lager: i32) -> Result<i64> {
+		use self::schema::teilnehmer;
+
+		Ok(teilnehmer::table
+			.filter(teilnehmer::lager.eq(lager).and(teilnehmer::geloescht.is_null()))
+			.count()
+			.get_result(&mut self.get().await?)
+			.await?)
+	}
+
+	/// Search for an existing signup of the same child for a camp.
+	pub async fn find_duplicate_member(
+		&self, member: &models::Teilnehmer, lager: i32,
+	) -> Result<Option<models::FullTeilnehmer>> {
 		use self::schema::teilnehmer;
 
-		Ok(teilnehmer::table.count().get_result(&mut self.get().await?).await?)
+		let candidates = teilnehmer::table
+			.filter(
+				teilnehmer::geburtsdatum
+					.eq(member.geburtsdatum)
+					.and(teilnehmer::lager.eq(lager))
+					.and(teilnehmer::geloescht.is_null()),
+			)
+			.load::<models::FullTeilnehmer>(&mut self.get().await?)
+			.await?;
+		Ok(candidates.into_iter().find(|m| member.is_duplicate_of(m)))
 	}
 
+	/// Save the signup of a supervisor for a camp.
 	pub async fn signup_supervisor(
-		&self, supervisor: &models::Supervisor, is_pre_signup: bool,
+		&self, supervisor: &models::Supervisor, is_pre_signup: bool, lager: i32,
 	) -> Result<()> {
-		use self::schema::betreuer;
 		use self::schema::betreuer::columns::*;
+		use self::schema::{betreuer, betreuer_teilnahme};
 
 		let mut connection = self.get().await?;
 
-		// Check if the e-mail already exists
+		// Check if the e-mail already exists, supervisors in the trash sign up anew
 		let supervisor_id = match betreuer::table
-			.filter(mail.eq(&supervisor.mail))
+			.filter(mail.eq(&supervisor.mail).and(geloescht.is_null()))
 			.select(id)
 			.first::<i32>(&mut connection)
 			.await
@@ -229,25 +306,46 @@ impl Database {
 			bail!("E-mail address is already registered");
 		}
 
-		if let Some(supervisor_id) = supervisor_id {
-			// Update
-			diesel::update(betreuer::table)
-				.filter(id.eq(&supervisor_id))
-				.set((
-					supervisor,
-					anmeldedatum.eq(diesel::dsl::now),
-					signup_token.eq(None::<String>),
-					signup_token_time.eq(None::<PrimitiveDateTime>),
-				))
-				.execute(&mut connection)
-				.await?;
-		} else {
-			// Insert new
-			diesel::insert_into(betreuer::table)
-				.values(supervisor)
-				.execute(&mut connection)
-				.await?;
-		}
+		let documents = consent::supervisor_documents(supervisor.agb, supervisor.selbsterklaerung);
+		connection
+			.transaction::<_, Error, _>(|con| {
+				async move {
+					let supervisor_id = if let Some(supervisor_id) = supervisor_id {
+						// Update
+						diesel::update(betreuer::table)
+							.filter(id.eq(&supervisor_id))
+							.set((
+								supervisor,
+								anmeldedatum.eq(diesel::dsl::now),
+								version.eq(version + 1),
+								signup_token.eq(None::<String>),
+								signup_token_time.eq(None::<PrimitiveDateTime>),
+							))
+							.execute(con)
+							.await?;
+						supervisor_id
+					} else {
+						// Insert new
+						diesel::insert_into(betreuer::table)
+							.values(supervisor)
+							.returning(id)
+							.get_result::<i32>(con)
+							.await?
+					};
+					diesel::insert_into(betreuer_teilnahme::table)
+						.values((
+							betreuer_teilnahme::betreuer.eq(supervisor_id),
+							betreuer_teilnahme::lager.eq(lager),
+						))
+						.on_conflict_do_nothing()
+						.execute(con)
+						.await?;
+					consent::record(con, consent::Signer::Supervisor(supervisor_id), &documents)
+						.await
+				}
+				.scope_boxed()
+			})
+			.await?;
 
 		Ok(())
 	}
@@ -14,15 +14,18 @@ use time::macros::format_description;
 use time::{Date, OffsetDateTime, PrimitiveDateTime};
 use tracing::warn;
 
-use crate::{GERMAN_DATE_FORMAT, ISO_DATE_FORMAT, LAGER_START, PRIMITIVE_DATE_TIME_FORMAT};
+use crate::i18n::Sprache;
+use crate::{GERMAN_DATE_FORMAT, ISO_DATE_FORMAT, PRIMITIVE_DATE_TIME_FORMAT, season};
 
-use super::FormError;
 use super::schema::betreuer;
 use super::schema::erwischt_member;
+use super::schema::lager;
 use super::schema::rate_limiting;
 use super::schema::roles;
+use super::schema::stornierung;
 use super::schema::teilnehmer;
 use super::schema::users;
+use super::{FormError, FormErrors, fields};
 
 macro_rules! get_bool {
 	($map:ident, $key:expr) => {
@@ -46,25 +49,10 @@ macro_rules! get_bool {
 	};
 }
 
-macro_rules! check_empty {
-	($obj:ident $(,)?) => {};
-	($obj:ident, $field:ident? $($rest:tt)*) => {
-		if $obj.$field.as_ref().unwrap().is_empty() {
-			return Err(FormError {
-				field: Some(stringify!($field).into()),
-				message: format!("{} muss ausgefüllt werden", stringify!($field).to_title_case()),
-			});
-		}
-		check_empty!($obj $($rest)*)
-	};
-	($obj:ident, $field:ident $($rest:tt)*) => {
-		if $obj.$field.is_empty() {
-			return Err(FormError {
-				field: Some(stringify!($field).into()),
-				message: format!("{} muss ausgefüllt werden", stringify!($field).to_title_case()),
-			});
-		}
-		check_empty!($obj $($rest)*)
+/// Add the error of a result to the error list and use the default value on errors.
+macro_rules! collect {
+	($errors:ident, $res:expr) => {
+		$errors.check($res).unwrap_or_default()
 	};
 }
 
@@ -96,6 +84,10 @@ pub struct Teilnehmer {
 	pub land: String,
 	pub krankheiten: String,
 	pub eigenanreise: bool,
+	/// Keep the data to prefill the signup next year.
+	pub daten_behalten: bool,
+	/// Language code of the signup, mails to the parents use this language.
+	pub sprache: String,
 }
 
 #[derive(AsChangeset, Clone, Debug, Deserialize, Identifiable, Serialize, Queryable)]
@@ -130,8 +122,25 @@ pub struct FullTeilnehmer {
 	pub land: String,
 	pub krankheiten: String,
 	pub eigenanreise: bool,
+	#[serde(default)]
+	pub daten_behalten: bool,
+	#[serde(default = "default_sprache")]
+	pub sprache: String,
+	/// Time when the entry was moved to the trash.
+	#[serde(skip)]
+	#[diesel(skip_update)]
+	pub geloescht: Option<PrimitiveDateTime>,
+	/// Incremented on every change, edits of an older version are rejected.
+	#[diesel(skip_update)]
+	pub version: i32,
+	/// The camp of the signup.
+	#[serde(default)]
+	#[diesel(skip_update)]
+	pub lager: Option<i32>,
 }
 
+fn default_sprache() -> String { Sprache::De.code().into() }
+
 // Without id, anmeldedatum and signup_token/time
 #[derive(Clone, Debug, Insertable, Serialize, Queryable, AsChangeset)]
 #[diesel(table_name = betreuer)]
@@ -208,15 +217,23 @@ pub struct FullSupervisor {
 	#[serde(skip)]
 	#[allow(dead_code)]
 	pub signup_token_time: Option<PrimitiveDateTime>,
+	/// Time when the entry was moved to the trash.
+	#[serde(skip)]
+	#[diesel(skip_update)]
+	pub geloescht: Option<PrimitiveDateTime>,
+	/// Incremented on every change, edits of an older version are rejected.
+	#[diesel(skip_update)]
+	pub version: i32,
 }
 
 #[derive(Clone, Debug, Insertable, Queryable, Identifiable)]
-#[diesel(primary_key(ip_addr))]
+#[diesel(primary_key(ip_addr, bereich))]
 #[diesel(table_name = rate_limiting)]
 pub struct RateLimiting {
 	pub ip_addr: IpNetwork,
 	pub counter: i32,
 	pub first_count: PrimitiveDateTime,
+	pub bereich: String,
 }
 
 #[derive(Clone, Debug, Insertable)]
@@ -236,6 +253,35 @@ pub struct UserQueryResult {
 	pub token: Option<String>,
 }
 
+/// The camp of one season.
+#[derive(Clone, Debug, Queryable, Serialize)]
+pub struct Lager {
+	pub id: i32,
+	pub name: String,
+	#[serde(with = "date")]
+	pub beginn: Date,
+	#[serde(with = "date")]
+	pub ende: Date,
+	/// Maximum number of participants.
+	pub kapazitaet: i32,
+	/// Participation fee in Euro.
+	pub preis: i32,
+	pub aktuell: bool,
+}
+
+// Without id and aktuell
+#[derive(AsChangeset, Clone, Debug, Deserialize, Insertable)]
+#[diesel(table_name = lager)]
+pub struct NewLager {
+	pub name: String,
+	#[serde(with = "date")]
+	pub beginn: Date,
+	#[serde(with = "date")]
+	pub ende: Date,
+	pub kapazitaet: i32,
+	pub preis: i32,
+}
+
 #[derive(Clone, Debug, Insertable, Queryable)]
 #[diesel(table_name = roles)]
 pub struct Role {
@@ -248,6 +294,7 @@ pub struct ErwischtGame {
 	pub id: i32,
 	#[serde(with = "primitive_datetime")]
 	pub created: PrimitiveDateTime,
+	pub lager: Option<i32>,
 }
 
 #[derive(Clone, Debug, Insertable)]
@@ -269,6 +316,57 @@ pub struct ErwischtMember {
 	pub last_change: Option<PrimitiveDateTime>,
 }
 
+// Without id, stornodatum and erstattet
+#[derive(Clone, Debug, Insertable)]
+#[diesel(table_name = stornierung)]
+pub struct NewCancellation {
+	pub vorname: String,
+	pub nachname: String,
+	pub geburtsdatum: Date,
+	pub eltern_name: String,
+	pub eltern_mail: String,
+	pub anmeldedatum: PrimitiveDateTime,
+	pub bezahlt: bool,
+	pub erstattung_prozent: i32,
+	pub erstattung: i32,
+	pub lager: Option<i32>,
+}
+
+#[derive(Clone, Debug, Queryable, Serialize)]
+pub struct Cancellation {
+	pub id: i32,
+	pub vorname: String,
+	pub nachname: String,
+	#[serde(with = "date")]
+	pub geburtsdatum: Date,
+	pub eltern_name: String,
+	pub eltern_mail: String,
+	#[serde(with = "primitive_datetime")]
+	pub anmeldedatum: PrimitiveDateTime,
+	#[serde(with = "primitive_datetime")]
+	pub stornodatum: PrimitiveDateTime,
+	pub bezahlt: bool,
+	pub erstattung_prozent: i32,
+	pub erstattung: i32,
+	pub erstattet: bool,
+	pub lager: Option<i32>,
+	/// Time when the entry was moved to the trash.
+	#[serde(skip)]
+	pub geloescht: Option<PrimitiveDateTime>,
+}
+
+/// A version of a document that has to be accepted at the signup.
+#[derive(Clone, Debug, Queryable, Serialize)]
+pub struct DocumentVersion {
+	pub id: i32,
+	pub dokument: String,
+	pub version: String,
+	pub datei: String,
+	pub hash: String,
+	#[serde(with = "date")]
+	pub gueltig_ab: Date,
+}
+
 pub fn try_parse_date(s: &str, field: &str) -> Result<Date, FormError> {
 	let formats = &[
 		GERMAN_DATE_FORMAT,
@@ -341,71 +439,99 @@ pub fn years_old(date: Date, birthday_date: &Date) -> i32 {
 	years
 }
 
-pub fn check_plz(text: &str, country: &str) -> Result<(), FormError> {
-	let error = if !text.chars().all(|c| c.is_numeric()) {
-		Some("darf nur Zahlen enthalten")
-	} else if country == "Deutschland" {
-		(text.len() != 5).then_some("muss 5 Stellen haben")
+/// Youngest participant age at the start of the camp.
+pub const MEMBER_MIN_AGE: i32 = 7;
+/// Oldest participant age at the start of the camp.
+pub const MEMBER_MAX_AGE: i32 = 15;
+/// Youngest supervisor age at the start of the camp.
+pub const SUPERVISOR_MIN_AGE: i32 = 15;
+
+pub fn check_email(text: &str, field: &str) -> Result<(), FormError> {
+	let at_pos = text.find('@');
+	let error = if at_pos.is_none() {
+		Some("muss ein @ enthalten")
+	} else if at_pos != text.rfind('@') {
+		Some("bitten nur eine einzelne E-Mail-Adresse angeben")
+	} else if text.contains(' ') {
+		Some("darf keine Leerzeichen enthalten")
 	} else {
 		None
 	};
 	if let Some(error) = error {
 		return Err(FormError {
-			field: Some("plz".into()),
-			message: format!("Ungültige Postleitzahl ({}), {}", text, error),
+			field: Some(field.into()),
+			message: format!("Ungültige E-Mail-Adresse ({}), {}", text, error),
 		});
 	}
 	Ok(())
 }
 
-pub fn check_krankenversicherung(text: &str) -> Result<(), FormError> {
-	// Check krankenversicherung
-	if text != "gesetzlich" && text != "privat" && text != "anderes" {
+/// Check that a participant has the right age for the camp.
+pub fn check_member_birthday(geburtsdatum: Date) -> Result<(), FormError> {
+	let now = OffsetDateTime::now_utc().date();
+	let years = years_old(geburtsdatum, &season::beginn());
+	if now <= geburtsdatum || years >= 100 {
 		return Err(FormError {
-			field: Some("krankenversicherung".into()),
+			field: Some("geburtsdatum".into()),
 			message: format!(
-				"Ungültige Krankenversicherung ({}), muss entweder gesetzlich, privat oder \
-				 anderes sein",
-				text
+				"Sind Sie sicher, dass {} das Geburtsdatum Ihres Kindes ist?\nBitte geben Sie das \
+				 Geburtsdatum im Format TT.MM.JJJJ an.",
+				geburtsdatum.format(GERMAN_DATE_FORMAT).unwrap()
 			),
 		});
 	}
-	Ok(())
-}
 
-pub fn check_email(text: &str, field: &str) -> Result<(), FormError> {
-	let at_pos = text.find('@');
-	let error = if at_pos.is_none() {
-		Some("muss ein @ enthalten")
-	} else if at_pos != text.rfind('@') {
-		Some("bitten nur eine einzelne E-Mail-Adresse angeben")
-	} else if text.contains(' ') {
-		Some("darf keine Leerzeichen enthalten")
-	} else {
-		None
-	};
-	if let Some(error) = error {
+	if years < MEMBER_MIN_AGE {
 		return Err(FormError {
-			field: Some(field.into()),
-			message: format!("Ungültige E-Mail-Adresse ({}), {}", text, error),
+			field: Some("geburtsdatum".into()),
+			message: format!(
+				"Ihr Kind ist zu jung (Geburtsdatum {}).\nDas Zeltlager ist für Kinder und \
+				 Jugendliche zwischen 7 und 15 Jahren.",
+				geburtsdatum.format(GERMAN_DATE_FORMAT).unwrap()
+			),
+		});
+	}
+	if years > MEMBER_MAX_AGE {
+		return Err(FormError {
+			field: Some("geburtsdatum".into()),
+			message: format!(
+				"Ihr Kind ist zu alt um als Teilnehmer beim Zeltlager mitzufahren (Geburtsdatum \
+				 {}).\nWir suchen immer nach motivierten Betreuern (ab 16 Jahren), die auf das \
+				 Zeltlager mitfahren.\nInfos dazu finden Sie auf der Betreuerseite.\nDas \
+				 Zeltlager ist für Kinder und Jugendliche zwischen 7 und 15 Jahren.",
+				geburtsdatum.format(GERMAN_DATE_FORMAT).unwrap()
+			),
 		});
 	}
 	Ok(())
 }
 
-pub fn check_house_number(text: &str) -> Result<(), FormError> {
-	// Check for at least one digit
-	if text.find(|c: char| c.is_ascii_digit()).is_none() {
-		Err(FormError {
-			field: Some("hausnummer".into()),
+/// Check that a supervisor is old enough.
+pub fn check_supervisor_birthday(geburtsdatum: Date) -> Result<(), FormError> {
+	let now = OffsetDateTime::now_utc().date();
+	let years = years_old(geburtsdatum, &season::beginn());
+	if now <= geburtsdatum || years >= 100 {
+		return Err(FormError {
+			field: Some("geburtsdatum".into()),
 			message: format!(
-				"Ungültige Hausnummer ({}), muss mindestens eine Ziffer enthalten",
-				text
+				"Sind Sie sicher, dass {} ihr Geburtsdatum ist?\nBitte geben Sie das Geburtsdatum \
+				 im Format TT.MM.JJJJ an.",
+				geburtsdatum.format(GERMAN_DATE_FORMAT).unwrap()
 			),
-		})
-	} else {
-		Ok(())
+		});
 	}
+
+	if years < SUPERVISOR_MIN_AGE {
+		return Err(FormError {
+			field: Some("geburtsdatum".into()),
+			message: format!(
+				"Mit deinem Geburtsdatum {} bist du leider zu jung, um als Betreuer mit aufs \
+				 Zeltlager zu fahren 🙂, bitte melde dich als Teilnehmer an.",
+				geburtsdatum.format(GERMAN_DATE_FORMAT).unwrap()
+			),
+		});
+	}
+	Ok(())
 }
 
 pub fn cleanup_freetext(text: String) -> String {
@@ -418,6 +544,40 @@ pub fn cleanup_freetext(text: String) -> String {
 	text
 }
 
+/// Entries with the same key are the same child, signed up by the same parents.
+pub fn duplicate_key(
+	vorname: &str, nachname: &str, geburtsdatum: Date, eltern_mail: &str,
+) -> (String, String, Date, String) {
+	(
+		normalize_name(vorname),
+		normalize_name(nachname),
+		geburtsdatum,
+		eltern_mail.trim().to_lowercase(),
+	)
+}
+
+/// Normalize a name to find participants that were signed up twice.
+///
+/// Ignores case, umlaut spelling and separating whitespace or dashes.
+pub fn normalize_name(name: &str) -> String {
+	let mut res = String::with_capacity(name.len());
+	for part in name.split(|c: char| c.is_whitespace() || c == '-').filter(|p| !p.is_empty()) {
+		if !res.is_empty() {
+			res.push(' ');
+		}
+		for c in part.chars().flat_map(char::to_lowercase) {
+			match c {
+				'ä' => res.push_str("ae"),
+				'ö' => res.push_str("oe"),
+				'ü' => res.push_str("ue"),
+				'ß' => res.push_str("ss"),
+				c => res.push(c),
+			}
+		}
+	}
+	res
+}
+
 #[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq, FromSqlRow, AsExpression, Serialize)]
 #[diesel(sql_type = Text)]
 pub enum Gender {
@@ -558,103 +718,66 @@ pub mod opt_primitive_datetime {
 }
 
 impl Teilnehmer {
-	pub fn from_hashmap(mut map: HashMap<String, String>) -> Result<Self, FormError> {
-		let date = get_str!(map, "geburtsdatum")?;
-		let geburtsdatum = try_parse_date(&date, "geburtsdatum")?;
-		let geschlecht = try_parse_gender(&get_str!(map, "geschlecht")?)?;
+	/// If this is the same child as `other`, signed up by the same parents.
+	pub fn is_duplicate_of(&self, other: &FullTeilnehmer) -> bool {
+		duplicate_key(&self.vorname, &self.nachname, self.geburtsdatum, &self.eltern_mail)
+			== other.duplicate_key()
+	}
+
+	/// Parse and validate a signup form.
+	///
+	/// Returns all errors that were found.
+	pub fn from_hashmap(mut map: HashMap<String, String>) -> Result<Self, Vec<FormError>> {
+		let mut errors = FormErrors::default();
+		fields::check(&fields::member(), &map, &mut errors);
+		let geburtsdatum = errors
+			.check(get_str!(map, "geburtsdatum").and_then(|d| try_parse_date(&d, "geburtsdatum")));
+		let geschlecht =
+			errors.check(get_str!(map, "geschlecht").and_then(|g| try_parse_gender(&g)));
 
 		let res = Self {
-			vorname: get_str!(map, "vorname")?,
-			nachname: get_str!(map, "nachname")?,
-			geburtsdatum,
-			geschlecht,
-
-			schwimmer: get_bool!(map, "schwimmer")?,
-			vegetarier: get_bool!(map, "vegetarier")?,
-			tetanus_impfung: get_bool!(map, "tetanus_impfung")?,
-
-			eltern_name: get_str!(map, "eltern_name")?,
-			eltern_mail: get_str!(map, "eltern_mail")?,
-			eltern_handynummer: get_str!(map, "eltern_handynummer")?,
-			land: get_str!(map, "land")?,
-			strasse: get_str!(map, "strasse")?,
-			hausnummer: get_str!(map, "hausnummer")?,
-			ort: get_str!(map, "ort")?,
-			plz: get_str!(map, "plz")?,
-
-			krankenversicherung: get_str!(map, "krankenversicherung")?,
-			krankheiten: get_freetext_str!(map, "krankheiten")?,
-			allergien: get_freetext_str!(map, "allergien")?,
-			unvertraeglichkeiten: get_freetext_str!(map, "unvertraeglichkeiten")?,
-			medikamente: get_freetext_str!(map, "medikamente")?,
-			kommentar: get_freetext_str!(map, "kommentar")?,
-
-			eigenanreise: get_bool!(map, "eigenanreise")?,
-			agb: get_bool!(map, "agb")?,
+			vorname: collect!(errors, get_str!(map, "vorname")),
+			nachname: collect!(errors, get_str!(map, "nachname")),
+			// Placeholders are never returned, there is an error for these fields
+			geburtsdatum: geburtsdatum.unwrap_or(Date::MIN),
+			geschlecht: geschlecht.unwrap_or(Gender::Male),
+
+			schwimmer: collect!(errors, get_bool!(map, "schwimmer")),
+			vegetarier: collect!(errors, get_bool!(map, "vegetarier")),
+			tetanus_impfung: collect!(errors, get_bool!(map, "tetanus_impfung")),
+
+			eltern_name: collect!(errors, get_str!(map, "eltern_name")),
+			eltern_mail: collect!(errors, get_str!(map, "eltern_mail")),
+			eltern_handynummer: collect!(errors, get_str!(map, "eltern_handynummer")),
+			land: collect!(errors, get_str!(map, "land")),
+			strasse: collect!(errors, get_str!(map, "strasse")),
+			hausnummer: collect!(errors, get_str!(map, "hausnummer")),
+			ort: collect!(errors, get_str!(map, "ort")),
+			plz: collect!(errors, get_str!(map, "plz")),
+
+			krankenversicherung: collect!(errors, get_str!(map, "krankenversicherung")),
+			krankheiten: collect!(errors, get_freetext_str!(map, "krankheiten")),
+			allergien: collect!(errors, get_freetext_str!(map, "allergien")),
+			unvertraeglichkeiten: collect!(errors, get_freetext_str!(map, "unvertraeglichkeiten")),
+			medikamente: collect!(errors, get_freetext_str!(map, "medikamente")),
+			kommentar: collect!(errors, get_freetext_str!(map, "kommentar")),
+
+			eigenanreise: collect!(errors, get_bool!(map, "eigenanreise")),
+			daten_behalten: collect!(errors, get_bool!(map, "daten_behalten")),
+			agb: collect!(errors, get_bool!(map, "agb")),
+			// Optional, older clients do not send it
+			sprache: Sprache::from_code(&map.remove("sprache").unwrap_or_default()).code().into(),
 		};
 
 		if !res.agb {
-			return Err(FormError {
+			errors.push(FormError {
 				field: Some("agb".into()),
 				message: "Die AGB müssen akzeptiert werden".into(),
 			});
 		}
 
-		check_empty!(
-			res,
-			vorname,
-			nachname,
-			eltern_name,
-			eltern_mail,
-			eltern_handynummer,
-			land,
-			strasse,
-			hausnummer,
-			ort,
-			plz,
-		);
-
-		check_plz(&res.plz, &res.land)?;
-		check_krankenversicherung(&res.krankenversicherung)?;
-		check_email(&res.eltern_mail, "eltern_mail")?;
-		check_house_number(&res.hausnummer)?;
-
-		// Check birth date
-		let now = OffsetDateTime::now_utc().date();
-		let years = years_old(res.geburtsdatum, &LAGER_START);
-		if now <= res.geburtsdatum || years >= 100 {
-			return Err(FormError {
-				field: Some("geburtsdatum".into()),
-				message: format!(
-					"Sind Sie sicher, dass {} das Geburtsdatum Ihres Kindes ist?\nBitte geben Sie \
-					 das Geburtsdatum im Format TT.MM.JJJJ an.",
-					res.geburtsdatum.format(GERMAN_DATE_FORMAT).unwrap()
-				),
-			});
-		}
-
-		if years < 7 {
-			return Err(FormError {
-				field: Some("geburtsdatum".into()),
-				message: format!(
-					"Ihr Kind ist zu jung (Geburtsdatum {}).\nDas Zeltlager ist für Kinder und \
-					 Jugendliche zwischen 7 und 15 Jahren.",
-					res.geburtsdatum.format(GERMAN_DATE_FORMAT).unwrap()
-				),
-			});
-		}
-		if years > 15 {
-			return Err(FormError {
-				field: Some("geburtsdatum".into()),
-				message: format!(
-					"Ihr Kind ist zu alt um als Teilnehmer beim Zeltlager mitzufahren \
-					 (Geburtsdatum {}).\nWir suchen immer nach motivierten Betreuern (ab 16 \
-					 Jahren), die auf das Zeltlager mitfahren.\nInfos dazu finden Sie auf der \
-					 Betreuerseite.\nDas Zeltlager ist für Kinder und Jugendliche zwischen 7 und \
-					 15 Jahren.",
-					res.geburtsdatum.format(GERMAN_DATE_FORMAT).unwrap()
-				),
-			});
+		if !errors.has("geburtsdatum") {
+			errors.check(check_member_birthday(res.geburtsdatum));
 		}
 
 		map.remove("submit");
@@ -662,7 +785,7 @@ impl Teilnehmer {
 			warn!(?map, "Teilnehmer::from_hashmap: Map is not yet empty");
 		}
 
-		Ok(res)
+		errors.into_result(res)
 	}
 
 	pub fn trim(&mut self) {
@@ -686,121 +809,127 @@ impl Teilnehmer {
 }
 
 impl Supervisor {
-	pub fn from_hashmap(mut map: HashMap<String, String>) -> Result<Self, FormError> {
-		let date = get_str!(map, "geburtsdatum")?;
-		let geburtsdatum = try_parse_date(&date, "geburtsdatum")?;
-		let geschlecht = try_parse_gender(&get_str!(map, "geschlecht")?)?;
-
-		let f_date = get_str!(map, "fuehrungszeugnis_ausstellung")?;
-		let fuehrungszeugnis_ausstellung = if !f_date.is_empty() {
-			Some(try_parse_date(&f_date, "fuehrungszeugnis_ausstellung")?)
-		} else {
-			None
-		};
-		let j_date = get_str!(map, "juleica_gueltig_bis")?;
-		let juleica_gueltig_bis = if !j_date.is_empty() {
-			Some(try_parse_date(&j_date, "juleica_gueltig_bis")?)
-		} else {
-			None
-		};
+	/// Parse and validate a supervisor signup form.
+	///
+	/// Returns all errors that were found.
+	pub fn from_hashmap(mut map: HashMap<String, String>) -> Result<Self, Vec<FormError>> {
+		let mut errors = FormErrors::default();
+		fields::check(&fields::supervisor(), &map, &mut errors);
+		let geburtsdatum = errors
+			.check(get_str!(map, "geburtsdatum").and_then(|d| try_parse_date(&d, "geburtsdatum")));
+		let geschlecht =
+			errors.check(get_str!(map, "geschlecht").and_then(|g| try_parse_gender(&g)));
+
+		let fuehrungszeugnis_ausstellung =
+			errors.check(get_str!(map, "fuehrungszeugnis_ausstellung").and_then(|d| {
+				if !d.is_empty() {
+					Ok(Some(try_parse_date(&d, "fuehrungszeugnis_ausstellung")?))
+				} else {
+					Ok(None)
+				}
+			}));
+		let juleica_gueltig_bis =
+			errors.check(get_str!(map, "juleica_gueltig_bis").and_then(|d| {
+				if !d.is_empty() {
+					Ok(Some(try_parse_date(&d, "juleica_gueltig_bis")?))
+				} else {
+					Ok(None)
+				}
+			}));
 
 		// Check that it is valid until the camp
-		if juleica_gueltig_bis.map(|d| d < *LAGER_START).unwrap_or_default() {
-			return Err(FormError {
+		if juleica_gueltig_bis.flatten().map(|d| d < season::beginn()).unwrap_or_default() {
+			errors.push(FormError {
 				field: Some("juleica_gueltig_bis".into()),
 				message: "Deine Juleica ist nicht mehr bis zum nächsten Zeltlager gültig!".into(),
 			});
 		}
 
-		let juleica_nummer_str = get_str!(map, "juleica_nummer")?;
+		let juleica_nummer_str = collect!(errors, get_str!(map, "juleica_nummer"));
 		let juleica_nummer =
 			if juleica_nummer_str.is_empty() { None } else { Some(juleica_nummer_str) };
 
 		let res = Self {
-			vorname: get_str!(map, "vorname")?,
-			nachname: get_str!(map, "nachname")?,
-			geburtsdatum,
-			geschlecht,
+			vorname: collect!(errors, get_str!(map, "vorname")),
+			nachname: collect!(errors, get_str!(map, "nachname")),
+			// Placeholders are never returned, there is an error for these fields
+			geburtsdatum: geburtsdatum.unwrap_or(Date::MIN),
+			geschlecht: geschlecht.unwrap_or(Gender::Male),
 
-			vegetarier: Some(get_bool!(map, "vegetarier")?),
-			tetanus_impfung: Some(get_bool!(map, "tetanus_impfung")?),
+			vegetarier: Some(collect!(errors, get_bool!(map, "vegetarier"))),
+			tetanus_impfung: Some(collect!(errors, get_bool!(map, "tetanus_impfung"))),
 
 			juleica_nummer,
-			juleica_gueltig_bis,
-			mail: get_str!(map, "mail")?,
-			handynummer: get_str!(map, "handynummer")?,
-			land: Some(get_str!(map, "land")?),
-			strasse: Some(get_str!(map, "strasse")?),
-			hausnummer: Some(get_str!(map, "hausnummer")?),
-			ort: Some(get_str!(map, "ort")?),
-			plz: Some(get_str!(map, "plz")?),
-
-			krankenversicherung: Some(get_str!(map, "krankenversicherung")?),
-			krankheiten: Some(get_freetext_str!(map, "krankheiten")?),
-			allergien: Some(get_freetext_str!(map, "allergien")?),
-			unvertraeglichkeiten: Some(get_freetext_str!(map, "unvertraeglichkeiten")?),
-			medikamente: Some(get_freetext_str!(map, "medikamente")?),
-			kommentar: Some(get_freetext_str!(map, "kommentar")?),
-			fuehrungszeugnis_ausstellung,
+			juleica_gueltig_bis: juleica_gueltig_bis.flatten(),
+			mail: collect!(errors, get_str!(map, "mail")),
+			handynummer: collect!(errors, get_str!(map, "handynummer")),
+			land: Some(collect!(errors, get_str!(map, "land"))),
+			strasse: Some(collect!(errors, get_str!(map, "strasse"))),
+			hausnummer: Some(collect!(errors, get_str!(map, "hausnummer"))),
+			ort: Some(collect!(errors, get_str!(map, "ort"))),
+			plz: Some(collect!(errors, get_str!(map, "plz"))),
+
+			krankenversicherung: Some(collect!(errors, get_str!(map, "krankenversicherung"))),
+			krankheiten: Some(collect!(errors, get_freetext_str!(map, "krankheiten"))),
+			allergien: Some(collect!(errors, get_freetext_str!(map, "allergien"))),
+			unvertraeglichkeiten: Some(collect!(
+				errors,
+				get_freetext_str!(map, "unvertraeglichkeiten")
+			)),
+			medikamente: Some(collect!(errors, get_freetext_str!(map, "medikamente"))),
+			kommentar: Some(collect!(errors, get_freetext_str!(map, "kommentar"))),
+			fuehrungszeugnis_ausstellung: fuehrungszeugnis_ausstellung.flatten(),
 			fuehrungszeugnis_eingesehen: None,
 
-			selbsterklaerung: get_bool!(map, "selbsterklaerung")?,
-			agb: get_bool!(map, "agb")?,
+			selbsterklaerung: collect!(errors, get_bool!(map, "selbsterklaerung")),
+			agb: collect!(errors, get_bool!(map, "agb")),
 		};
 
 		if !res.selbsterklaerung {
-			return Err(FormError {
+			errors.push(FormError {
 				field: Some("selbsterklaerung".into()),
 				message: "Die Selbsterklärung muss abgegeben werden".into(),
 			});
 		}
 
-		check_empty!(res, land?, strasse?, hausnummer?, ort?, plz?);
-
-		check_plz(res.plz.as_ref().unwrap(), res.land.as_ref().unwrap())?;
-		check_krankenversicherung(res.krankenversicherung.as_ref().unwrap())?;
-		check_house_number(res.hausnummer.as_ref().unwrap())?;
-
-		// Check Juleica Number
-		if let Some(ref jn) = res.juleica_nummer {
-			if !jn.chars().all(|c| c.is_numeric()) {
-				return Err(FormError {
-					field: Some("juleica_nummer".into()),
-					message: format!(
-						"Ungültige Juleicanummer ({}), darf nur Ziffern enthalten",
-						jn
-					),
-				});
-			}
-		}
-
-		res.validate_common()?;
+		res.validate_common(&mut errors);
 
 		map.remove("submit");
 		if !map.is_empty() {
 			warn!(?map, "Supervisor::from_hashmap: Map is not yet empty");
 		}
 
-		Ok(res)
+		errors.into_result(res)
 	}
 
-	pub fn from_pre_hashmap(mut map: HashMap<String, String>) -> Result<Self, FormError> {
-		let date = get_str!(map, "geburtsdatum")?;
-		let geburtsdatum = try_parse_date(&date, "geburtsdatum")?;
-		let geschlecht = try_parse_gender(&get_str!(map, "geschlecht")?)?;
+	/// Parse and validate a supervisor pre-signup form.
+	///
+	/// Returns all errors that were found.
+	pub fn from_pre_hashmap(mut map: HashMap<String, String>) -> Result<Self, Vec<FormError>> {
+		let mut errors = FormErrors::default();
+		let form_fields = fields::presignup()
+			.into_iter()
+			.filter(|f| !fields::PRESIGNUP_EXTRA.contains(&f.id))
+			.collect::<Vec<_>>();
+		fields::check(&form_fields, &map, &mut errors);
+		let geburtsdatum = errors
+			.check(get_str!(map, "geburtsdatum").and_then(|d| try_parse_date(&d, "geburtsdatum")));
+		let geschlecht =
+			errors.check(get_str!(map, "geschlecht").and_then(|g| try_parse_gender(&g)));
 
 		let res = Self {
-			vorname: get_str!(map, "vorname")?,
-			nachname: get_str!(map, "nachname")?,
-			geburtsdatum,
-			geschlecht,
+			vorname: collect!(errors, get_str!(map, "vorname")),
+			nachname: collect!(errors, get_str!(map, "nachname")),
+			// Placeholders are never returned, there is an error for these fields
+			geburtsdatum: geburtsdatum.unwrap_or(Date::MIN),
+			geschlecht: geschlecht.unwrap_or(Gender::Male),
 
 			vegetarier: None,
 			tetanus_impfung: None,
 			juleica_nummer: None,
 			juleica_gueltig_bis: None,
-			mail: get_str!(map, "mail")?,
-			handynummer: get_str!(map, "handynummer")?,
+			mail: collect!(errors, get_str!(map, "mail")),
+			handynummer: collect!(errors, get_str!(map, "handynummer")),
 			land: None,
 			strasse: None,
 			hausnummer: None,
@@ -817,57 +946,93 @@ impl Supervisor {
 			fuehrungszeugnis_eingesehen: None,
 
 			selbsterklaerung: false,
-			agb: get_bool!(map, "agb")?,
+			agb: collect!(errors, get_bool!(map, "agb")),
 		};
 
-		res.validate_common()?;
+		res.validate_common(&mut errors);
 
 		map.remove("submit");
 		if !map.is_empty() {
 			warn!(?map, "Supervisor::from_pre_hashmap: Map is not yet empty");
 		}
 
-		Ok(res)
+		errors.into_result(res)
 	}
 
-	fn validate_common(&self) -> Result<(), FormError> {
+	fn validate_common(&self, errors: &mut FormErrors) {
 		if !self.agb {
-			return Err(FormError {
+			errors.push(FormError {
 				field: Some("agb".into()),
 				message: "Die AGB müssen akzeptiert werden".into(),
 			});
 		}
 
-		check_empty!(self, vorname, nachname, mail, handynummer);
-
-		check_email(&self.mail, "mail")?;
-
-		// Check birth date
-		let now = OffsetDateTime::now_utc().date();
-		let years = years_old(self.geburtsdatum, &LAGER_START);
-		if now <= self.geburtsdatum || years >= 100 {
-			return Err(FormError {
-				field: Some("geburtsdatum".into()),
-				message: format!(
-					"Sind Sie sicher, dass {} ihr Geburtsdatum ist?\nBitte geben Sie das \
-					 Geburtsdatum im Format TT.MM.JJJJ an.",
-					self.geburtsdatum.format(GERMAN_DATE_FORMAT).unwrap()
-				),
-			});
+		if !errors.has("geburtsdatum") {
+			errors.check(check_supervisor_birthday(self.geburtsdatum));
 		}
+	}
+}
 
-		if years < 15 {
-			return Err(FormError {
-				field: Some("geburtsdatum".into()),
-				message: format!(
-					"Mit deinem Geburtsdatum {} bist du leider zu jung, um als Betreuer mit aufs \
-					 Zeltlager zu fahren 🙂, bitte melde dich als Teilnehmer an.",
-					self.geburtsdatum.format(GERMAN_DATE_FORMAT).unwrap()
-				),
-			});
-		}
+impl FullTeilnehmer {
+	/// See [`duplicate_key`].
+	pub fn duplicate_key(&self) -> (String, String, Date, String) {
+		duplicate_key(&self.vorname, &self.nachname, self.geburtsdatum, &self.eltern_mail)
+	}
 
-		Ok(())
+	/// The data that is kept to prefill next year's signup form, keyed by form field names.
+	pub fn returning_form_data(&self) -> HashMap<String, String> {
+		let bool_str = |b: bool| if b { "true" } else { "false" }.to_string();
+		vec![
+			("vorname", self.vorname.clone()),
+			("nachname", self.nachname.clone()),
+			("geburtsdatum", self.geburtsdatum.format(GERMAN_DATE_FORMAT).unwrap()),
+			("geschlecht", self.geschlecht.to_string()),
+			("schwimmer", bool_str(self.schwimmer)),
+			("vegetarier", bool_str(self.vegetarier)),
+			("tetanus_impfung", bool_str(self.tetanus_impfung)),
+			("eltern_name", self.eltern_name.clone()),
+			("eltern_mail", self.eltern_mail.clone()),
+			("eltern_handynummer", self.eltern_handynummer.clone()),
+			("land", self.land.clone()),
+			("strasse", self.strasse.clone()),
+			("hausnummer", self.hausnummer.clone()),
+			("ort", self.ort.clone()),
+			("plz", self.plz.clone()),
+			("krankenversicherung", self.krankenversicherung.clone()),
+			("krankheiten", self.krankheiten.clone()),
+			("allergien", self.allergien.clone()),
+			("unvertraeglichkeiten", self.unvertraeglichkeiten.clone()),
+			("medikamente", self.medikamente.clone()),
+			("daten_behalten", bool_str(self.daten_behalten)),
+			("sprache", self.sprache.clone()),
+		]
+		.into_iter()
+		.map(|(k, v)| (k.to_string(), v))
+		.collect()
+	}
+
+	/// Take over payment and history data from a duplicate entry of the same child.
+	///
+	/// Text fields which are empty in `self` are filled from `other`, comments are combined. The
+	/// signup date of `self` is kept, the cancel link that was sent to the parents depends on it.
+	pub fn merge(&mut self, other: FullTeilnehmer) {
+		self.bezahlt |= other.bezahlt;
+		self.anwesend |= other.anwesend;
+		self.daten_behalten |= other.daten_behalten;
+		for (field, other_field) in [
+			(&mut self.allergien, other.allergien),
+			(&mut self.unvertraeglichkeiten, other.unvertraeglichkeiten),
+			(&mut self.medikamente, other.medikamente),
+			(&mut self.krankheiten, other.krankheiten),
+			(&mut self.kommentar, other.kommentar),
+		] {
+			if field.is_empty() {
+				*field = other_field;
+			} else if !other_field.is_empty() && *field != other_field {
+				field.push('\n');
+				field.push_str(&other_field);
+			}
+		}
 	}
 }
 
@@ -875,7 +1040,7 @@ impl Supervisor {
 mod tests {
 	use time::{Date, Month};
 
-	use super::try_parse_date;
+	use super::{normalize_name, try_parse_date};
 
 	#[test]
 	fn parse_date() {
@@ -892,4 +1057,13 @@ mod tests {
 			assert_eq!(d.1.unwrap(), res);
 		}
 	}
+
+	#[test]
+	fn normalize_names() {
+		assert_eq!(normalize_name(" Anna  Lena "), "anna lena");
+		assert_eq!(normalize_name("Anna-Lena"), "anna lena");
+		assert_eq!(normalize_name("Müller"), normalize_name("Mueller"));
+		assert_eq!(normalize_name("STRAUSS"), normalize_name("Strauß"));
+		assert_ne!(normalize_name("Max"), normalize_name("Moritz"));
+	}
 }
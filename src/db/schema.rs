@@ -1,5 +1,14 @@
 // @generated automatically by Diesel CLI.
 
+diesel::table! {
+	anmeldung_entwurf (token) {
+		token -> Text,
+		eltern_mail -> Text,
+		daten -> Text,
+		geaendert -> Timestamptz,
+	}
+}
+
 diesel::table! {
 	betreuer (id) {
 		id -> Int4,
@@ -31,6 +40,46 @@ diesel::table! {
 		juleica_gueltig_bis -> Nullable<Date>,
 		signup_token -> Nullable<Text>,
 		signup_token_time -> Nullable<Timestamptz>,
+		geloescht -> Nullable<Timestamptz>,
+		version -> Int4,
+	}
+}
+
+diesel::table! {
+	betreuer_teilnahme (betreuer, lager) {
+		betreuer -> Int4,
+		lager -> Int4,
+	}
+}
+
+diesel::table! {
+	datei (id) {
+		id -> Int4,
+		name -> Text,
+		titel -> Text,
+		rolle -> Nullable<Text>,
+	}
+}
+
+diesel::table! {
+	datei_version (id) {
+		id -> Int4,
+		datei -> Int4,
+		dateiname -> Text,
+		mime -> Text,
+		inhalt -> Bytea,
+		hochgeladen -> Timestamptz,
+	}
+}
+
+diesel::table! {
+	dokument_version (id) {
+		id -> Int4,
+		dokument -> Text,
+		version -> Text,
+		datei -> Text,
+		hash -> Text,
+		gueltig_ab -> Date,
 	}
 }
 
@@ -38,6 +87,7 @@ diesel::table! {
 	erwischt_game (id) {
 		id -> Int4,
 		created -> Timestamptz,
+		lager -> Nullable<Int4>,
 	}
 }
 
@@ -53,10 +103,62 @@ diesel::table! {
 }
 
 diesel::table! {
-	rate_limiting (ip_addr) {
+	lager (id) {
+		id -> Int4,
+		name -> Text,
+		beginn -> Date,
+		ende -> Date,
+		kapazitaet -> Int4,
+		preis -> Int4,
+		aktuell -> Bool,
+	}
+}
+
+diesel::table! {
+	mail_vorlage (name) {
+		name -> Text,
+		inhalt -> Text,
+		geaendert -> Timestamptz,
+	}
+}
+
+diesel::table! {
+	postausgang (id) {
+		id -> Int4,
+		absender -> Text,
+		empfaenger -> Text,
+		an -> Text,
+		betreff -> Text,
+		nachricht -> Bytea,
+		erstellt -> Timestamptz,
+		versuche -> Int4,
+		naechster_versuch -> Timestamptz,
+		fehler -> Nullable<Text>,
+		gesendet -> Nullable<Timestamptz>,
+		aufgegeben -> Bool,
+		message_id -> Nullable<Text>,
+	}
+}
+
+diesel::table! {
+	protokoll (id) {
+		id -> Int4,
+		zeit -> Timestamptz,
+		benutzer -> Nullable<Int4>,
+		oidc_subjekt -> Nullable<Text>,
+		objekt -> Text,
+		objekt_id -> Nullable<Text>,
+		aktion -> Text,
+		aenderung -> Text,
+	}
+}
+
+diesel::table! {
+	rate_limiting (ip_addr, bereich) {
 		ip_addr -> Inet,
 		counter -> Int4,
 		first_count -> Timestamp,
+		bereich -> Text,
 	}
 }
 
@@ -67,6 +169,65 @@ diesel::table! {
 	}
 }
 
+diesel::table! {
+	rueckkehrer (id) {
+		id -> Int4,
+		eltern_mail -> Text,
+		daten -> Text,
+		token -> Nullable<Text>,
+		token_zeit -> Nullable<Timestamptz>,
+	}
+}
+
+diesel::table! {
+	rundmail (id) {
+		id -> Int4,
+		betreff -> Text,
+		text -> Text,
+		zielgruppe -> Text,
+		erstellt -> Timestamptz,
+	}
+}
+
+diesel::table! {
+	rundmail_empfaenger (id) {
+		id -> Int4,
+		rundmail -> Int4,
+		name -> Text,
+		mail -> Text,
+		fehler -> Nullable<Text>,
+		postausgang -> Nullable<Int4>,
+	}
+}
+
+diesel::table! {
+	statistik (id) {
+		id -> Int4,
+		lager -> Int4,
+		erstellt -> Timestamptz,
+		daten -> Text,
+	}
+}
+
+diesel::table! {
+	stornierung (id) {
+		id -> Int4,
+		vorname -> Text,
+		nachname -> Text,
+		geburtsdatum -> Date,
+		eltern_name -> Text,
+		eltern_mail -> Text,
+		anmeldedatum -> Timestamptz,
+		stornodatum -> Timestamptz,
+		bezahlt -> Bool,
+		erstattung_prozent -> Int4,
+		erstattung -> Int4,
+		erstattet -> Bool,
+		lager -> Nullable<Int4>,
+		geloescht -> Nullable<Timestamptz>,
+	}
+}
+
 diesel::table! {
 	teilnehmer (id) {
 		id -> Int4,
@@ -96,6 +257,19 @@ diesel::table! {
 		land -> Text,
 		krankheiten -> Text,
 		eigenanreise -> Bool,
+		daten_behalten -> Bool,
+		sprache -> Text,
+		geloescht -> Nullable<Timestamptz>,
+		version -> Int4,
+		lager -> Nullable<Int4>,
+	}
+}
+
+diesel::table! {
+	unzustellbar (mail) {
+		mail -> Text,
+		grund -> Text,
+		zeit -> Timestamptz,
 	}
 }
 
@@ -108,15 +282,76 @@ diesel::table! {
 	}
 }
 
+diesel::table! {
+	zielgruppe (id) {
+		id -> Int4,
+		name -> Text,
+		betreuer -> Bool,
+		bezahlt -> Nullable<Bool>,
+		eigenanreise -> Nullable<Bool>,
+		alter_von -> Nullable<Int4>,
+		alter_bis -> Nullable<Int4>,
+	}
+}
+
+diesel::table! {
+	zusammenfassung (id) {
+		id -> Int4,
+		art -> Text,
+		text -> Text,
+		zeit -> Timestamptz,
+	}
+}
+
+diesel::table! {
+	zustimmung (id) {
+		id -> Int4,
+		teilnehmer -> Nullable<Int4>,
+		betreuer -> Nullable<Int4>,
+		dokument_version -> Int4,
+		zeit -> Timestamptz,
+	}
+}
+
+diesel::joinable!(betreuer_teilnahme -> betreuer (betreuer));
+diesel::joinable!(betreuer_teilnahme -> lager (lager));
+diesel::joinable!(datei_version -> datei (datei));
+diesel::joinable!(erwischt_game -> lager (lager));
 diesel::joinable!(erwischt_member -> erwischt_game (game));
 diesel::joinable!(roles -> users (user_id));
+diesel::joinable!(rundmail_empfaenger -> postausgang (postausgang));
+diesel::joinable!(rundmail_empfaenger -> rundmail (rundmail));
+diesel::joinable!(statistik -> lager (lager));
+diesel::joinable!(stornierung -> lager (lager));
+diesel::joinable!(teilnehmer -> lager (lager));
+diesel::joinable!(zustimmung -> betreuer (betreuer));
+diesel::joinable!(zustimmung -> dokument_version (dokument_version));
+diesel::joinable!(zustimmung -> teilnehmer (teilnehmer));
 
 diesel::allow_tables_to_appear_in_same_query!(
+	anmeldung_entwurf,
 	betreuer,
+	betreuer_teilnahme,
+	datei,
+	datei_version,
+	dokument_version,
 	erwischt_game,
 	erwischt_member,
+	lager,
+	mail_vorlage,
+	postausgang,
+	protokoll,
 	rate_limiting,
 	roles,
+	rueckkehrer,
+	rundmail,
+	rundmail_empfaenger,
+	statistik,
+	stornierung,
 	teilnehmer,
+	unzustellbar,
 	users,
+	zielgruppe,
+	zusammenfassung,
+	zustimmung,
 );
@@ -0,0 +1,123 @@
+//! Daily summary mail for the additional mail receivers.
+//!
+//! If `additional_mail_mode` is `digest`, the additional receivers are not in bcc of every mail.
+//! Instead, signups, payments, cancellations, supervisor pre-signups and mails that could not be
+//! sent are collected in the `zusammenfassung` table and sent once per day.
+
+use std::convert::TryInto;
+use std::sync::Arc;
+
+use anyhow::Result;
+use diesel::prelude::*;
+use diesel_async::RunQueryDsl;
+use minijinja::context;
+use time::{Duration, OffsetDateTime, Time};
+use tracing::{error, info};
+
+use crate::config::AdditionalMailMode;
+use crate::{State, db};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Art {
+	Anmeldung,
+	Bezahlt,
+	Abmeldung,
+	Betreuer,
+	Fehler,
+}
+
+impl Art {
+	fn name(self) -> &'static str {
+		match self {
+			Self::Anmeldung => "anmeldung",
+			Self::Bezahlt => "bezahlt",
+			Self::Abmeldung => "abmeldung",
+			Self::Betreuer => "betreuer",
+			Self::Fehler => "fehler",
+		}
+	}
+}
+
+/// Add an event to the next summary.
+pub async fn add(db: &db::Database, art: Art, text: String) -> Result<()> {
+	use db::schema::zusammenfassung;
+
+	diesel::insert_into(zusammenfassung::table)
+		.values((zusammenfassung::art.eq(art.name()), zusammenfassung::text.eq(text)))
+		.execute(&mut db.get().await?)
+		.await?;
+	Ok(())
+}
+
+/// Send the summary to all additional receivers, if something happened since the last one.
+async fn send(state: &State) -> Result<()> {
+	use db::schema::zusammenfassung;
+
+	let entries = zusammenfassung::table
+		.order(zusammenfassung::id)
+		.select((zusammenfassung::id, zusammenfassung::art, zusammenfassung::text))
+		.load::<(i32, String, String)>(&mut state.db.get().await?)
+		.await?;
+	let Some((last, _, _)) = entries.last() else {
+		return Ok(());
+	};
+	let of = |art: Art| {
+		entries.iter().filter(|(_, a, _)| a == art.name()).map(|(_, _, t)| t).collect::<Vec<_>>()
+	};
+	for receiver in &state.config.additional_mail_receivers {
+		state
+			.mail
+			.send_digest(receiver, context! {
+				receiver,
+				anmeldungen => of(Art::Anmeldung),
+				zahlungen => of(Art::Bezahlt),
+				abmeldungen => of(Art::Abmeldung),
+				betreuer => of(Art::Betreuer),
+				fehler => of(Art::Fehler),
+			})
+			.await?;
+	}
+
+	diesel::delete(zusammenfassung::table.filter(zusammenfassung::id.le(last)))
+		.execute(&mut state.db.get().await?)
+		.await?;
+	info!(count = entries.len(), "Sent summary mail");
+	Ok(())
+}
+
+/// Time until the next summary at `hour` o'clock UTC.
+fn until_next(now: OffsetDateTime, hour: u8) -> std::time::Duration {
+	let mut next = now.replace_time(Time::from_hms(hour, 0, 0).unwrap());
+	if next <= now {
+		next += Duration::days(1);
+	}
+	(next - now).try_into().unwrap_or_default()
+}
+
+/// Send the summary once per day, if the digest mode is configured.
+pub async fn run(state: Arc<State>) {
+	let AdditionalMailMode::Digest { hour } = state.config.additional_mail_mode else {
+		return;
+	};
+	info!(hour, "Sending daily summary mails");
+	loop {
+		tokio::time::sleep(until_next(OffsetDateTime::now_utc(), hour)).await;
+		if let Err(error) = send(&state).await {
+			error!(%error, "Failed to send summary mail");
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use time::macros::datetime;
+
+	use super::*;
+
+	#[test]
+	fn next_summary() {
+		let now = datetime!(2026-05-01 05:30 UTC);
+		assert_eq!(until_next(now, 6), std::time::Duration::from_secs(30 * 60));
+		assert_eq!(until_next(now, 5), std::time::Duration::from_secs(((24 * 60) - 30) * 60));
+	}
+}
@@ -0,0 +1,285 @@
+//! DKIM signatures for outgoing mails.
+//!
+//! Mails are signed with `relaxed/relaxed` canonicalization before they are stored in the outbox.
+//! The `dkim` subcommand prints the DNS record for the key and checks a signed test mail with
+//! the small verifier in this module.
+
+use std::convert::TryInto;
+
+use anyhow::{Context, Result, bail};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use ed25519_dalek::Verifier;
+use lettre::message::dkim::{
+	DkimCanonicalization, DkimCanonicalizationType, DkimConfig, DkimSigningAlgorithm,
+	DkimSigningKey,
+};
+use lettre::message::header::HeaderName;
+use rsa::pkcs1::DecodeRsaPrivateKey;
+use rsa::pkcs8::EncodePublicKey;
+use rsa::{Pkcs1v15Sign, RsaPrivateKey, RsaPublicKey};
+use sha2::{Digest, Sha256};
+
+use crate::config::{Config, DkimAlgorithm, DkimSettings};
+
+/// Signed headers, missing headers are signed as absent.
+const SIGNED_HEADERS: &[&str] =
+	&["From", "To", "Cc", "Subject", "Date", "Message-ID", "MIME-Version", "Content-Type"];
+
+pub enum PublicKey {
+	Rsa(RsaPublicKey),
+	Ed25519(ed25519_dalek::VerifyingKey),
+}
+
+/// The key and domain used to sign mails.
+pub struct Dkim {
+	pub domain: String,
+	pub selector: String,
+	pub public_key: PublicKey,
+	config: DkimConfig,
+}
+
+impl Dkim {
+	/// Load the private key from the configuration.
+	pub fn new(config: &Config, settings: &DkimSettings) -> Result<Self> {
+		let domain = match &settings.domain {
+			Some(d) => d.clone(),
+			None => config
+				.sender_mail
+				.address
+				.rsplit_once('@')
+				.map(|(_, d)| d.to_string())
+				.context("Sender mail address has no domain")?,
+		};
+		let key = std::fs::read_to_string(&settings.private_key).with_context(|| {
+			format!("Failed to read dkim key {}", settings.private_key.display())
+		})?;
+		let key = key.trim();
+
+		let (algorithm, public_key) = match settings.algorithm {
+			DkimAlgorithm::Rsa => (
+				DkimSigningAlgorithm::Rsa,
+				PublicKey::Rsa(RsaPrivateKey::from_pkcs1_pem(key)?.to_public_key()),
+			),
+			DkimAlgorithm::Ed25519 => {
+				let bytes: [u8; 32] = BASE64
+					.decode(key)?
+					.try_into()
+					.map_err(|_| anyhow::anyhow!("Ed25519 key must have 32 bytes"))?;
+				(
+					DkimSigningAlgorithm::Ed25519,
+					PublicKey::Ed25519(
+						ed25519_dalek::SigningKey::from_bytes(&bytes).verifying_key(),
+					),
+				)
+			}
+		};
+		let signing_key = DkimSigningKey::new(key, algorithm)
+			.map_err(|e| anyhow::anyhow!("Invalid dkim key: {e}"))?;
+		let config = DkimConfig::new(
+			settings.selector.clone(),
+			domain.clone(),
+			signing_key,
+			SIGNED_HEADERS.iter().map(|h| HeaderName::new_from_ascii_str(h)).collect(),
+			DkimCanonicalization {
+				header: DkimCanonicalizationType::Relaxed,
+				body: DkimCanonicalizationType::Relaxed,
+			},
+		);
+		Ok(Self { domain, selector: settings.selector.clone(), public_key, config })
+	}
+
+	pub fn sign(&self, email: &mut lettre::Message) {
+		lettre::message::dkim::dkim_sign(email, &self.config);
+	}
+
+	/// Name of the DNS TXT record.
+	pub fn record_name(&self) -> String { format!("{}._domainkey.{}", self.selector, self.domain) }
+
+	/// Content of the DNS TXT record.
+	pub fn record_value(&self) -> Result<String> {
+		let (k, p) = match &self.public_key {
+			PublicKey::Rsa(key) => ("rsa", BASE64.encode(key.to_public_key_der()?.as_bytes())),
+			PublicKey::Ed25519(key) => ("ed25519", BASE64.encode(key.to_bytes())),
+		};
+		Ok(format!("v=DKIM1; k={k}; p={p}"))
+	}
+}
+
+/// Split a TXT record into strings of at most 255 bytes, as needed for long rsa keys.
+pub fn quote_record(value: &str) -> String {
+	value
+		.as_bytes()
+		.chunks(255)
+		.map(|c| format!("\"{}\"", String::from_utf8_lossy(c)))
+		.collect::<Vec<_>>()
+		.join(" ")
+}
+
+/// Relaxed body canonicalization from RFC 6376.
+fn canonicalize_body(body: &[u8]) -> Vec<u8> {
+	let mut lines = body
+		.split(|c| *c == b'\n')
+		.map(|line| {
+			let line = line.strip_suffix(b"\r").unwrap_or(line);
+			let mut res = Vec::with_capacity(line.len());
+			for &c in line {
+				if c == b' ' || c == b'\t' {
+					if res.last() != Some(&b' ') {
+						res.push(b' ');
+					}
+				} else {
+					res.push(c);
+				}
+			}
+			while res.last() == Some(&b' ') {
+				res.pop();
+			}
+			res
+		})
+		.collect::<Vec<_>>();
+	while lines.last().is_some_and(|l| l.is_empty()) {
+		lines.pop();
+	}
+	let mut res = Vec::new();
+	for line in lines {
+		res.extend_from_slice(&line);
+		res.extend_from_slice(b"\r\n");
+	}
+	res
+}
+
+/// Relaxed header canonicalization from RFC 6376, without the line break.
+fn canonicalize_header(name: &str, value: &str) -> String {
+	let value = value.replace("\r\n", "").split_whitespace().collect::<Vec<_>>().join(" ");
+	format!("{}:{}", name.trim().to_ascii_lowercase(), value)
+}
+
+/// Check the DKIM signature of a formatted mail.
+pub fn verify(message: &[u8], key: &PublicKey) -> Result<()> {
+	let split = message.windows(4).position(|w| w == b"\r\n\r\n").context("Mail has no body")?;
+	let header_part = String::from_utf8_lossy(&message[..split]);
+	let body = &message[split + 4..];
+
+	// Unfold headers into (name, value)
+	let mut headers: Vec<(String, String)> = Vec::new();
+	for line in header_part.split("\r\n") {
+		if line.starts_with([' ', '\t']) {
+			if let Some((_, value)) = headers.last_mut() {
+				value.push_str("\r\n");
+				value.push_str(line);
+			}
+		} else if let Some((name, value)) = line.split_once(':') {
+			headers.push((name.to_string(), value.to_string()));
+		}
+	}
+
+	let (_, signature) = headers
+		.iter()
+		.find(|(n, _)| n.eq_ignore_ascii_case("DKIM-Signature"))
+		.context("Mail has no DKIM-Signature")?;
+	let signature = canonicalize_header("DKIM-Signature", signature);
+	let value = signature.split_once(':').unwrap().1;
+	let tag = |name: &str| {
+		value.split(';').find_map(|t| {
+			let (n, v) = t.split_once('=')?;
+			(n.trim() == name).then(|| v.split_whitespace().collect::<String>())
+		})
+	};
+
+	let c = tag("c").unwrap_or_default();
+	if c != "relaxed/relaxed" {
+		bail!("Only relaxed/relaxed canonicalization is supported, not '{c}'");
+	}
+	let expected_algorithm = match key {
+		PublicKey::Rsa(_) => "rsa-sha256",
+		PublicKey::Ed25519(_) => "ed25519-sha256",
+	};
+	if tag("a").as_deref() != Some(expected_algorithm) {
+		bail!("Signature algorithm does not match the key");
+	}
+
+	let body_hash = BASE64.encode(Sha256::digest(canonicalize_body(body)));
+	if tag("bh").as_deref() != Some(body_hash.as_str()) {
+		bail!("Body hash does not match, the body was changed");
+	}
+
+	// Signed headers followed by the signature header without the signature
+	let mut data = String::new();
+	for name in tag("h").context("Signature has no h= tag")?.split(':') {
+		if let Some((n, v)) = headers.iter().rev().find(|(n, _)| n.eq_ignore_ascii_case(name)) {
+			data.push_str(&canonicalize_header(n, v));
+			data.push_str("\r\n");
+		}
+	}
+	let without_b = value
+		.split(';')
+		.map(|t| match t.split_once('=') {
+			Some((n, _)) if n.trim() == "b" => format!("{n}="),
+			_ => t.to_string(),
+		})
+		.collect::<Vec<_>>()
+		.join(";");
+	data.push_str(&format!("dkim-signature:{without_b}"));
+	let hash = Sha256::digest(data.as_bytes());
+
+	let b = BASE64.decode(tag("b").context("Signature has no b= tag")?)?;
+	match key {
+		PublicKey::Rsa(key) => key.verify(Pkcs1v15Sign::new::<Sha256>(), &hash, &b)?,
+		PublicKey::Ed25519(key) => key.verify(&hash, &ed25519_dalek::Signature::from_slice(&b)?)?,
+	}
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn sign_and_verify() {
+		let dir = std::env::temp_dir().join(format!("zeltlager-dkim-{}", std::process::id()));
+		std::fs::create_dir_all(&dir).unwrap();
+		let key_file = dir.join("dkim.key");
+		std::fs::write(&key_file, BASE64.encode([7u8; 32])).unwrap();
+
+		let config: Config = toml::from_str(
+			r#"
+			database = "postgres://localhost/zeltlager"
+			max_members = 70
+			error_message = "Fehler"
+			[sender_mail]
+			address = "zeltlager@example.com"
+			"#,
+		)
+		.unwrap();
+		let dkim = Dkim::new(&config, &DkimSettings {
+			domain: None,
+			selector: "mail".into(),
+			private_key: key_file,
+			algorithm: DkimAlgorithm::Ed25519,
+		})
+		.unwrap();
+		std::fs::remove_dir_all(&dir).unwrap();
+		assert_eq!(dkim.record_name(), "mail._domainkey.example.com");
+		assert!(dkim.record_value().unwrap().starts_with("v=DKIM1; k=ed25519; p="));
+
+		let mut email = lettre::Message::builder()
+			.from("Zeltlager <zeltlager@example.com>".parse().unwrap())
+			.to("Erika Mustermann <erika@example.com>".parse().unwrap())
+			.subject("Zahlungsbestätigung für das Zeltlager")
+			.body(String::from("Sehr geehrte Frau Mustermann,\n\nvielen Dank!  \n\n"))
+			.unwrap();
+		dkim.sign(&mut email);
+		let formatted = email.formatted();
+		verify(&formatted, &dkim.public_key).unwrap();
+
+		let changed = String::from_utf8(formatted).unwrap().replace("Dank", "Geld");
+		assert!(verify(changed.as_bytes(), &dkim.public_key).is_err());
+	}
+
+	#[test]
+	fn split_long_records() {
+		let value = "a".repeat(300);
+		assert_eq!(quote_record(&value), format!("\"{}\" \"{}\"", "a".repeat(255), "a".repeat(45)));
+	}
+}
@@ -0,0 +1,273 @@
+//! Signup forms that are saved to be continued later.
+//!
+//! A draft is stored unvalidated behind a random token and the parents get a link to continue
+//! the signup. Drafts are no signups, they do not count towards `max_members` and are deleted
+//! after `draft_expiry_days`.
+//!
+//! Saving a draft sends a mail without a login, so these mails are rate limited per IP address
+//! separately from the login and an address gets at most one new link per
+//! [`MAIL_COOLDOWN`].
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use axum::extract::ConnectInfo;
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::{Form, Json, extract};
+use diesel::prelude::*;
+use diesel_async::RunQueryDsl;
+use rand::RngExt;
+use serde::{Deserialize, Serialize};
+use time::{Duration, OffsetDateTime, PrimitiveDateTime};
+use tracing::{error, info, warn};
+
+use crate::db::models::check_email;
+use crate::i18n::{self, Sprache};
+use crate::{ExtractState, State, db};
+
+type DbResult<T> = anyhow::Result<T>;
+
+/// Minimum time between two mails with a new link to the same address.
+const MAIL_COOLDOWN: Duration = Duration::minutes(10);
+/// Check for expired drafts this often.
+const PURGE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60 * 60);
+
+/// Result of saving a draft.
+enum Saved {
+	/// Contains the token and if a mail with the link should be sent.
+	Ok(String, bool),
+	/// A link was sent to the address shortly before.
+	TooEarly,
+	/// The client sent too many mails.
+	RateLimited,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub(crate) struct DraftRequest {
+	token: String,
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub(crate) struct DraftResult {
+	error: Option<db::FormError>,
+	token: Option<String>,
+}
+
+/// Drafts that were last changed before this time are expired.
+pub fn expired_before(state: &State) -> PrimitiveDateTime {
+	ago(Duration::days(state.config.draft_expiry_days))
+}
+
+fn ago(duration: Duration) -> PrimitiveDateTime {
+	let since = OffsetDateTime::now_utc() - duration;
+	PrimitiveDateTime::new(since.date(), since.time())
+}
+
+/// Delete expired drafts.
+async fn purge(state: &State) -> DbResult<()> {
+	use db::schema::anmeldung_entwurf;
+
+	let deleted = diesel::delete(
+		anmeldung_entwurf::table.filter(anmeldung_entwurf::geaendert.le(expired_before(state))),
+	)
+	.execute(&mut state.db.get().await?)
+	.await?;
+	if deleted > 0 {
+		info!(deleted, "Deleted expired signup drafts");
+	}
+	Ok(())
+}
+
+/// Periodically delete expired drafts.
+pub async fn run(state: Arc<State>) {
+	loop {
+		if let Err(error) = purge(&state).await {
+			error!(%error, "Failed to delete expired signup drafts");
+		}
+		tokio::time::sleep(PURGE_INTERVAL).await;
+	}
+}
+
+/// Save a partially filled signup form and send a link to continue it.
+///
+/// If the form contains the token of an existing draft, the draft is updated. The mail is only
+/// sent again if the mail address changed.
+pub async fn save_draft(
+	extract::State(state): ExtractState, headers: HeaderMap,
+	ConnectInfo(addr): ConnectInfo<SocketAddr>, Form(mut body): Form<HashMap<String, String>>,
+) -> (StatusCode, Json<DraftResult>) {
+	let sprache = Sprache::from_code(body.get("sprache").map(String::as_str).unwrap_or_default());
+	let err = |status, mut error: db::FormError| {
+		error.message = i18n::translate(sprache, &error.message);
+		(status, Json(DraftResult { error: Some(error), ..Default::default() }))
+	};
+
+	let old_token = body.remove("entwurf").filter(|t| !t.is_empty());
+	let mail = body.get("eltern_mail").map(|m| m.trim().to_string()).unwrap_or_default();
+	if mail.is_empty() {
+		return err(StatusCode::BAD_REQUEST, db::FormError {
+			field: Some("eltern_mail".into()),
+			message: "Bitte geben Sie eine E-Mail-Adresse an, an die wir den Link zum Fortsetzen \
+			          schicken können."
+				.into(),
+		});
+	}
+	if let Err(error) = check_email(&mail, "eltern_mail") {
+		return err(StatusCode::BAD_REQUEST, error);
+	}
+	let eltern_name = body.get("eltern_name").map(|n| n.trim().to_string()).unwrap_or_default();
+
+	// Only keep fields of the signup form and the language
+	let fields = db::fields::member();
+	body.retain(|k, _| k == "sprache" || fields.iter().any(|f| f.id == k));
+	let data = serde_json::to_string(&body).unwrap();
+
+	let new_token = {
+		let mut rng = rand::rng();
+		(0..24).map(|_| rng.sample(rand::distr::Alphanumeric) as char).collect::<String>()
+	};
+
+	let (token, send_mail) = match async {
+		use db::schema::anmeldung_entwurf;
+		use db::schema::anmeldung_entwurf::columns::*;
+
+		let mut connection = state.db.get().await?;
+		let expired = expired_before(&state);
+
+		let old_mail = match &old_token {
+			Some(old_token) => anmeldung_entwurf::table
+				.filter(token.eq(old_token).and(geaendert.gt(expired)))
+				.select(eltern_mail)
+				.first::<String>(&mut connection)
+				.await
+				.optional()?,
+			None => None,
+		};
+		if old_mail.as_ref() != Some(&mail) {
+			// A new link is sent, other drafts for this address must be older than the cooldown
+			let recent = anmeldung_entwurf::table
+				.filter(eltern_mail.eq(&mail).and(geaendert.gt(ago(MAIL_COOLDOWN))))
+				.count()
+				.get_result::<i64>(&mut connection)
+				.await?;
+			if recent > 0 {
+				return DbResult::Ok(Saved::TooEarly);
+			}
+			if let Err(error) =
+				crate::auth::rate_limit(&state, &headers, addr, crate::auth::MAIL).await
+			{
+				warn!(%error, "Rate limit for signup drafts");
+				return Ok(Saved::RateLimited);
+			}
+		}
+
+		if let (Some(old_token), Some(old_mail)) = (old_token, old_mail) {
+			diesel::update(anmeldung_entwurf::table.filter(token.eq(&old_token)))
+				.set((eltern_mail.eq(&mail), daten.eq(&data), geaendert.eq(diesel::dsl::now)))
+				.execute(&mut connection)
+				.await?;
+			return Ok(Saved::Ok(old_token, old_mail != mail));
+		}
+
+		diesel::insert_into(anmeldung_entwurf::table)
+			.values((token.eq(&new_token), eltern_mail.eq(&mail), daten.eq(&data)))
+			.execute(&mut connection)
+			.await?;
+		Ok(Saved::Ok(new_token, true))
+	}
+	.await
+	{
+		Err(error) => {
+			error!(%error, "Failed to save signup draft");
+			return err(
+				StatusCode::INTERNAL_SERVER_ERROR,
+				format!("Es ist ein Datenbank-Fehler aufgetreten.\n{}", state.config.error_message)
+					.into(),
+			);
+		}
+		Ok(Saved::TooEarly) => {
+			warn!(mail, "Signup draft link was sent shortly before");
+			return err(
+				StatusCode::TOO_MANY_REQUESTS,
+				"An diese E-Mail-Adresse wurde gerade erst ein Link geschickt. Bitte versuchen \
+				 Sie es in ein paar Minuten erneut."
+					.into(),
+			);
+		}
+		Ok(Saved::RateLimited) => {
+			return err(
+				StatusCode::TOO_MANY_REQUESTS,
+				"Zu viele Anfragen. Bitte versuchen Sie es später erneut.".into(),
+			);
+		}
+		Ok(Saved::Ok(token, send_mail)) => (token, send_mail),
+	};
+
+	if send_mail {
+		let link = state.config.link(&format!("/anmeldung?entwurf={token}"));
+		if let Err(error) = state.mail.send_signup_draft(sprache, &eltern_name, &mail, &link).await
+		{
+			error!(mail, %error, "Error sending e-mail");
+			return err(
+				StatusCode::INTERNAL_SERVER_ERROR,
+				format!(
+					"Ihre Daten wurden gespeichert.\nEs ist leider ein Fehler beim E-Mail senden \
+					 aufgetreten.\n{}",
+					state.config.error_message
+				)
+				.into(),
+			);
+		}
+	}
+
+	(StatusCode::OK, Json(DraftResult { error: None, token: Some(token) }))
+}
+
+/// Get the saved form content of a draft.
+pub async fn load_draft(
+	extract::State(state): ExtractState, Json(request): Json<DraftRequest>,
+) -> Response {
+	let err = |status, msg: String| {
+		(status, Json(DraftResult { error: Some(msg.into()), ..Default::default() }))
+			.into_response()
+	};
+
+	match async {
+		use db::schema::anmeldung_entwurf;
+		use db::schema::anmeldung_entwurf::columns::*;
+
+		let data = anmeldung_entwurf::table
+			.filter(token.eq(&request.token).and(geaendert.gt(expired_before(&state))))
+			.select(daten)
+			.first::<String>(&mut state.db.get().await?)
+			.await
+			.optional()?;
+		DbResult::Ok(data)
+	}
+	.await
+	{
+		Err(error) => {
+			error!(%error, "Failed to get signup draft");
+			err(
+				StatusCode::INTERNAL_SERVER_ERROR,
+				format!("Es ist ein Datenbank-Fehler aufgetreten.\n{}", state.config.error_message),
+			)
+		}
+		Ok(None) => {
+			warn!("Failed to find signup draft");
+			err(
+				StatusCode::BAD_REQUEST,
+				"Die gespeicherte Anmeldung ist abgelaufen oder wurde bereits abgeschickt.".into(),
+			)
+		}
+		Ok(Some(data)) => match serde_json::from_str::<HashMap<String, String>>(&data) {
+			Ok(data) => Json(data).into_response(),
+			Err(error) => {
+				error!(%error, "Failed to parse signup draft");
+				err(StatusCode::INTERNAL_SERVER_ERROR, state.config.error_message.clone())
+			}
+		},
+	}
+}
@@ -2,7 +2,7 @@
 
 use anyhow::{Error, bail};
 use axum::{
-	Json,
+	Extension, Json,
 	extract::{self, Path},
 	http::StatusCode,
 	response::IntoResponse,
@@ -11,9 +11,11 @@ use diesel::prelude::*;
 use diesel_async::{AsyncConnection, RunQueryDsl, scoped_futures::ScopedFutureExt};
 use rand::seq::SliceRandom;
 use serde::Deserialize;
+use serde_json::{Value, json};
 use time::{OffsetDateTime, PrimitiveDateTime};
 use tracing::error;
 
+use crate::audit::Audit;
 use crate::{ExtractState, WebResult, db};
 
 type DbResult<T> = anyhow::Result<T>;
@@ -45,6 +47,7 @@ pub async fn get_games(
 
 		DbResult::Ok(
 			erwischt_game::table
+				.filter(erwischt_game::columns::lager.eq(crate::season::current().id))
 				.order(erwischt_game::columns::created)
 				.get_results::<db::models::ErwischtGame>(&mut state.db.get().await?)
 				.await?,
@@ -80,7 +83,9 @@ pub async fn get_game(
 	}
 }
 
-pub async fn create_game(extract::State(state): ExtractState) -> WebResult<Json<i32>> {
+pub async fn create_game(
+	extract::State(state): ExtractState, Extension(audit): Extension<Audit>,
+) -> WebResult<Json<i32>> {
 	match async {
 		use db::schema::betreuer;
 		use db::schema::erwischt_game;
@@ -89,14 +94,20 @@ pub async fn create_game(extract::State(state): ExtractState) -> WebResult<Json<
 
 		let mut connection = state.db.get().await?;
 
+		let lager = crate::season::current();
 		let new_game: db::models::ErwischtGame = diesel::insert_into(erwischt_game::table)
-			.default_values()
+			.values(erwischt_game::columns::lager.eq(lager.id))
 			.get_result(&mut connection)
 			.await?;
 
 		let mut member = Vec::new();
 
 		let teilnehmer_member = teilnehmer::table
+			.filter(
+				teilnehmer::columns::lager
+					.eq(lager.id)
+					.and(teilnehmer::columns::geloescht.is_null()),
+			)
 			.select((teilnehmer::columns::vorname, teilnehmer::columns::nachname))
 			.get_results::<(String, String)>(&mut connection)
 			.await?;
@@ -111,9 +122,10 @@ pub async fn create_game(extract::State(state): ExtractState) -> WebResult<Json<
 			});
 		}
 
+		let active = crate::season::active_supervisors(&mut connection, &lager).await?;
 		let supervisor_member = betreuer::table
 			.filter(
-				betreuer::columns::anmeldedatum.ge(crate::admin::betreuer_signup_date_last_year()),
+				betreuer::columns::id.eq_any(active).and(betreuer::columns::geloescht.is_null()),
 			)
 			.select((betreuer::columns::vorname, betreuer::columns::nachname))
 			.get_results::<(String, String)>(&mut connection)
@@ -142,6 +154,16 @@ pub async fn create_game(extract::State(state): ExtractState) -> WebResult<Json<
 		member[len - 1].target = member[0].id;
 
 		diesel::insert_into(erwischt_member::table).values(member).execute(&mut connection).await?;
+		audit
+			.log(
+				&mut connection,
+				"erwischt_game",
+				Some(new_game.id.to_string()),
+				"erstellt",
+				Value::Null,
+				json!({ "mitspieler": len }),
+			)
+			.await?;
 
 		Ok(new_game.id)
 	}
@@ -153,7 +175,8 @@ pub async fn create_game(extract::State(state): ExtractState) -> WebResult<Json<
 }
 
 pub async fn delete_game(
-	extract::State(state): ExtractState, Path(game_id): Path<i32>,
+	extract::State(state): ExtractState, Extension(audit): Extension<Audit>,
+	Path(game_id): Path<i32>,
 ) -> WebResult<&'static str> {
 	match async {
 		use db::schema::erwischt_game;
@@ -162,7 +185,7 @@ pub async fn delete_game(
 
 		let mut connection = state.db.get().await?;
 
-		diesel::delete(erwischt_member::table)
+		let members = diesel::delete(erwischt_member::table)
 			.filter(game.eq(game_id))
 			.execute(&mut connection)
 			.await?;
@@ -175,7 +198,16 @@ pub async fn delete_game(
 			bail!("Game not found");
 		}
 
-		Ok(())
+		audit
+			.log(
+				&mut connection,
+				"erwischt_game",
+				Some(game_id.to_string()),
+				"geloescht",
+				json!({ "mitspieler": members }),
+				Value::Null,
+			)
+			.await
 	}
 	.await
 	{
@@ -185,25 +217,43 @@ pub async fn delete_game(
 }
 
 pub(crate) async fn catch(
-	extract::State(state): ExtractState, Json(data): Json<CatchData>,
+	extract::State(state): ExtractState, Extension(audit): Extension<Audit>,
+	Json(data): Json<CatchData>,
 ) -> WebResult<&'static str> {
 	match async {
 		use db::schema::erwischt_member;
 		use db::schema::erwischt_member::columns::*;
 
+		let mut connection = state.db.get().await?;
+		let Some(old_catcher) = erwischt_member::table
+			.filter(game.eq(data.game).and(id.eq(data.member)))
+			.select(catcher)
+			.first::<Option<i32>>(&mut connection)
+			.await
+			.optional()?
+		else {
+			bail!("Member not found");
+		};
+
 		let now = OffsetDateTime::now_utc();
-		let r = diesel::update(erwischt_member::table)
+		diesel::update(erwischt_member::table)
 			.filter(game.eq(data.game).and(id.eq(data.member)))
 			.set((
 				catcher.eq(data.catcher),
 				last_change.eq(Some(PrimitiveDateTime::new(now.date(), now.time()))),
 			))
-			.execute(&mut state.db.get().await?)
+			.execute(&mut connection)
 			.await?;
-		if r == 0 {
-			bail!("Member not found");
-		}
-		Ok(())
+		audit
+			.log(
+				&mut connection,
+				"erwischt_member",
+				Some(format!("{}/{}", data.game, data.member)),
+				"erwischt",
+				json!({ "catcher": old_catcher }),
+				json!({ "catcher": data.catcher }),
+			)
+			.await
 	}
 	.await
 	{
@@ -213,7 +263,8 @@ pub(crate) async fn catch(
 }
 
 pub(crate) async fn insert(
-	extract::State(state): ExtractState, Json(data): Json<InsertData>,
+	extract::State(state): ExtractState, Extension(audit): Extension<Audit>,
+	Json(data): Json<InsertData>,
 ) -> WebResult<&'static str> {
 	match async {
 		use diesel::dsl::max;
@@ -223,11 +274,12 @@ pub(crate) async fn insert(
 		use db::schema::erwischt_member::columns::*;
 
 		// Result will be `after` `new` `before`
+		let audit = &audit;
 		state
 			.db
 			.get()
 			.await?
-			.transaction::<_, diesel::result::Error, _>(|con| {
+			.transaction::<_, anyhow::Error, _>(|con| {
 				async move {
 					// Find current member before `before`
 					let after = erwischt_member::table
@@ -259,7 +311,16 @@ pub(crate) async fn insert(
 						.execute(con)
 						.await?;
 
-					Ok(())
+					audit
+						.log(
+							con,
+							"erwischt_member",
+							Some(format!("{}/{}", data.game, last_id + 1)),
+							"erstellt",
+							Value::Null,
+							json!({ "name": data.name, "target": data.before }),
+						)
+						.await
 				}
 				.scope_boxed()
 			})
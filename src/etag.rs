@@ -53,7 +53,10 @@ pub(crate) async fn compute_etag(
 	let entry = this.cache.lock().unwrap().get(&path).cloned();
 	if let Some(entry) = entry {
 		// 1.1 If tag matches, check last-modified to see if modified
-		let head_resp = next.clone().run(Request::head(req.uri()).body(().into()).unwrap()).await;
+		// Keep the session, handlers may check the role of the user
+		let mut head_req = Request::head(req.uri()).body(().into()).unwrap();
+		*head_req.extensions_mut() = req.extensions().clone();
+		let head_resp = next.clone().run(head_req).await;
 		let last_modified = head_resp
 			.headers()
 			.get(LAST_MODIFIED)
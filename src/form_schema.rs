@@ -0,0 +1,226 @@
+//! Description of the signup forms for the frontend.
+//!
+//! The schema is the field table of [`crate::db::fields`] that the parsers use to validate the
+//! forms, so the frontend can check a form before sending it without keeping its own copy of the
+//! rules. Forms that are sent without javascript are shown again with
+//! [`render_error_page`] if they contain errors.
+//!
+//! The labels of the participant signup are translated to the language in the `sprache` query
+//! parameter.
+
+use std::collections::HashMap;
+
+use axum::Json;
+use axum::extract::{Path, Query};
+use axum::http::StatusCode;
+use axum::response::{Html, IntoResponse, Response};
+use serde::Deserialize;
+use t4rust_derive::Template;
+
+use crate::db::FormError;
+use crate::db::fields::{self, Field, FieldType};
+use crate::i18n::{self, Sprache};
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct SchemaQuery {
+	sprache: Option<String>,
+}
+
+/// A form that was sent without javascript, shown again with the entered values and all errors.
+#[derive(Debug, Template)]
+#[TemplatePath = "templates/form-error.tt"]
+struct ErrorPage<'a> {
+	sprache: Sprache,
+	title: &'a str,
+	action: &'a str,
+	fields: &'a [Field],
+	values: &'a HashMap<String, String>,
+	errors: &'a [FormError],
+}
+
+impl ErrorPage<'_> {
+	fn t(&self, text: &str) -> String { i18n::translate(self.sprache, text) }
+
+	fn value(&self, id: &str) -> &str {
+		self.values.get(id).map(|v| v.as_str()).unwrap_or_default()
+	}
+
+	fn field_errors(&self, id: &str) -> Vec<&FormError> {
+		self.errors.iter().filter(|e| e.field.as_deref() == Some(id)).collect()
+	}
+
+	/// Sent values that are not fields of the form, e.g. `sprache` and `entwurf`.
+	fn hidden_values(&self) -> Vec<(&str, &str)> {
+		let mut res = self
+			.values
+			.iter()
+			.filter(|(k, _)| *k != "submit" && !self.fields.iter().any(|f| f.id == *k))
+			.map(|(k, v)| (k.as_str(), v.as_str()))
+			.collect::<Vec<_>>();
+		res.sort();
+		res
+	}
+
+	/// Errors that do not belong to a field of the form.
+	fn general_errors(&self) -> impl Iterator<Item = &FormError> {
+		self.errors.iter().filter(move |e| {
+			e.field.as_ref().is_none_or(|id| !self.fields.iter().any(|f| f.id == id))
+		})
+	}
+}
+
+fn escape(s: &str) -> String {
+	s.replace('&', "&amp;")
+		.replace('<', "&lt;")
+		.replace('>', "&gt;")
+		.replace('"', "&quot;")
+		.replace('\'', "&#39;")
+}
+
+/// An error message as html, keeping line breaks.
+fn message(s: &str) -> String { escape(s).replace('\n', "<br>") }
+
+fn value_label(value: &str) -> String {
+	match value {
+		"true" => "Ja".into(),
+		"false" => "Nein".into(),
+		"m" => "Männlich".into(),
+		"w" => "Weiblich".into(),
+		_ => heck::ToTitleCase::to_title_case(value),
+	}
+}
+
+/// Show a form again after it was sent without javascript and contained errors.
+///
+/// The form is filled with the sent `values` and the wrong fields are marked. The page is shown
+/// in the language of the `sprache` value.
+pub fn render_error_page(
+	status: StatusCode, title: &str, action: &str, fields: &[Field],
+	values: &HashMap<String, String>, errors: &[FormError],
+) -> Response {
+	let sprache = Sprache::from_code(values.get("sprache").map(String::as_str).unwrap_or_default());
+	let page = ErrorPage { sprache, title, action, fields, values, errors };
+	(status, Html(page.to_string())).into_response()
+}
+
+pub async fn form_schema(Path(form): Path<String>, Query(query): Query<SchemaQuery>) -> Response {
+	let mut fields = match form.as_str() {
+		"teilnehmer" => fields::member(),
+		"betreuer" => fields::supervisor(),
+		"presignup" => fields::presignup(),
+		_ => return StatusCode::NOT_FOUND.into_response(),
+	};
+	let sprache = Sprache::from_code(query.sprache.as_deref().unwrap_or_default());
+	for f in &mut fields {
+		f.label = i18n::translate(sprache, &f.label).into();
+	}
+	Json(fields).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+	use std::collections::HashMap;
+
+	use time::macros::format_description;
+
+	use super::*;
+	use crate::db::fields::{PRESIGNUP_EXTRA, member, presignup, supervisor};
+	use crate::db::models::{Supervisor, Teilnehmer};
+	use crate::season;
+
+	/// Fill in a valid value for every field.
+	fn valid_form(fields: &[Field]) -> HashMap<String, String> {
+		let date_format = format_description!("[day].[month].[year]");
+		fields
+			.iter()
+			.map(|f| {
+				let value = match (f.id, f.kind) {
+					("plz", _) => "80000".into(),
+					("hausnummer", _) => "1".into(),
+					("juleica_nummer", _) => "123".into(),
+					(_, FieldType::Text) => "a".into(),
+					(_, FieldType::Email) => "a@b".into(),
+					(_, FieldType::Date) => f
+						.min_date
+						.or(f.max_date)
+						.unwrap_or(season::beginn())
+						.format(date_format)
+						.unwrap(),
+					(_, FieldType::Enum) => f.values[0].into(),
+					(_, FieldType::Freetext) => "".into(),
+					(_, FieldType::Checkbox) => "true".into(),
+				};
+				(f.id.to_string(), value)
+			})
+			.collect()
+	}
+
+	/// The schema accepts the same forms as the parser.
+	fn check_schema<T>(
+		fields: &[Field],
+		parse: impl Fn(HashMap<String, String>) -> Result<T, Vec<crate::db::FormError>>,
+	) {
+		let mut form = valid_form(fields);
+		form.insert("land".into(), "Deutschland".into());
+		assert!(parse(form.clone()).is_ok());
+
+		for f in fields {
+			let mut form = form.clone();
+			form.remove(f.id);
+			let errors =
+				parse(form).err().unwrap_or_else(|| panic!("Missing {} is accepted", f.id));
+			assert!(errors.iter().any(|e| e.field.as_deref() == Some(f.id)), "{}", f.id);
+
+			if f.required && f.kind != FieldType::Date {
+				let mut form = valid_form(fields);
+				form.insert(f.id.into(), "".into());
+				assert!(parse(form).is_err(), "Empty {} is accepted", f.id);
+			}
+		}
+	}
+
+	#[test]
+	fn schema_matches_parser() {
+		check_schema(&member(), Teilnehmer::from_hashmap);
+		check_schema(&supervisor(), Supervisor::from_hashmap);
+		let pre_fields = presignup()
+			.into_iter()
+			.filter(|f| !PRESIGNUP_EXTRA.contains(&f.id))
+			.collect::<Vec<_>>();
+		check_schema(&pre_fields, Supervisor::from_pre_hashmap);
+	}
+
+	#[test]
+	fn error_page() {
+		let fields = presignup();
+		let values = HashMap::from([
+			("vorname".to_string(), "<b>Max</b>".to_string()),
+			("geschlecht".to_string(), "w".to_string()),
+			("sprache".to_string(), "en".to_string()),
+			("entwurf".to_string(), "abc\"".to_string()),
+			("submit".to_string(), "Absenden".to_string()),
+		]);
+		let errors =
+			[FormError { field: Some("mail".into()), message: "Mail fehlt".into() }, FormError {
+				field: None,
+				message: "Erste Zeile\nZweite Zeile".into(),
+			}];
+		let page = ErrorPage {
+			sprache: Sprache::De,
+			title: "Test",
+			action: "/api/test",
+			fields: &fields,
+			values: &values,
+			errors: &errors,
+		}
+		.to_string();
+		assert!(page.contains(r#"value="&lt;b&gt;Max&lt;/b&gt;""#));
+		assert!(page.contains(r#"value="w" checked"#));
+		assert!(page.contains("Erste Zeile<br>Zweite Zeile"));
+		assert!(page.contains(r#"<div class="field invalid">"#));
+		assert_eq!(page.matches("Mail fehlt").count(), 1);
+		assert!(page.contains(r#"<input type="hidden" name="entwurf" value="abc&quot;">"#));
+		assert!(page.contains(r#"<input type="hidden" name="sprache" value="en">"#));
+		assert!(!page.contains(r#"type="hidden" name="submit""#));
+	}
+}
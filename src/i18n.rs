@@ -0,0 +1,253 @@
+//! Translations of the participant signup.
+//!
+//! German is the source language. Like with gettext, the German text is the key of a
+//! translation. A `{}` in a key matches any text, which is translated as well and inserted at the
+//! `{}` of the translation. Texts without a translation stay German.
+//!
+//! Mails are translated with separate templates, see [`crate::mail_template`].
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Sprache {
+	#[default]
+	De,
+	En,
+}
+
+/// English translations.
+static EN: &[(&str, &str)] = &[
+	// Field names in errors
+	("Vorname", "First name"),
+	("Nachname", "Last name"),
+	("Geburtsdatum", "Date of birth"),
+	("Geschlecht", "Gender"),
+	("Schwimmer", "Swimmer"),
+	("Vegetarier", "Vegetarian"),
+	("Tetanus Impfung", "Tetanus vaccination"),
+	("Eltern Name", "Name of the parent"),
+	("Eltern Mail", "E-mail of the parent"),
+	("Eltern Handynummer", "Mobile number of the parent"),
+	("Land", "Country"),
+	("Strasse", "Street"),
+	("Hausnummer", "House number"),
+	("Ort", "City"),
+	("Plz", "Postal code"),
+	("Krankenversicherung", "Health insurance"),
+	("Krankheiten", "Characteristics/illnesses"),
+	("Allergien", "Allergies"),
+	("Unvertraeglichkeiten", "Food intolerances"),
+	("Medikamente", "Medication"),
+	("Kommentar", "Comments"),
+	("Eigenanreise", "Own arrival"),
+	("Daten Behalten", "Keep data"),
+	("Agb", "Terms and conditions"),
+	// Field labels
+	("Vegetarisch", "Vegetarian"),
+	("Tetanusimpfung", "Tetanus vaccination"),
+	("Name eines Erziehungsberechtigten", "Name of a parent or guardian"),
+	("E-Mailadresse des Erziehungsberechtigten", "E-mail address of the parent or guardian"),
+	(
+		"Handynummer des Erziehungsberechtigten (für Notfälle)",
+		"Mobile number of the parent or guardian (for emergencies)",
+	),
+	("Straße", "Street"),
+	("Postleitzahl", "Postal code"),
+	("Eigenheiten/Krankheiten", "Characteristics/illnesses"),
+	("Sonstige Allergien", "Other allergies"),
+	("Lebensmittelunverträglichkeiten/-allergien", "Food intolerances/allergies"),
+	("Sonstige Kommentare", "Other comments"),
+	("Eigene Anreise", "Own arrival"),
+	("Daten für die Anmeldung im nächsten Jahr speichern", "Keep the data for next year's signup"),
+	(
+		"Ich habe die Allgemeinen Geschäftsbedingungen und die Datenschutzbestimmungen gelesen \
+		 und akzeptiere sie.",
+		"I have read and accept the terms and conditions and the privacy policy.",
+	),
+	// Values
+	("Ja", "Yes"),
+	("Nein", "No"),
+	("Männlich", "Male"),
+	("Weiblich", "Female"),
+	("Gesetzlich", "Statutory"),
+	("Privat", "Private"),
+	("Anderes", "Other"),
+	("TT.MM.JJJJ", "DD.MM.YYYY"),
+	// Validation
+	("{} fehlt", "{} is missing"),
+	("{} ({}) ist kein Wahrheitswert", "{} ({}) is not a boolean value"),
+	("{} muss ausgefüllt werden", "{} must be filled in"),
+	(
+		"Bitte geben Sie das Datum ({}) im Format TT.MM.JJJJ an.",
+		"Please enter the date ({}) in the format DD.MM.YYYY.",
+	),
+	("{} ist kein bekanntes Geschlecht.", "{} is not a known gender."),
+	("Ungültige Postleitzahl ({}), {}", "Invalid postal code ({}), {}"),
+	("darf nur Zahlen enthalten", "must only contain digits"),
+	("muss {} Stellen haben", "must have {} digits"),
+	(
+		"Ungültige Krankenversicherung ({}), muss entweder gesetzlich, privat oder anderes sein",
+		"Invalid health insurance ({}), must be gesetzlich (statutory), privat (private) or \
+		 anderes (other)",
+	),
+	("Ungültige E-Mail-Adresse ({}), {}", "Invalid e-mail address ({}), {}"),
+	("muss ein @ enthalten", "must contain an @"),
+	("bitten nur eine einzelne E-Mail-Adresse angeben", "please enter a single e-mail address"),
+	("darf keine Leerzeichen enthalten", "must not contain spaces"),
+	(
+		"Ungültige Hausnummer ({}), muss mindestens eine Ziffer enthalten",
+		"Invalid house number ({}), must contain at least one digit",
+	),
+	(
+		"Sind Sie sicher, dass {} das Geburtsdatum Ihres Kindes ist?\nBitte geben Sie das \
+		 Geburtsdatum im Format TT.MM.JJJJ an.",
+		"Are you sure that {} is the date of birth of your child?\nPlease enter the date of birth \
+		 in the format DD.MM.YYYY.",
+	),
+	(
+		"Ihr Kind ist zu jung (Geburtsdatum {}).\nDas Zeltlager ist für Kinder und Jugendliche \
+		 zwischen 7 und 15 Jahren.",
+		"Your child is too young (date of birth {}).\nThe camp is for children and teenagers \
+		 between 7 and 15 years.",
+	),
+	(
+		"Ihr Kind ist zu alt um als Teilnehmer beim Zeltlager mitzufahren (Geburtsdatum {}).\nWir \
+		 suchen immer nach motivierten Betreuern (ab 16 Jahren), die auf das Zeltlager \
+		 mitfahren.\nInfos dazu finden Sie auf der Betreuerseite.\nDas Zeltlager ist für Kinder \
+		 und Jugendliche zwischen 7 und 15 Jahren.",
+		"Your child is too old to join the camp as a participant (date of birth {}).\nWe are \
+		 always looking for motivated supervisors (from 16 years).\nYou can find more information \
+		 on the supervisor page.\nThe camp is for children and teenagers between 7 and 15 years.",
+	),
+	("Die AGB müssen akzeptiert werden", "The terms and conditions must be accepted"),
+	// Signup
+	(
+		"Ihre Daten wurden erfolgreich gespeichert.\nEs ist leider ein Fehler beim E-Mail senden \
+		 aufgetreten.\n{}",
+		"Your data was saved successfully.\nUnfortunately, sending the e-mail failed.\n{}",
+	),
+	("Es ist ein Datenbank-Fehler aufgetreten.\n{}", "A database error occurred.\n{}"),
+	(
+		"Ihre Daten wurden gespeichert.\nEs ist leider ein Fehler beim E-Mail senden \
+		 aufgetreten.\n{}",
+		"Your data was saved.\nUnfortunately, sending the e-mail failed.\n{}",
+	),
+	(
+		"{} {} ist bereits zum Zeltlager angemeldet, eine zweite Anmeldung ist nicht \
+		 nötig.\nFalls Sie keine Bestätigungsmail bekommen haben, schauen Sie bitte in Ihren \
+		 Spam-Ordner oder schreiben Sie uns.",
+		"{} {} is already signed up for the camp, a second signup is not needed.\nIf you did not \
+		 get a confirmation mail, please check your spam folder or write to us.",
+	),
+	(
+		"Während Ihrer Anmeldung ist das Zeltlager leider schon voll geworden.",
+		"Unfortunately, the camp became full during your signup.",
+	),
+	(
+		"Bitte geben Sie eine E-Mail-Adresse an, an die wir den Link zum Fortsetzen schicken \
+		 können.",
+		"Please enter an e-mail address, where we can send the link to continue.",
+	),
+	(
+		"Zu viele Anfragen. Bitte versuchen Sie es später erneut.",
+		"Too many requests. Please try again later.",
+	),
+	(
+		"An diese E-Mail-Adresse wurde gerade erst ein Link geschickt. Bitte versuchen Sie es in \
+		 ein paar Minuten erneut.",
+		"A link was just sent to this e-mail address. Please try again in a few minutes.",
+	),
+	// Form without javascript
+	("Anmeldung", "Signup"),
+	(
+		"Das Formular konnte leider nicht abgeschickt werden. Bitte die markierten Angaben \
+		 überprüfen und das Formular erneut absenden.",
+		"Unfortunately, the form could not be sent. Please check the marked fields and send the \
+		 form again.",
+	),
+	("Absenden", "Send"),
+];
+
+impl Sprache {
+	pub fn code(self) -> &'static str {
+		match self {
+			Self::De => "de",
+			Self::En => "en",
+		}
+	}
+
+	/// Unknown or missing languages fall back to German.
+	pub fn from_code(code: &str) -> Self {
+		match code.trim() {
+			"en" => Self::En,
+			_ => Self::De,
+		}
+	}
+
+	fn catalog(self) -> &'static [(&'static str, &'static str)] {
+		match self {
+			Self::De => &[],
+			Self::En => EN,
+		}
+	}
+}
+
+/// Match `text` against a key with `{}` placeholders, returns the text of the placeholders.
+fn matches<'a>(key: &str, text: &'a str) -> Option<Vec<&'a str>> {
+	let mut parts = key.split("{}");
+	let mut rest = text.strip_prefix(parts.next().unwrap_or_default())?;
+	let parts = parts.collect::<Vec<_>>();
+	let mut args = Vec::with_capacity(parts.len());
+	for (i, part) in parts.iter().enumerate() {
+		if i + 1 == parts.len() {
+			args.push(rest.strip_suffix(part)?);
+			rest = "";
+		} else {
+			let pos = rest.find(part)?;
+			args.push(&rest[..pos]);
+			rest = &rest[pos + part.len()..];
+		}
+	}
+	rest.is_empty().then_some(args)
+}
+
+/// Translate a German text.
+pub fn translate(sprache: Sprache, text: &str) -> String {
+	for (key, translation) in sprache.catalog() {
+		if let Some(args) = matches(key, text) {
+			let mut parts = translation.split("{}");
+			let mut res = parts.next().unwrap_or_default().to_string();
+			for (arg, part) in args.into_iter().zip(parts) {
+				res.push_str(&translate(sprache, arg));
+				res.push_str(part);
+			}
+			return res;
+		}
+	}
+	text.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn translate_messages() {
+		assert_eq!(translate(Sprache::De, "Eltern Mail fehlt"), "Eltern Mail fehlt");
+		assert_eq!(translate(Sprache::En, "Eltern Mail fehlt"), "E-mail of the parent is missing");
+		assert_eq!(
+			translate(Sprache::En, "Ungültige Postleitzahl (1234), muss 5 Stellen haben"),
+			"Invalid postal code (1234), must have 5 digits"
+		);
+		assert_eq!(
+			translate(
+				Sprache::En,
+				"Es ist ein Datenbank-Fehler aufgetreten.\nBitte schreiben Sie uns."
+			),
+			"A database error occurred.\nBitte schreiben Sie uns."
+		);
+		assert_eq!(translate(Sprache::En, "Unbekannt"), "Unbekannt");
+		assert_eq!(translate(Sprache::En, "Vorname fehlt nicht"), "Vorname fehlt nicht");
+	}
+}
@@ -0,0 +1,418 @@
+//! Documents like the AGB or forms that can be downloaded from the website.
+//!
+//! Admins upload documents through the api, every upload adds a new version. The newest version
+//! is served at the stable url `/dokumente/<name>`, older versions at
+//! `/dokumente/<name>/<version id>`. Documents can be restricted to users with a role.
+//!
+//! Only PDFs and images whose content matches their type are shown in the browser, everything
+//! else is served as a download, so an uploaded HTML or SVG file cannot run scripts on the site.
+
+use axum::body::{Body, Bytes};
+use axum::extract::{Path, Query};
+use axum::http::header::{
+	CONTENT_DISPOSITION, CONTENT_TYPE, LAST_MODIFIED, X_CONTENT_TYPE_OPTIONS,
+};
+use axum::http::{Extensions, HeaderMap, Method, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::{Json, extract};
+use diesel::prelude::*;
+use diesel_async::scoped_futures::ScopedFutureExt;
+use diesel_async::{AsyncConnection, RunQueryDsl};
+use serde::{Deserialize, Serialize};
+use time::PrimitiveDateTime;
+use time::macros::format_description;
+use tower_sessions::Session;
+use tracing::{error, warn};
+
+use crate::db::models::primitive_datetime;
+use crate::{ExtractState, OidcClaims, WebResult, auth, db};
+
+type DbResult<T> = anyhow::Result<T>;
+
+/// Maximum size of an uploaded document
+pub const MAX_SIZE: usize = 20 * 1024 * 1024;
+
+/// Types that are shown in the browser, with the bytes their content starts with
+const INLINE_TYPES: &[(&str, &[u8])] = &[
+	("application/pdf", b"%PDF-"),
+	("image/png", b"\x89PNG\r\n\x1a\n"),
+	("image/jpeg", b"\xff\xd8\xff"),
+	("image/gif", b"GIF8"),
+];
+const DEFAULT_TYPE: &str = "application/octet-stream";
+
+#[derive(Clone, Debug, Serialize)]
+pub struct LibraryDocument {
+	id: i32,
+	name: String,
+	titel: String,
+	rolle: Option<String>,
+	/// Newest version first
+	versionen: Vec<LibraryVersion>,
+}
+
+#[derive(Clone, Debug, Queryable, Serialize)]
+pub struct LibraryVersion {
+	id: i32,
+	#[serde(skip)]
+	datei: i32,
+	dateiname: String,
+	mime: String,
+	/// Size in bytes
+	groesse: i32,
+	#[serde(with = "primitive_datetime")]
+	hochgeladen: PrimitiveDateTime,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct UploadData {
+	/// Title of a new document, defaults to the name
+	titel: Option<String>,
+	dateiname: String,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct EditData {
+	titel: String,
+	/// Public if unset
+	rolle: Option<String>,
+}
+
+/// Names are used in urls.
+fn check_name(name: &str) -> bool {
+	!name.is_empty()
+		&& name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || "-_.".contains(c))
+}
+
+/// Format a date for the `Last-Modified` header.
+fn http_date(date: &PrimitiveDateTime) -> String {
+	date.format(format_description!(
+		"[weekday repr:short], [day] [month repr:short] [year] [hour]:[minute]:[second] GMT"
+	))
+	.unwrap()
+}
+
+/// The type that is stored for an upload.
+///
+/// Known types are detected from the content. A declared type that would be shown in the browser
+/// but does not match the content is replaced by [`DEFAULT_TYPE`].
+fn content_type(declared: Option<&str>, content: &[u8]) -> String {
+	if let Some((mime, _)) = INLINE_TYPES.iter().find(|(_, magic)| content.starts_with(magic)) {
+		return mime.to_string();
+	}
+	match declared {
+		Some(mime) if !is_inline(mime) && !mime.is_empty() => mime.to_string(),
+		_ => DEFAULT_TYPE.to_string(),
+	}
+}
+
+fn is_inline(mime: &str) -> bool {
+	let essence = mime.split(';').next().unwrap_or_default().trim().to_ascii_lowercase();
+	INLINE_TYPES.iter().any(|(m, _)| *m == essence)
+}
+
+fn err(error: anyhow::Error, msg: &'static str) -> Response {
+	error!(%error, "{msg}");
+	(StatusCode::INTERNAL_SERVER_ERROR, msg).into_response()
+}
+
+/// Serve the newest version of a document.
+pub async fn get_document(
+	extract::State(state): ExtractState, Path(name): Path<String>, method: Method,
+	extensions: Extensions,
+) -> Response {
+	serve(&state, &name, None, &method, &extensions).await
+}
+
+/// Serve an old version of a document.
+pub async fn get_document_version(
+	extract::State(state): ExtractState, Path((name, version)): Path<(String, i32)>,
+	method: Method, extensions: Extensions,
+) -> Response {
+	serve(&state, &name, Some(version), &method, &extensions).await
+}
+
+/// The role is checked before the content is loaded. `HEAD` requests, which the etag layer uses
+/// to check for changes, do not load the content at all.
+async fn serve(
+	state: &crate::State, name: &str, version: Option<i32>, method: &Method,
+	extensions: &Extensions,
+) -> Response {
+	let not_found = || (StatusCode::NOT_FOUND, "Page not found").into_response();
+
+	let res = async {
+		use db::schema::{datei, datei_version};
+
+		let mut connection = state.db.get().await?;
+		let Some((id, rolle)) = datei::table
+			.filter(datei::name.eq(name))
+			.select((datei::id, datei::rolle))
+			.first::<(i32, Option<String>)>(&mut connection)
+			.await
+			.optional()?
+		else {
+			return DbResult::Ok(None);
+		};
+
+		if let Some(rolle) = rolle {
+			let role = rolle.parse::<auth::Roles>()?;
+			let Some(session) = extensions.get::<Session>() else {
+				return Ok(None);
+			};
+			let oidc = extensions.get::<OidcClaims>().cloned();
+			let roles = auth::get_roles(state, session, &oidc).await?;
+			if !roles.map(|r| r.contains(&role)).unwrap_or_default() {
+				warn!(name, "Forbidden");
+				return Ok(None);
+			}
+		}
+
+		let mut query = datei_version::table
+			.filter(datei_version::datei.eq(id))
+			.order(datei_version::id.desc())
+			.select((
+				datei_version::id,
+				datei_version::dateiname,
+				datei_version::mime,
+				datei_version::hochgeladen,
+			))
+			.into_boxed();
+		if let Some(version) = version {
+			query = query.filter(datei_version::id.eq(version));
+		}
+		let Some((version, dateiname, mime, hochgeladen)) = query
+			.first::<(i32, String, String, PrimitiveDateTime)>(&mut connection)
+			.await
+			.optional()?
+		else {
+			return Ok(None);
+		};
+		let inhalt = if method == Method::HEAD {
+			Vec::new()
+		} else {
+			datei_version::table
+				.find(version)
+				.select(datei_version::inhalt)
+				.first::<Vec<u8>>(&mut connection)
+				.await?
+		};
+		Ok(Some((dateiname, mime, inhalt, hochgeladen)))
+	}
+	.await;
+
+	match res {
+		Err(error) => {
+			error!(%error, name, "Failed to get document");
+			(StatusCode::INTERNAL_SERVER_ERROR, "Fehler beim Laden des Dokuments").into_response()
+		}
+		Ok(None) => not_found(),
+		Ok(Some((dateiname, mime, inhalt, hochgeladen))) => {
+			let filename =
+				url::form_urlencoded::byte_serialize(dateiname.as_bytes()).collect::<String>();
+			let disposition = if is_inline(&mime) { "inline" } else { "attachment" };
+			Response::builder()
+				.header(CONTENT_TYPE, mime)
+				.header(X_CONTENT_TYPE_OPTIONS, "nosniff")
+				.header(CONTENT_DISPOSITION, format!("{disposition}; filename*=UTF-8''{filename}"))
+				.header(LAST_MODIFIED, http_date(&hochgeladen))
+				.body(Body::from(inhalt))
+				.unwrap()
+		}
+	}
+}
+
+/// List all documents with their versions.
+pub async fn list_documents(
+	extract::State(state): ExtractState,
+) -> WebResult<Json<Vec<LibraryDocument>>> {
+	match async {
+		use db::schema::{datei, datei_version};
+
+		let mut connection = state.db.get().await?;
+		let versions = datei_version::table
+			.order(datei_version::id.desc())
+			.select((
+				datei_version::id,
+				datei_version::datei,
+				datei_version::dateiname,
+				datei_version::mime,
+				diesel::dsl::sql::<diesel::sql_types::Int4>("octet_length(inhalt)"),
+				datei_version::hochgeladen,
+			))
+			.load::<LibraryVersion>(&mut connection)
+			.await?;
+		let docs = datei::table
+			.order(datei::name)
+			.load::<(i32, String, String, Option<String>)>(&mut connection)
+			.await?;
+		DbResult::Ok(
+			docs.into_iter()
+				.map(|(id, name, titel, rolle)| LibraryDocument {
+					id,
+					name,
+					titel,
+					rolle,
+					versionen: versions.iter().filter(|v| v.datei == id).cloned().collect(),
+				})
+				.collect(),
+		)
+	}
+	.await
+	{
+		Err(error) => Err(err(error, "Failed to list documents")),
+		Ok(r) => Ok(Json(r)),
+	}
+}
+
+/// Upload a new version of a document, the document is created if it does not exist.
+///
+/// The request body is the content of the file.
+pub async fn upload_document(
+	extract::State(state): ExtractState, Path(name): Path<String>, Query(data): Query<UploadData>,
+	headers: HeaderMap, body: Bytes,
+) -> WebResult<&'static str> {
+	if !check_name(&name) {
+		return Err((
+			StatusCode::BAD_REQUEST,
+			"Der Name darf nur Kleinbuchstaben, Zahlen, '-', '_' und '.' enthalten",
+		)
+			.into_response());
+	}
+	if body.is_empty() {
+		return Err((StatusCode::BAD_REQUEST, "Die Datei ist leer").into_response());
+	}
+	let mime = content_type(headers.get(CONTENT_TYPE).and_then(|m| m.to_str().ok()), &body);
+
+	match async {
+		use db::schema::{datei, datei_version};
+
+		state
+			.db
+			.get()
+			.await?
+			.transaction::<_, diesel::result::Error, _>(|con| {
+				async move {
+					let id = diesel::insert_into(datei::table)
+						.values((
+							datei::name.eq(&name),
+							datei::titel.eq(data.titel.as_deref().unwrap_or(&name)),
+						))
+						.on_conflict(datei::name)
+						.do_update()
+						.set(datei::name.eq(&name))
+						.returning(datei::id)
+						.get_result::<i32>(con)
+						.await?;
+					diesel::insert_into(datei_version::table)
+						.values((
+							datei_version::datei.eq(id),
+							datei_version::dateiname.eq(&data.dateiname),
+							datei_version::mime.eq(&mime),
+							datei_version::inhalt.eq(body.as_ref()),
+						))
+						.execute(con)
+						.await?;
+					Ok(())
+				}
+				.scope_boxed()
+			})
+			.await?;
+		DbResult::Ok(())
+	}
+	.await
+	{
+		Err(error) => Err(err(error, "Failed to upload document")),
+		Ok(()) => Ok("Success"),
+	}
+}
+
+/// Change the title and visibility of a document.
+pub async fn edit_document(
+	extract::State(state): ExtractState, Path(name): Path<String>, Json(data): Json<EditData>,
+) -> WebResult<&'static str> {
+	let rolle = data.rolle.clone().filter(|r| !r.is_empty());
+	if let Some(rolle) = &rolle {
+		if rolle.parse::<auth::Roles>().is_err() {
+			return Err((StatusCode::BAD_REQUEST, "Unbekannte Rolle").into_response());
+		}
+	}
+
+	match async {
+		use db::schema::datei;
+
+		let r = diesel::update(datei::table.filter(datei::name.eq(&name)))
+			.set((datei::titel.eq(&data.titel), datei::rolle.eq(&rolle)))
+			.execute(&mut state.db.get().await?)
+			.await?;
+		if r == 0 {
+			anyhow::bail!("Document not found");
+		}
+		DbResult::Ok(())
+	}
+	.await
+	{
+		Err(error) => Err(err(error, "Failed to edit document")),
+		Ok(()) => Ok("Success"),
+	}
+}
+
+/// Remove a document with all versions.
+pub async fn delete_document(
+	extract::State(state): ExtractState, Path(name): Path<String>,
+) -> WebResult<&'static str> {
+	match async {
+		use db::schema::datei;
+
+		diesel::delete(datei::table.filter(datei::name.eq(&name)))
+			.execute(&mut state.db.get().await?)
+			.await?;
+		DbResult::Ok(())
+	}
+	.await
+	{
+		Err(error) => Err(err(error, "Failed to delete document")),
+		Ok(()) => Ok("Success"),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use time::format_description::well_known;
+	use time::macros::datetime;
+
+	use super::*;
+
+	#[test]
+	fn last_modified_is_parsable() {
+		// The etag layer parses the header to detect changes
+		let date = datetime!(2026-10-19 12:30:05);
+		let s = http_date(&date);
+		assert_eq!(s, "Mon, 19 Oct 2026 12:30:05 GMT");
+		assert_eq!(
+			time::UtcDateTime::parse(&s, &well_known::Rfc2822).unwrap(),
+			time::UtcDateTime::new(date.date(), date.time())
+		);
+	}
+
+	#[test]
+	fn names() {
+		assert!(check_name("agb-2021.pdf"));
+		assert!(!check_name("../agb"));
+		assert!(!check_name(""));
+	}
+
+	#[test]
+	fn content_types() {
+		let pdf = b"%PDF-1.7\n...";
+		assert_eq!(content_type(None, pdf), "application/pdf");
+		assert_eq!(content_type(Some("text/html"), pdf), "application/pdf");
+		let html = b"<html><script>alert(1)</script></html>";
+		assert_eq!(content_type(Some("text/html"), html), "text/html");
+		assert_eq!(content_type(Some("image/png"), html), DEFAULT_TYPE);
+		assert_eq!(content_type(Some("Application/PDF; x=y"), html), DEFAULT_TYPE);
+		assert_eq!(content_type(None, html), DEFAULT_TYPE);
+		assert!(is_inline("application/pdf"));
+		assert!(!is_inline("text/html"));
+		assert!(!is_inline("image/svg+xml"));
+	}
+}
@@ -1,123 +1,412 @@
 use std::convert::TryInto;
 use std::str::FromStr;
+use std::sync::Arc;
 
-use anyhow::Result;
-use lettre::message::header;
+use anyhow::{Result, bail};
+use lettre::AsyncSmtpTransport;
+use lettre::message::{Attachment, MessageBuilder, MultiPart, header};
 use lettre::transport::smtp::authentication::Credentials;
-use lettre::{AsyncSmtpTransport, AsyncTransport};
+use minijinja::{Value, context};
+use rand::RngExt;
 use t4rust_derive::Template;
+use tokio::sync::Notify;
+use tracing::warn;
 
-use crate::config::{Config, MailAddress};
+use crate::config::{AdditionalMailMode, Config, MailAddress, MailTransport};
+use crate::db::Database;
 use crate::db::models::{
-	FullSupervisor, FullTeilnehmer, Gender, Supervisor, Teilnehmer, years_old,
+	Cancellation, FullSupervisor, FullTeilnehmer, Supervisor, Teilnehmer, years_old,
 };
-use crate::{GERMAN_DATE_FORMAT, LAGER_START};
+use crate::dkim::Dkim;
+use crate::i18n::Sprache;
+use crate::mail_transport::{Captured, Transport};
+use crate::{digest, mail_template, outbox, season};
 
-#[derive(Clone, Debug)]
+#[derive(Clone)]
 pub struct Mail {
 	config: Config,
+	db: Database,
+	/// Wakes up the outbox worker when a mail is queued.
+	queued: Arc<Notify>,
+	/// Mails sent with the memory transport
+	captured: Captured,
+	dkim: Option<Arc<Dkim>>,
 }
 
+/// Shared layout of the html part of all mails.
 #[derive(Debug, Template)]
-#[TemplatePath = "templates/mail-subject.tt"]
-struct Subject<'a> {
-	member: &'a Teilnehmer,
+#[TemplatePath = "templates/mail-layout.tt"]
+struct Layout<'a> {
+	/// Language code
+	lang: &'a str,
+	/// Escaped title
+	title: &'a str,
+	/// Html content
+	content: &'a str,
 }
 
-#[derive(Debug, Template)]
-#[TemplatePath = "templates/mail-body.tt"]
-struct Body<'a> {
-	member: &'a Teilnehmer,
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Block {
+	Paragraph,
+	List,
+	Table,
 }
 
-#[derive(Debug, Template)]
-#[TemplatePath = "templates/mail-payed-subject.tt"]
-struct PayedSubject<'a> {
-	member: &'a FullTeilnehmer,
+fn escape_html(s: &str) -> String {
+	let mut res = String::with_capacity(s.len());
+	for c in s.chars() {
+		match c {
+			'&' => res.push_str("&amp;"),
+			'<' => res.push_str("&lt;"),
+			'>' => res.push_str("&gt;"),
+			'"' => res.push_str("&quot;"),
+			_ => res.push(c),
+		}
+	}
+	res
 }
 
-#[derive(Debug, Template)]
-#[TemplatePath = "templates/mail-payed-body.tt"]
-struct PayedBody<'a> {
-	member: &'a FullTeilnehmer,
+/// Escape a line and turn urls into links.
+fn inline_html(mut line: &str) -> String {
+	let mut res = String::new();
+	while let Some(start) = ["https://", "http://"].iter().filter_map(|p| line.find(p)).min() {
+		res.push_str(&escape_html(&line[..start]));
+		let end = line[start..].find(char::is_whitespace).map(|i| start + i).unwrap_or(line.len());
+		// Punctuation at the end belongs to the sentence
+		let url = line[start..end].trim_end_matches(['.', ',', ':', ';', '!', '?', ')']);
+		let escaped = escape_html(url);
+		res.push_str(&format!("<a href=\"{escaped}\">{escaped}</a>"));
+		line = &line[start + url.len()..];
+	}
+	res.push_str(&escape_html(line));
+	res
 }
 
-#[derive(Debug, Template)]
-#[TemplatePath = "templates/resignup-mail.tt"]
-struct ResignupBody<'a> {
-	pub supervisor: &'a FullSupervisor,
-	pub token: &'a str,
+/// Split lines like `IBAN:   DE12…` that are aligned with spaces into label and value.
+fn table_row(line: &str) -> Option<(&str, &str)> {
+	if line.starts_with(char::is_whitespace) {
+		return None;
+	}
+	let i = line.find("  ")?;
+	let (label, value) = (&line[..i], line[i..].trim());
+	(label.chars().count() <= 25 && !value.is_empty()).then_some((label, value))
 }
 
-#[derive(Debug, Template)]
-#[TemplatePath = "templates/mail-presignup-body.tt"]
-pub struct PresignupBody<'a> {
-	receiver: &'a MailAddress,
-	supervisor: &'a Supervisor,
-	grund: &'a str,
-	kommentar: &'a str,
+/// Convert the plain text of a mail to html.
+///
+/// Paragraphs are separated by empty lines, lines starting with `- ` become lists and lines that
+/// are aligned with multiple spaces become tables.
+fn text_to_html(text: &str) -> String {
+	fn flush(html: &mut String, block: &mut Option<(Block, Vec<String>)>) {
+		let Some((kind, lines)) = block.take() else {
+			return;
+		};
+		match kind {
+			Block::Paragraph => html.push_str(&format!("<p>{}</p>\n", lines.join("<br>\n"))),
+			Block::List => {
+				html.push_str("<ul>\n");
+				for l in lines {
+					html.push_str(&format!("<li>{l}</li>\n"));
+				}
+				html.push_str("</ul>\n");
+			}
+			Block::Table => {
+				html.push_str("<table style=\"border-collapse: collapse;\">\n");
+				for l in lines {
+					html.push_str(&format!("<tr>{l}</tr>\n"));
+				}
+				html.push_str("</table>\n");
+			}
+		}
+	}
+
+	let mut html = String::new();
+	let mut block: Option<(Block, Vec<String>)> = None;
+	for line in text.lines() {
+		let line = line.trim_end();
+		if line.is_empty() {
+			flush(&mut html, &mut block);
+			continue;
+		}
+		let (kind, content) = if let Some(item) = line.strip_prefix("- ") {
+			(Block::List, inline_html(item))
+		} else if let Some((label, value)) = table_row(line) {
+			(
+				Block::Table,
+				format!(
+					"<td style=\"padding-right: 1em; vertical-align: top;\">{}</td><td>{}</td>",
+					inline_html(label),
+					inline_html(value)
+				),
+			)
+		} else {
+			(Block::Paragraph, inline_html(line))
+		};
+		if block.as_ref().map(|(k, _)| *k) != Some(kind) {
+			flush(&mut html, &mut block);
+		}
+		block.get_or_insert_with(|| (kind, Vec::new())).1.push(content);
+	}
+	flush(&mut html, &mut block);
+	html
 }
 
-#[derive(Debug, Template)]
-#[TemplatePath = "templates/mail-presignup-failed-body.tt"]
-pub struct PresignupFailedBody<'a> {
-	supervisor: &'a Supervisor,
+/// Mail body with the plain text and an html version in the shared layout.
+fn alternative(sprache: Sprache, subject: &str, text: String) -> MultiPart {
+	let html = format!("{}", Layout {
+		lang: sprache.code(),
+		title: &escape_html(subject),
+		content: &text_to_html(&text),
+	});
+	MultiPart::alternative_plain_html(text, html)
 }
 
 impl Mail {
-	pub fn new(config: Config) -> Self { Self { config } }
-
-	fn mailer(&self) -> Result<AsyncSmtpTransport<lettre::Tokio1Executor>> {
-		Ok(AsyncSmtpTransport::<lettre::Tokio1Executor>::relay(
-			self.config.sender_mail_account.host.as_str(),
-		)?
-		.credentials(Credentials::new(
-			self.config
-				.sender_mail_account
-				.name
-				.clone()
-				.unwrap_or_else(|| self.config.sender_mail.address.clone()),
-			self.config.sender_mail_account.password.clone(),
-		))
-		.build())
+	pub fn new(config: Config, db: Database) -> Result<Self> {
+		let dkim = match &config.dkim {
+			Some(settings) => Some(Arc::new(Dkim::new(&config, settings)?)),
+			None => None,
+		};
+		Ok(Self { config, db, queued: Default::default(), captured: Default::default(), dkim })
 	}
 
-	async fn send_eltern(
-		&self, eltern_name: &str, eltern_mail: &str, subject: String, body: String,
-	) -> Result<()> {
+	pub(crate) fn mailer(&self) -> Result<Transport> {
+		Ok(match &self.config.mail_transport {
+			MailTransport::Smtp => {
+				let Some(account) = &self.config.sender_mail_account else {
+					bail!("sender_mail_account is needed to send mails over smtp");
+				};
+				Transport::Smtp(
+					AsyncSmtpTransport::<lettre::Tokio1Executor>::relay(account.host.as_str())?
+						.credentials(Credentials::new(
+							account
+								.name
+								.clone()
+								.unwrap_or_else(|| self.config.sender_mail.address.clone()),
+							account.password.clone(),
+						))
+						.build(),
+				)
+			}
+			MailTransport::File { directory } => Transport::File(directory.clone()),
+			MailTransport::Memory => Transport::Memory(self.captured.clone()),
+		})
+	}
+
+	/// Sign a mail and store it in the outbox, it is sent in the background.
+	///
+	/// The mail gets a Message-ID on our domain, so bounces can be matched to the outbox entry.
+	async fn enqueue(&self, mut email: lettre::Message) -> Result<i32> {
+		email.headers_mut().set(header::MessageId::from(self.message_id()));
+		if let Some(dkim) = &self.dkim {
+			dkim.sign(&mut email);
+		}
+		let id = outbox::store(&self.db, &email).await?;
+		self.notify_queued();
+		Ok(id)
+	}
+
+	fn message_id(&self) -> String {
+		let domain = self.config.sender_mail.address.rsplit_once('@').map(|(_, d)| d);
+		let mut rng = rand::rng();
+		let id = (0..32).map(|_| rng.sample(rand::distr::Alphanumeric) as char).collect::<String>();
+		format!("<{id}@{}>", domain.unwrap_or("localhost"))
+	}
+
+	/// Render the subject and text of a mail from the templates `<name>-betreff` and `<name>` in
+	/// the given language.
+	async fn render(&self, sprache: Sprache, name: &str, ctx: Value) -> Result<(String, String)> {
+		let subject_name = mail_template::localized(&format!("{name}-betreff"), sprache);
+		let subject = mail_template::render(&self.db, &subject_name, ctx.clone()).await?;
+		let body =
+			mail_template::render(&self.db, &mail_template::localized(name, sprache), ctx).await?;
+		Ok((subject, body))
+	}
+
+	/// Wake up the outbox worker.
+	pub fn notify_queued(&self) { self.queued.notify_one(); }
+
+	/// Wait until a mail is queued.
+	pub async fn queued(&self) { self.queued.notified().await }
+
+	/// Add an event to the summary for the additional receivers, if it is enabled.
+	///
+	/// Like the bcc, this is skipped for the test mail address.
+	pub async fn add_to_digest(&self, eltern_mail: Option<&str>, art: digest::Art, text: String) {
+		if !matches!(self.config.additional_mail_mode, AdditionalMailMode::Digest { .. })
+			|| (eltern_mail.is_some() && self.config.test_mail.as_deref() == eltern_mail)
+		{
+			return;
+		}
+		if let Err(error) = digest::add(&self.db, art, text).await {
+			warn!(%error, ?art, "Failed to add event to summary");
+		}
+	}
+
+	/// Mail to parents, also sent to the additional receivers in bcc mode.
+	fn eltern_builder(
+		&self, eltern_name: &str, eltern_mail: &str, subject: String,
+	) -> Result<MessageBuilder> {
 		let mut email_builder = lettre::Message::builder()
 			.to((eltern_name, eltern_mail).try_into()?)
-			.header(header::ContentType::TEXT_PLAIN)
 			.from(self.config.sender_mail.clone().try_into()?)
 			.subject(subject);
 
-		if self.config.test_mail.as_ref().map(|m| m != eltern_mail).unwrap_or(true) {
+		if matches!(self.config.additional_mail_mode, AdditionalMailMode::Bcc)
+			&& self.config.test_mail.as_ref().map(|m| m != eltern_mail).unwrap_or(true)
+		{
 			// Send to additional receivers in bcc
 			for receiver in &self.config.additional_mail_receivers {
 				email_builder = email_builder.bcc(receiver.clone().try_into()?);
 			}
 		}
 
-		let email = email_builder.body(body)?;
+		Ok(email_builder)
+	}
+
+	async fn send_eltern(
+		&self, sprache: Sprache, eltern_name: &str, eltern_mail: &str, subject: String,
+		body: String,
+	) -> Result<()> {
+		let body = alternative(sprache, &subject, body);
+		let email = self.eltern_builder(eltern_name, eltern_mail, subject)?.multipart(body)?;
 
-		// Send the email
-		self.mailer()?.send(email).await?;
+		self.enqueue(email).await?;
 
 		Ok(())
 	}
 
-	pub async fn send_member_signup(&self, member: &Teilnehmer) -> Result<()> {
-		let subject = format!("{}", Subject { member }).trim().to_string();
-		let body = format!("{}", Body { member }).trim().to_string();
+	/// Send the signup confirmation with a PDF of all data, which is signed and handed in at
+	/// the bus.
+	pub async fn send_member_signup(
+		&self, member: &Teilnehmer, cancel_link: Option<&str>,
+	) -> Result<()> {
+		let sprache = Sprache::from_code(&member.sprache);
+		let (subject, body) =
+			self.render(sprache, "anmeldung", context! { member, cancel_link }).await?;
+		let filename = format!(
+			"Anmeldung Zeltlager {} {} {}.pdf",
+			season::beginn().year(),
+			member.vorname,
+			member.nachname
+		);
+
+		let body = alternative(sprache, &subject, body);
+		let email =
+			self.eltern_builder(&member.eltern_name, &member.eltern_mail, subject)?.multipart(
+				MultiPart::mixed().multipart(body).singlepart(Attachment::new(filename).body(
+					crate::pdf::signup_confirmation(member, season::current().preis),
+					header::ContentType::parse("application/pdf")?,
+				)),
+			)?;
+
+		self.enqueue(email).await?;
+		self.add_to_digest(
+			Some(&member.eltern_mail),
+			digest::Art::Anmeldung,
+			format!(
+				"{} {} ({}), {} <{}>",
+				member.vorname, member.nachname, member.ort, member.eltern_name, member.eltern_mail
+			),
+		)
+		.await;
 
-		self.send_eltern(&member.eltern_name, &member.eltern_mail, subject, body).await
+		Ok(())
 	}
 
 	pub async fn send_member_payed(&self, member: &FullTeilnehmer) -> Result<()> {
-		let subject = format!("{}", PayedSubject { member }).trim().to_string();
-		let body = format!("{}", PayedBody { member }).trim().to_string();
+		let sprache = Sprache::from_code(&member.sprache);
+		let (subject, body) = self.render(sprache, "bezahlt", context! { member }).await?;
+
+		self.send_eltern(sprache, &member.eltern_name, &member.eltern_mail, subject, body).await?;
+		self.add_to_digest(
+			Some(&member.eltern_mail),
+			digest::Art::Bezahlt,
+			format!("{} {}", member.vorname, member.nachname),
+		)
+		.await;
+		Ok(())
+	}
+
+	pub async fn send_member_cancelled(
+		&self, cancellation: &Cancellation, sprache: Sprache,
+	) -> Result<()> {
+		let (subject, body) = self.render(sprache, "abmeldung", context! { cancellation }).await?;
+
+		self.send_eltern(
+			sprache,
+			&cancellation.eltern_name,
+			&cancellation.eltern_mail,
+			subject,
+			body,
+		)
+		.await?;
+		self.add_to_digest(
+			Some(&cancellation.eltern_mail),
+			digest::Art::Abmeldung,
+			format!(
+				"{} {}, Erstattung {} Euro",
+				cancellation.vorname, cancellation.nachname, cancellation.erstattung
+			),
+		)
+		.await;
+		Ok(())
+	}
+
+	/// Send the link to continue a saved signup.
+	///
+	/// This is not sent to the additional receivers.
+	pub async fn send_signup_draft(
+		&self, sprache: Sprache, eltern_name: &str, eltern_mail: &str, link: &str,
+	) -> Result<()> {
+		let expiry_days = self.config.draft_expiry_days;
+		let (subject, body) =
+			self.render(sprache, "entwurf", context! { eltern_name, link, expiry_days }).await?;
+
+		let body = alternative(sprache, &subject, body);
+		let email = lettre::Message::builder()
+			.to((eltern_name, eltern_mail).try_into()?)
+			.from(self.config.sender_mail.clone().try_into()?)
+			.subject(subject)
+			.multipart(body)?;
 
-		self.send_eltern(&member.eltern_name, &member.eltern_mail, subject, body).await
+		self.enqueue(email).await?;
+		Ok(())
+	}
+
+	/// Queue a mail of a campaign, returns the id in the outbox.
+	///
+	/// This is not sent to the additional receivers.
+	pub async fn send_campaign(
+		&self, name: &str, mail: &str, subject: &str, body: String,
+	) -> Result<i32> {
+		let body = alternative(Sprache::De, subject, body);
+		let email = lettre::Message::builder()
+			.to((name, mail).try_into()?)
+			.from(self.config.sender_mail.clone().try_into()?)
+			.subject(subject)
+			.multipart(body)?;
+
+		self.enqueue(email).await
+	}
+
+	/// Send the link to prefill the signup with last year's data.
+	pub async fn send_returning_family(
+		&self, sprache: Sprache, eltern_name: &str, eltern_mail: &str, children: &[&str],
+		link: &str,
+	) -> Result<()> {
+		let (subject, body) =
+			self.render(sprache, "wiederkehrend", context! { eltern_name, children, link }).await?;
+
+		let body = alternative(sprache, &subject, body);
+		let email = lettre::Message::builder()
+			.to((eltern_name, eltern_mail).try_into()?)
+			.from(self.config.sender_mail.clone().try_into()?)
+			.subject(subject)
+			.multipart(body)?;
+
+		self.enqueue(email).await?;
+		Ok(())
 	}
 
 	pub async fn send_supervisor_resignup(
@@ -125,65 +414,89 @@ impl Mail {
 	) -> Result<()> {
 		let full_name = format!("{} {}", supervisor.vorname, supervisor.nachname);
 
-		let subject = format!("Zeltlager {} Betreueranmeldung", LAGER_START.year());
-		let body = format!("{}", ResignupBody { supervisor, token }).trim().to_string();
+		let (subject, body) =
+			self.render(Sprache::De, "betreuer-anmeldung", context! { supervisor, token }).await?;
 
+		let body = alternative(Sprache::De, &subject, body);
 		let email = lettre::Message::builder()
 			.to((full_name, &supervisor.mail).try_into()?)
-			.header(header::ContentType::TEXT_PLAIN)
 			.from(self.config.sender_mail.clone().try_into()?)
 			.subject(subject)
-			.body(body)?;
+			.multipart(body)?;
 
-		// Send the email
-		self.mailer()?.send(email).await?;
+		self.enqueue(email).await?;
 		Ok(())
 	}
 
 	pub async fn send_supervisor_presignup(
 		&self, supervisor: &Supervisor, grund: &str, kommentar: &str,
 	) -> Result<()> {
-		let subject =
-			format!("Zeltlager Betreueranmeldung {} {}", supervisor.vorname, supervisor.nachname);
-		let mailer = self.mailer()?;
-
+		let alter = years_old(supervisor.geburtsdatum, &season::beginn());
 		for receiver in &self.config.supervisor_mail_receivers {
-			let body = format!("{}", PresignupBody { receiver, supervisor, grund, kommentar })
-				.trim()
-				.to_string();
-
+			let (subject, body) = self
+				.render(Sprache::De, "betreuer-voranmeldung", context! {
+					receiver,
+					supervisor,
+					alter,
+					grund,
+					kommentar,
+				})
+				.await?;
+
+			let body = alternative(Sprache::De, &subject, body);
 			let email = lettre::Message::builder()
 				.to(receiver.clone().try_into()?)
-				.header(header::ContentType::TEXT_PLAIN)
 				.from(self.config.sender_mail.clone().try_into()?)
 				.subject(&subject)
-				.body(body)?;
+				.multipart(body)?;
 
-			// Send the email
-			mailer.send(email).await?;
+			self.enqueue(email).await?;
 		}
+		self.add_to_digest(
+			None,
+			digest::Art::Betreuer,
+			format!(
+				"{} {} ({} Jahre), {}",
+				supervisor.vorname, supervisor.nachname, alter, supervisor.mail
+			),
+		)
+		.await;
 
 		Ok(())
 	}
 
 	pub async fn send_supervisor_presignup_failed(&self, supervisor: &Supervisor) -> Result<()> {
-		let subject = "Zeltlager Betreueranmeldung fehlgeschlagen";
-
-		let body = format!("{}", PresignupFailedBody { supervisor }).trim().to_string();
+		let (subject, body) = self
+			.render(Sprache::De, "betreuer-voranmeldung-fehlgeschlagen", context! { supervisor })
+			.await?;
 
 		let name = format!("{} {}", supervisor.vorname, supervisor.nachname);
+		let body = alternative(Sprache::De, &subject, body);
 		let email = lettre::Message::builder()
 			.to((name, &supervisor.mail).try_into()?)
-			.header(header::ContentType::TEXT_PLAIN)
 			.from(self.config.sender_mail.clone().try_into()?)
 			.subject(subject)
-			.body(body)?;
+			.multipart(body)?;
 
-		// Send the email
-		self.mailer()?.send(email).await?;
+		self.enqueue(email).await?;
 
 		Ok(())
 	}
+
+	/// Send the daily summary to an additional receiver.
+	pub async fn send_digest(&self, receiver: &MailAddress, ctx: Value) -> Result<()> {
+		let (subject, body) = self.render(Sprache::De, "zusammenfassung", ctx).await?;
+
+		let body = alternative(Sprache::De, &subject, body);
+		let email = lettre::Message::builder()
+			.to(receiver.clone().try_into()?)
+			.from(self.config.sender_mail.clone().try_into()?)
+			.subject(subject)
+			.multipart(body)?;
+
+		self.enqueue(email).await?;
+		Ok(())
+	}
 }
 
 pub fn check_parsable(mail_addr: &str) -> Result<()> {
@@ -193,7 +506,7 @@ pub fn check_parsable(mail_addr: &str) -> Result<()> {
 
 #[cfg(test)]
 mod tests {
-	use super::check_parsable;
+	use super::{check_parsable, text_to_html};
 
 	#[test]
 	fn test_parse_mails() {
@@ -202,4 +515,23 @@ mod tests {
 			check_parsable(m).unwrap();
 		}
 	}
+
+	#[test]
+	fn html_from_text() {
+		let text = "Hallo <Name>,
+siehe https://meinzeltlager.com/packliste.
+
+- Schlafsack
+- Jacke
+
+Bankverbindung
+IBAN:       DE  13 7015
+BIC/SWIFT:  SSKMDEMMXXX";
+		let html = text_to_html(text);
+		assert!(html.starts_with("<p>Hallo &lt;Name&gt;,<br>\nsiehe <a href="));
+		assert!(html.contains(">https://meinzeltlager.com/packliste</a>.</p>"));
+		assert!(html.contains("<ul>\n<li>Schlafsack</li>\n<li>Jacke</li>\n</ul>"));
+		assert!(html.contains("<p>Bankverbindung</p>\n<table"));
+		assert!(html.contains(">BIC/SWIFT:</td><td>SSKMDEMMXXX</td></tr>\n</table>"));
+	}
 }
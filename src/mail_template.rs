@@ -0,0 +1,586 @@
+//! Mail templates that admins can change at runtime.
+//!
+//! The default templates are in `templates/mail/` and compiled into the binary. Admins can
+//! overwrite them in the admin area, changed templates are stored in the `mail_vorlage` table.
+//! Templates use the [minijinja](https://docs.rs/minijinja) syntax, e.g.
+//! `{{ member.vorname }}` or `{% if cancel_link %}…{% endif %}`.
+//!
+//! Mails to parents can have translations with the language as suffix, e.g. `anmeldung.en`. If a
+//! template has no translation, the German template is used.
+
+use anyhow::Result;
+use axum::extract::Path;
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::{Json, extract};
+use diesel::prelude::*;
+use diesel_async::RunQueryDsl;
+use minijinja::{Environment, UndefinedBehavior, Value, context};
+use serde::{Deserialize, Serialize};
+use time::PrimitiveDateTime;
+use time::macros::{date, datetime};
+use tracing::{error, warn};
+
+use crate::config::MailAddress;
+use crate::db::models::{
+	Cancellation, FullSupervisor, FullTeilnehmer, Gender, Supervisor, Teilnehmer,
+	opt_primitive_datetime,
+};
+use crate::i18n::Sprache;
+use crate::{ExtractState, GERMAN_DATE_FORMAT, WebResult, db, season};
+
+type DbResult<T> = anyhow::Result<T>;
+
+pub struct MailTemplate {
+	/// Name in the database and the api
+	pub name: &'static str,
+	pub titel: &'static str,
+	/// Template that is used if no admin changed it
+	pub standard: &'static str,
+	/// Variables of this template, additionally to `LAGER_START`, `LAGER_ENDE`, `JAHR` and `PREIS`
+	pub variablen: &'static [&'static str],
+	/// Example values for the preview and to check templates before saving
+	beispiel: fn() -> Value,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct TemplateInfo {
+	name: &'static str,
+	titel: &'static str,
+	variablen: &'static [&'static str],
+	standard: &'static str,
+	/// Current template
+	inhalt: String,
+	/// Set if an admin changed the template
+	#[serde(with = "opt_primitive_datetime")]
+	geaendert: Option<PrimitiveDateTime>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct PreviewData {
+	name: String,
+	inhalt: String,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct SaveData {
+	inhalt: String,
+}
+
+macro_rules! template {
+	($name:literal, $titel:literal, [$($var:literal),*], $beispiel:expr) => {
+		MailTemplate {
+			name: $name,
+			titel: $titel,
+			standard: include_str!(concat!("../templates/mail/", $name, ".txt")),
+			variablen: &[$($var),*],
+			beispiel: $beispiel,
+		}
+	};
+}
+
+pub static TEMPLATES: &[MailTemplate] = &[
+	template!("anmeldung-betreff", "Anmeldung (Betreff)", ["member"], sample_signup),
+	template!("anmeldung", "Anmeldung", ["member", "cancel_link"], sample_signup),
+	template!("anmeldung-betreff.en", "Anmeldung Englisch (Betreff)", ["member"], sample_signup),
+	template!("anmeldung.en", "Anmeldung Englisch", ["member", "cancel_link"], sample_signup),
+	template!("bezahlt-betreff", "Zahlungsbestätigung (Betreff)", ["member"], sample_payed),
+	template!("bezahlt", "Zahlungsbestätigung", ["member"], sample_payed),
+	template!(
+		"bezahlt-betreff.en",
+		"Zahlungsbestätigung Englisch (Betreff)",
+		["member"],
+		sample_payed
+	),
+	template!("bezahlt.en", "Zahlungsbestätigung Englisch", ["member"], sample_payed),
+	template!("abmeldung-betreff", "Abmeldung (Betreff)", ["cancellation"], sample_cancel),
+	template!("abmeldung", "Abmeldung", ["cancellation"], sample_cancel),
+	template!(
+		"abmeldung-betreff.en",
+		"Abmeldung Englisch (Betreff)",
+		["cancellation"],
+		sample_cancel
+	),
+	template!("abmeldung.en", "Abmeldung Englisch", ["cancellation"], sample_cancel),
+	template!("entwurf-betreff", "Angefangene Anmeldung (Betreff)", [], sample_draft),
+	template!(
+		"entwurf",
+		"Angefangene Anmeldung",
+		["eltern_name", "link", "expiry_days"],
+		sample_draft
+	),
+	template!("entwurf-betreff.en", "Angefangene Anmeldung Englisch (Betreff)", [], sample_draft),
+	template!(
+		"entwurf.en",
+		"Angefangene Anmeldung Englisch",
+		["eltern_name", "link", "expiry_days"],
+		sample_draft
+	),
+	template!("wiederkehrend-betreff", "Wiederkehrende Familie (Betreff)", [], sample_returning),
+	template!(
+		"wiederkehrend",
+		"Wiederkehrende Familie",
+		["eltern_name", "children", "link"],
+		sample_returning
+	),
+	template!(
+		"wiederkehrend-betreff.en",
+		"Wiederkehrende Familie Englisch (Betreff)",
+		[],
+		sample_returning
+	),
+	template!(
+		"wiederkehrend.en",
+		"Wiederkehrende Familie Englisch",
+		["eltern_name", "children", "link"],
+		sample_returning
+	),
+	template!(
+		"betreuer-anmeldung-betreff",
+		"Betreuer-Anmeldelink (Betreff)",
+		["supervisor"],
+		sample_resignup
+	),
+	template!(
+		"betreuer-anmeldung",
+		"Betreuer-Anmeldelink",
+		["supervisor", "token"],
+		sample_resignup
+	),
+	template!(
+		"betreuer-voranmeldung-betreff",
+		"Neue Betreuer-Anmeldung (Betreff)",
+		["supervisor"],
+		sample_presignup
+	),
+	template!(
+		"betreuer-voranmeldung",
+		"Neue Betreuer-Anmeldung",
+		["receiver", "supervisor", "alter", "grund", "kommentar"],
+		sample_presignup
+	),
+	template!(
+		"betreuer-voranmeldung-fehlgeschlagen-betreff",
+		"Betreuer-Anmeldung fehlgeschlagen (Betreff)",
+		["supervisor"],
+		sample_presignup_failed
+	),
+	template!(
+		"betreuer-voranmeldung-fehlgeschlagen",
+		"Betreuer-Anmeldung fehlgeschlagen",
+		["supervisor"],
+		sample_presignup_failed
+	),
+	template!("zusammenfassung-betreff", "Tägliche Zusammenfassung (Betreff)", [], sample_digest),
+	template!(
+		"zusammenfassung",
+		"Tägliche Zusammenfassung",
+		["receiver", "anmeldungen", "zahlungen", "abmeldungen", "betreuer", "fehler"],
+		sample_digest
+	),
+];
+
+pub fn find(name: &str) -> Option<&'static MailTemplate> {
+	TEMPLATES.iter().find(|t| t.name == name)
+}
+
+/// Name of the translated template, falls back to the German template.
+pub fn localized(name: &str, sprache: Sprache) -> String {
+	if sprache != Sprache::De {
+		let translated = format!("{name}.{}", sprache.code());
+		if find(&translated).is_some() {
+			return translated;
+		}
+	}
+	name.to_string()
+}
+
+fn environment() -> Environment<'static> {
+	let mut env = Environment::new();
+	// Lines with only a tag like `{% if … %}` vanish
+	env.set_trim_blocks(true);
+	env.set_lstrip_blocks(true);
+	// Fail on typos in variable names instead of rendering nothing
+	env.set_undefined_behavior(UndefinedBehavior::Strict);
+	let (beginn, ende) = (season::beginn(), season::ende());
+	env.add_global("LAGER_START", beginn.format(GERMAN_DATE_FORMAT).unwrap());
+	env.add_global("LAGER_ENDE", ende.format(GERMAN_DATE_FORMAT).unwrap());
+	env.add_global("JAHR", beginn.year());
+	env.add_global("PREIS", season::preis());
+	env
+}
+
+/// Render a template source with the given variables.
+pub fn render_source(source: &str, ctx: Value) -> Result<String, minijinja::Error> {
+	Ok(environment().render_str(source, ctx)?.trim().to_string())
+}
+
+/// Render the current version of a template.
+///
+/// If a changed template fails to render, the default template is used.
+pub async fn render(db: &db::Database, name: &str, ctx: Value) -> Result<String> {
+	use db::schema::mail_vorlage;
+
+	let template = find(name).ok_or_else(|| anyhow::anyhow!("Unknown mail template {name}"))?;
+	let source = mail_vorlage::table
+		.find(name)
+		.select(mail_vorlage::inhalt)
+		.first::<String>(&mut db.get().await?)
+		.await
+		.optional()?;
+	if let Some(source) = source {
+		match render_source(&source, ctx.clone()) {
+			Ok(r) => return Ok(r),
+			Err(error) => error!(name, %error, "Failed to render changed mail template"),
+		}
+	}
+	Ok(render_source(template.standard, ctx)?)
+}
+
+/// Render a template with the example values, returns an error message for admins.
+fn check(template: &MailTemplate, source: &str) -> Result<String, String> {
+	render_source(source, (template.beispiel)()).map_err(|error| {
+		let mut msg = error.to_string();
+		let mut source = std::error::Error::source(&error);
+		while let Some(e) = source {
+			msg.push_str(&format!(": {e}"));
+			source = e.source();
+		}
+		msg
+	})
+}
+
+/// List all mail templates with their current content.
+pub async fn list_templates(
+	extract::State(state): ExtractState,
+) -> WebResult<Json<Vec<TemplateInfo>>> {
+	match async {
+		use db::schema::mail_vorlage;
+
+		let changed = mail_vorlage::table
+			.load::<(String, String, PrimitiveDateTime)>(&mut state.db.get().await?)
+			.await?;
+		DbResult::Ok(
+			TEMPLATES
+				.iter()
+				.map(|t| {
+					let changed = changed.iter().find(|(name, _, _)| name == t.name);
+					TemplateInfo {
+						name: t.name,
+						titel: t.titel,
+						variablen: t.variablen,
+						standard: t.standard,
+						inhalt: changed
+							.map(|(_, inhalt, _)| inhalt.clone())
+							.unwrap_or_else(|| t.standard.to_string()),
+						geaendert: changed.map(|(_, _, geaendert)| *geaendert),
+					}
+				})
+				.collect(),
+		)
+	}
+	.await
+	{
+		Err(error) => {
+			warn!(%error, "Failed to list mail templates");
+			crate::error_response(&state)
+		}
+		Ok(r) => Ok(Json(r)),
+	}
+}
+
+/// Render a template with example values.
+pub async fn preview(Json(data): Json<PreviewData>) -> WebResult<Json<String>> {
+	let Some(template) = find(&data.name) else {
+		return Err((StatusCode::NOT_FOUND, "Unbekannte Vorlage").into_response());
+	};
+	check(template, &data.inhalt)
+		.map(Json)
+		.map_err(|msg| (StatusCode::BAD_REQUEST, msg).into_response())
+}
+
+/// Change a template, templates that fail to render are refused.
+pub async fn save_template(
+	extract::State(state): ExtractState, Path(name): Path<String>, Json(data): Json<SaveData>,
+) -> WebResult<&'static str> {
+	let Some(template) = find(&name) else {
+		return Err((StatusCode::NOT_FOUND, "Unbekannte Vorlage").into_response());
+	};
+	if let Err(msg) = check(template, &data.inhalt) {
+		return Err((StatusCode::BAD_REQUEST, msg).into_response());
+	}
+
+	match async {
+		use db::schema::mail_vorlage;
+
+		diesel::insert_into(mail_vorlage::table)
+			.values((mail_vorlage::name.eq(&name), mail_vorlage::inhalt.eq(&data.inhalt)))
+			.on_conflict(mail_vorlage::name)
+			.do_update()
+			.set((
+				mail_vorlage::inhalt.eq(&data.inhalt),
+				mail_vorlage::geaendert.eq(diesel::dsl::now),
+			))
+			.execute(&mut state.db.get().await?)
+			.await?;
+		DbResult::Ok(())
+	}
+	.await
+	{
+		Err(error) => {
+			warn!(%error, "Failed to save mail template");
+			crate::error_response(&state)
+		}
+		Ok(()) => Ok("Success"),
+	}
+}
+
+/// Go back to the default template.
+pub async fn reset_template(
+	extract::State(state): ExtractState, Path(name): Path<String>,
+) -> WebResult<&'static str> {
+	match async {
+		use db::schema::mail_vorlage;
+
+		diesel::delete(mail_vorlage::table.find(&name)).execute(&mut state.db.get().await?).await?;
+		DbResult::Ok(())
+	}
+	.await
+	{
+		Err(error) => {
+			warn!(%error, "Failed to reset mail template");
+			crate::error_response(&state)
+		}
+		Ok(()) => Ok("Success"),
+	}
+}
+
+fn sample_member() -> FullTeilnehmer {
+	FullTeilnehmer {
+		id: 1,
+		vorname: "Max".into(),
+		nachname: "Mustermann".into(),
+		geburtsdatum: date!(2015 - 05 - 01),
+		geschlecht: Gender::Male,
+		schwimmer: true,
+		vegetarier: false,
+		tetanus_impfung: true,
+		eltern_name: "Erika Mustermann".into(),
+		eltern_mail: "erika@example.com".into(),
+		eltern_handynummer: "0170 1234567".into(),
+		strasse: "Musterstraße".into(),
+		hausnummer: "1".into(),
+		ort: "München".into(),
+		plz: "80639".into(),
+		kommentar: String::new(),
+		agb: true,
+		anmeldedatum: datetime!(2026-03-01 12:00),
+		bezahlt: true,
+		anwesend: false,
+		allergien: String::new(),
+		unvertraeglichkeiten: String::new(),
+		medikamente: String::new(),
+		krankenversicherung: "gesetzlich".into(),
+		land: "Deutschland".into(),
+		krankheiten: String::new(),
+		eigenanreise: false,
+		daten_behalten: false,
+		sprache: "de".into(),
+		geloescht: None,
+		version: 0,
+		lager: None,
+	}
+}
+
+fn sample_supervisor() -> FullSupervisor {
+	FullSupervisor {
+		id: 1,
+		vorname: "Erika".into(),
+		nachname: "Musterfrau".into(),
+		geburtsdatum: date!(2000 - 01 - 01),
+		geschlecht: Gender::Female,
+		juleica_nummer: None,
+		mail: "erika@example.com".into(),
+		handynummer: "0170 1234567".into(),
+		strasse: None,
+		hausnummer: None,
+		ort: None,
+		plz: None,
+		kommentar: None,
+		agb: true,
+		selbsterklaerung: true,
+		fuehrungszeugnis_ausstellung: None,
+		fuehrungszeugnis_eingesehen: None,
+		anmeldedatum: datetime!(2026-03-01 12:00),
+		allergien: None,
+		unvertraeglichkeiten: None,
+		medikamente: None,
+		krankenversicherung: None,
+		vegetarier: None,
+		tetanus_impfung: None,
+		land: None,
+		krankheiten: None,
+		juleica_gueltig_bis: None,
+		signup_token: None,
+		signup_token_time: None,
+		geloescht: None,
+		version: 0,
+	}
+}
+
+fn sample_signup() -> Value {
+	let m = sample_member();
+	let member = Teilnehmer {
+		vorname: m.vorname,
+		nachname: m.nachname,
+		geburtsdatum: m.geburtsdatum,
+		geschlecht: m.geschlecht,
+		schwimmer: m.schwimmer,
+		vegetarier: m.vegetarier,
+		tetanus_impfung: m.tetanus_impfung,
+		eltern_name: m.eltern_name,
+		eltern_mail: m.eltern_mail,
+		eltern_handynummer: m.eltern_handynummer,
+		strasse: m.strasse,
+		hausnummer: m.hausnummer,
+		ort: m.ort,
+		plz: m.plz,
+		kommentar: m.kommentar,
+		agb: m.agb,
+		allergien: m.allergien,
+		unvertraeglichkeiten: m.unvertraeglichkeiten,
+		medikamente: m.medikamente,
+		krankenversicherung: m.krankenversicherung,
+		land: m.land,
+		krankheiten: m.krankheiten,
+		eigenanreise: m.eigenanreise,
+		daten_behalten: m.daten_behalten,
+		sprache: m.sprache,
+	};
+	context! { member, cancel_link => "https://meinzeltlager.com/abmeldung?token=beispiel" }
+}
+
+fn sample_payed() -> Value {
+	context! { member => sample_member() }
+}
+
+fn sample_cancel() -> Value {
+	let m = sample_member();
+	let cancellation = Cancellation {
+		id: 1,
+		vorname: m.vorname,
+		nachname: m.nachname,
+		geburtsdatum: m.geburtsdatum,
+		eltern_name: m.eltern_name,
+		eltern_mail: m.eltern_mail,
+		anmeldedatum: m.anmeldedatum,
+		stornodatum: datetime!(2026-05-01 12:00),
+		bezahlt: true,
+		erstattung_prozent: 50,
+		erstattung: 125,
+		erstattet: false,
+		lager: m.lager,
+		geloescht: None,
+	};
+	context! { cancellation }
+}
+
+fn sample_draft() -> Value {
+	context! {
+		eltern_name => "Erika Mustermann",
+		link => "https://meinzeltlager.com/anmeldung?entwurf=beispiel",
+		expiry_days => 30,
+	}
+}
+
+fn sample_returning() -> Value {
+	context! {
+		eltern_name => "Erika Mustermann",
+		children => ["Max", "Maria"],
+		link => "https://meinzeltlager.com/anmeldung?familie=beispiel",
+	}
+}
+
+fn sample_resignup() -> Value {
+	context! { supervisor => sample_supervisor(), token => "beispiel" }
+}
+
+fn presignup_supervisor() -> Supervisor {
+	let s = sample_supervisor();
+	Supervisor {
+		vorname: s.vorname,
+		nachname: s.nachname,
+		geburtsdatum: s.geburtsdatum,
+		geschlecht: s.geschlecht,
+		juleica_nummer: s.juleica_nummer,
+		mail: s.mail,
+		handynummer: s.handynummer,
+		strasse: s.strasse,
+		hausnummer: s.hausnummer,
+		ort: s.ort,
+		plz: s.plz,
+		kommentar: s.kommentar,
+		agb: s.agb,
+		selbsterklaerung: s.selbsterklaerung,
+		fuehrungszeugnis_ausstellung: s.fuehrungszeugnis_ausstellung,
+		fuehrungszeugnis_eingesehen: s.fuehrungszeugnis_eingesehen,
+		allergien: s.allergien,
+		unvertraeglichkeiten: s.unvertraeglichkeiten,
+		medikamente: s.medikamente,
+		krankenversicherung: s.krankenversicherung,
+		vegetarier: s.vegetarier,
+		tetanus_impfung: s.tetanus_impfung,
+		land: s.land,
+		krankheiten: s.krankheiten,
+		juleica_gueltig_bis: s.juleica_gueltig_bis,
+	}
+}
+
+fn sample_presignup() -> Value {
+	context! {
+		receiver => MailAddress { name: Some("Sebi".into()), address: "sebi@example.com".into() },
+		supervisor => presignup_supervisor(),
+		alter => 15,
+		grund => "Ich war selbst als Kind dabei.",
+		kommentar => "",
+	}
+}
+
+fn sample_presignup_failed() -> Value {
+	context! { supervisor => presignup_supervisor() }
+}
+
+fn sample_digest() -> Value {
+	context! {
+		receiver => MailAddress { name: Some("Sebi".into()), address: "sebi@example.com".into() },
+		anmeldungen => ["Max Mustermann (München), Erika Mustermann <erika@example.com>"],
+		zahlungen => ["Max Mustermann", "Maria Mustermann"],
+		abmeldungen => ["Moritz Beispiel, Erstattung 125 Euro"],
+		betreuer => ["Anna Beispiel (17 Jahre), anna@example.com"],
+		fehler => Vec::<String>::new(),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn default_templates_render() {
+		for t in TEMPLATES {
+			let text = check(t, t.standard).unwrap_or_else(|e| panic!("{}: {e}", t.name));
+			assert!(!text.contains("{{"), "{}", t.name);
+		}
+	}
+
+	#[test]
+	fn refuse_unknown_variables() {
+		let t = find("bezahlt").unwrap();
+		assert!(check(t, "{{ member.vornahme }}").is_err());
+		assert!(check(t, "{% if member.vorname %}").is_err());
+		assert_eq!(
+			check(t, "{{ member.vorname }} {{ JAHR }}").unwrap(),
+			format!("Max {}", season::beginn().year())
+		);
+	}
+}
@@ -0,0 +1,259 @@
+//! Transports that deliver mails from the outbox.
+//!
+//! Besides SMTP, mails can be written into a directory as `.eml` files or kept in memory. Both
+//! are meant for development and tests, the captured mails can be browsed by admins in dev mode.
+
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use anyhow::{Result, bail};
+use axum::body::Body;
+use axum::extract::Path;
+use axum::http::StatusCode;
+use axum::http::header::CONTENT_TYPE;
+use axum::response::{IntoResponse, Response};
+use axum::{Json, extract};
+use base64::Engine;
+use lettre::address::Envelope;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Tokio1Executor};
+use serde::Serialize;
+use time::{OffsetDateTime, PrimitiveDateTime};
+use tracing::warn;
+
+use crate::db::models::primitive_datetime;
+use crate::{ExtractState, WebResult};
+
+/// Number of mails that are kept in memory or listed from a directory.
+pub const CAPTURE_LIMIT: usize = 100;
+
+#[derive(Clone, Debug, Serialize)]
+pub struct CapturedMail {
+	/// File name or number in memory
+	id: String,
+	#[serde(with = "primitive_datetime")]
+	zeit: PrimitiveDateTime,
+	an: String,
+	betreff: String,
+	#[serde(skip)]
+	nachricht: String,
+}
+
+/// Mails captured by the memory transport, oldest first.
+#[derive(Clone, Debug, Default)]
+pub struct Captured {
+	mails: Arc<Mutex<VecDeque<CapturedMail>>>,
+	count: Arc<Mutex<u64>>,
+}
+
+pub enum Transport {
+	Smtp(AsyncSmtpTransport<Tokio1Executor>),
+	/// Write mails as `.eml` files into this directory
+	File(PathBuf),
+	Memory(Captured),
+}
+
+fn now() -> PrimitiveDateTime {
+	let now = OffsetDateTime::now_utc();
+	PrimitiveDateTime::new(now.date(), now.time())
+}
+
+/// Decode encoded words like `=?utf-8?b?…?=` that lettre uses for non-ascii headers.
+fn decode_header(value: &str) -> String {
+	value
+		.split_whitespace()
+		.map(|word| {
+			const PREFIX: &str = "=?utf-8?b?";
+			let decoded = word
+				.get(..PREFIX.len())
+				.filter(|p| p.eq_ignore_ascii_case(PREFIX))
+				.and_then(|_| word[PREFIX.len()..].strip_suffix("?="))
+				.and_then(|w| base64::engine::general_purpose::STANDARD.decode(w).ok())
+				.and_then(|b| String::from_utf8(b).ok());
+			match decoded {
+				Some(s) => (s, true),
+				None => (word.to_string(), false),
+			}
+		})
+		.fold((String::new(), false), |(mut res, last_encoded), (word, encoded)| {
+			// Whitespace between encoded words is ignored
+			if !(res.is_empty() || last_encoded && encoded) {
+				res.push(' ');
+			}
+			res.push_str(&word);
+			(res, encoded)
+		})
+		.0
+}
+
+/// Get a header from a formatted mail.
+fn header(message: &str, name: &str) -> String {
+	let mut value: Option<String> = None;
+	for line in message.lines() {
+		if line.is_empty() {
+			break;
+		}
+		if let Some(v) = value.as_mut() {
+			// Folded header
+			if line.starts_with([' ', '\t']) {
+				v.push(' ');
+				v.push_str(line.trim());
+				continue;
+			}
+			break;
+		}
+		if let Some((n, v)) = line.split_once(':') {
+			if n.eq_ignore_ascii_case(name) {
+				value = Some(v.trim().to_string());
+			}
+		}
+	}
+	value.map(|v| decode_header(&v)).unwrap_or_default()
+}
+
+impl CapturedMail {
+	fn new(id: String, zeit: PrimitiveDateTime, nachricht: String) -> Self {
+		Self {
+			id,
+			zeit,
+			an: header(&nachricht, "To"),
+			betreff: header(&nachricht, "Subject"),
+			nachricht,
+		}
+	}
+}
+
+impl Captured {
+	fn push(&self, message: &[u8]) {
+		let id = {
+			let mut count = self.count.lock().unwrap();
+			*count += 1;
+			*count
+		};
+		let mail =
+			CapturedMail::new(id.to_string(), now(), String::from_utf8_lossy(message).into());
+		let mut mails = self.mails.lock().unwrap();
+		mails.push_back(mail);
+		if mails.len() > CAPTURE_LIMIT {
+			mails.pop_front();
+		}
+	}
+
+	/// All captured mails, newest first.
+	pub fn mails(&self) -> Vec<CapturedMail> {
+		self.mails.lock().unwrap().iter().rev().cloned().collect()
+	}
+}
+
+impl Transport {
+	pub async fn send_raw(&self, envelope: &Envelope, message: &[u8]) -> Result<()> {
+		match self {
+			Self::Smtp(t) => {
+				t.send_raw(envelope, message).await?;
+			}
+			Self::File(dir) => {
+				std::fs::create_dir_all(dir)?;
+				// Sort by name to get the newest mails
+				let name = format!("{}.eml", OffsetDateTime::now_utc().unix_timestamp_nanos());
+				std::fs::write(dir.join(name), message)?;
+			}
+			Self::Memory(captured) => captured.push(message),
+		}
+		Ok(())
+	}
+
+	/// The last captured mails, newest first.
+	pub fn captured(&self) -> Result<Vec<CapturedMail>> {
+		match self {
+			Self::Smtp(_) => Ok(Vec::new()),
+			Self::File(dir) => {
+				let mut names = match std::fs::read_dir(dir) {
+					Ok(entries) => entries
+						.filter_map(|e| e.ok()?.file_name().into_string().ok())
+						.filter(|n| n.ends_with(".eml"))
+						.collect::<Vec<_>>(),
+					Err(error) if error.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+					Err(error) => return Err(error.into()),
+				};
+				names.sort_unstable_by(|a, b| b.cmp(a));
+				names
+					.into_iter()
+					.take(CAPTURE_LIMIT)
+					.map(|name| {
+						let path = dir.join(&name);
+						let zeit = OffsetDateTime::from(std::fs::metadata(&path)?.modified()?);
+						let nachricht = String::from_utf8_lossy(&std::fs::read(&path)?).into();
+						Ok(CapturedMail::new(
+							name,
+							PrimitiveDateTime::new(zeit.date(), zeit.time()),
+							nachricht,
+						))
+					})
+					.collect()
+			}
+			Self::Memory(captured) => Ok(captured.mails()),
+		}
+	}
+}
+
+/// List the last captured mails.
+pub async fn list_captured(
+	extract::State(state): ExtractState,
+) -> WebResult<Json<Vec<CapturedMail>>> {
+	match state.mail.mailer().and_then(|m| m.captured()) {
+		Err(error) => {
+			warn!(%error, "Failed to list captured mails");
+			crate::error_response(&state)
+		}
+		Ok(r) => Ok(Json(r)),
+	}
+}
+
+/// Get a captured mail as `.eml` file.
+pub async fn get_captured(
+	extract::State(state): ExtractState, Path(id): Path<String>,
+) -> WebResult<Response> {
+	let res = state.mail.mailer().and_then(|m| m.captured()).and_then(|mails| {
+		let Some(mail) = mails.into_iter().find(|m| m.id == id) else {
+			bail!("Mail {id} not found");
+		};
+		Ok(mail)
+	});
+	match res {
+		Err(error) => {
+			warn!(%error, "Failed to get captured mail");
+			Err((StatusCode::NOT_FOUND, "Page not found").into_response())
+		}
+		Ok(mail) => Ok(Response::builder()
+			.header(CONTENT_TYPE, "message/rfc822")
+			.body(Body::from(mail.nachricht))
+			.unwrap()),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn capture_in_memory() {
+		let captured = Captured::default();
+		let transport = Transport::Memory(captured.clone());
+		let email = lettre::Message::builder()
+			.from("Zeltlager <zeltlager@example.com>".parse().unwrap())
+			.to("Erika Mustermann <erika@example.com>".parse().unwrap())
+			.subject("Zahlungsbestätigung für das Zeltlager")
+			.body(String::from("Hallo"))
+			.unwrap();
+		for _ in 0..CAPTURE_LIMIT + 1 {
+			futures::executor::block_on(transport.send_raw(email.envelope(), &email.formatted()))
+				.unwrap();
+		}
+
+		let mails = captured.mails();
+		assert_eq!(mails.len(), CAPTURE_LIMIT);
+		assert_eq!(mails[0].id, (CAPTURE_LIMIT + 1).to_string());
+		assert_eq!(mails[0].betreff, "Zahlungsbestätigung für das Zeltlager");
+		assert_eq!(mails[0].an, "\"Erika Mustermann\" <erika@example.com>");
+	}
+}
@@ -5,17 +5,18 @@ use std::collections::HashMap;
 use std::convert::{Infallible, TryFrom, TryInto};
 use std::fs;
 use std::net::SocketAddr;
-use std::sync::{Arc, LazyLock, Mutex, RwLock};
+use std::sync::{Arc, Mutex, RwLock};
 
-use anyhow::{Result, format_err};
+use anyhow::{Result, bail, format_err};
 use axum::body::{Body, Bytes};
 use axum::error_handling::HandleErrorLayer;
+use axum::extract::DefaultBodyLimit;
 use axum::extract::{ConnectInfo, Query, Request};
 use axum::handler::HandlerWithoutStateExt;
 use axum::http::header::LAST_MODIFIED;
 use axum::http::{self, StatusCode, Uri};
 use axum::response::{IntoResponse, Response};
-use axum::routing::{any, get, post};
+use axum::routing::{any, delete, get, patch, post, put};
 use axum::{Json, Router, extract};
 use axum_oidc::error::MiddlewareError;
 use axum_oidc::openidconnect::{ClientId, ClientSecret, IssuerUrl};
@@ -25,8 +26,8 @@ use axum_oidc::{
 use clap::Parser;
 use lettre::message::Mailbox;
 use serde::{Deserialize, Serialize};
+use time::Duration;
 use time::macros::format_description;
-use time::{Date, Duration};
 use tower::{Layer, ServiceBuilder, ServiceExt};
 use tower_http::services::ServeDir;
 use tower_http::trace::TraceLayer;
@@ -36,18 +37,37 @@ use tower_sessions_sqlx_store::sqlx::Pool;
 use tracing::{error, info, warn};
 
 mod admin;
+mod audit;
 mod auth;
 mod basic;
+mod bounce;
+mod campaign;
+mod cancel;
 mod config;
+mod consent;
 mod db;
+mod digest;
+mod dkim;
+mod draft;
 mod erwischt;
 mod etag;
+mod form_schema;
+mod i18n;
 mod images;
+mod library;
 mod mail;
+mod mail_template;
+mod mail_transport;
 mod management;
+mod outbox;
+mod pdf;
+mod returning;
+mod season;
 mod signup;
 mod signup_supervisor;
+mod statistics;
 mod thumbs;
+mod trash;
 
 use crate::config::{Config, MailAddress};
 
@@ -59,9 +79,6 @@ const GERMAN_DATE_FORMAT: &[time::format_description::BorrowedFormatItem<'_>] =
 	format_description!("[day].[month].[year]");
 const PRIMITIVE_DATE_TIME_FORMAT: &[time::format_description::BorrowedFormatItem<'_>] =
 	format_description!("[year]-[month]-[day] [hour]:[minute]:[second]");
-const LAGER_START_STR: &str = include_str!("../frontend/lager-start.txt");
-static LAGER_START: LazyLock<Date> =
-	LazyLock::new(|| Date::parse(LAGER_START_STR.trim(), ISO_DATE_FORMAT).unwrap());
 
 fn cookie_maxtime() -> Duration { Duration::days(2) }
 fn ratelimit_duration() -> Duration { Duration::days(1) }
@@ -314,6 +331,14 @@ impl Config {
 		if let Some(addr) = &self.test_mail {
 			mail::check_parsable(addr)?;
 		}
+		if let config::AdditionalMailMode::Digest { hour } = self.additional_mail_mode {
+			if hour >= 24 {
+				bail!("The hour of the digest must be less than 24");
+			}
+		}
+		if self.cancellation.is_some() && self.link_secret.is_none() {
+			bail!("Cancellations need a link_secret");
+		}
 		Ok(())
 	}
 }
@@ -481,7 +506,8 @@ async fn main() -> Result<()> {
 	let database = db::Database::new(&config)?;
 	database.run_migrations().await?;
 
-	let mail = mail::Mail::new(config.clone());
+	let mail = mail::Mail::new(config.clone(), database.clone())?;
+	season::init(&database, &config).await?;
 
 	let address = config.bind_address.clone();
 	let (recreate_webserver, mut recreate_webserver_recv) = tokio::sync::mpsc::channel(1);
@@ -495,6 +521,12 @@ async fn main() -> Result<()> {
 		log_mutex: Mutex::new(()),
 	});
 
+	tokio::spawn(outbox::run(state.clone()));
+	tokio::spawn(bounce::run(state.clone()));
+	tokio::spawn(digest::run(state.clone()));
+	tokio::spawn(trash::run(state.clone()));
+	tokio::spawn(draft::run(state.clone()));
+
 	// Start thumbnail creator
 	let mut started_thumbs = false;
 
@@ -516,20 +548,65 @@ async fn main() -> Result<()> {
 		}
 		started_thumbs = true;
 
-		let api_admin_routes = Router::new()
+		let mut api_admin_routes = Router::new()
 			.route("/mails", get(admin::download_mails))
 			.route("/teilnehmer", get(admin::download_members))
 			.route("/betreuer", get(admin::download_supervisors))
+			.route("/unzustellbar/remove", post(bounce::remove))
 			.route("/lager", get(admin::lager_info).delete(admin::remove_lager))
+			.route("/stornierung", get(admin::download_cancellations))
+			.route("/stornierung/erstattet", post(admin::set_cancellation_refunded))
 			.route("/teilnehmer/remove", post(admin::remove_member))
 			.route("/teilnehmer/edit", post(admin::edit_member))
+			.route("/teilnehmer/{id}", patch(admin::patch_member))
+			.route("/teilnehmer/duplicates", get(admin::list_duplicate_members))
+			.route("/zustimmungen", get(consent::list_missing))
+			.route("/rundmail", get(campaign::list_campaigns).post(campaign::send))
+			.route(
+				"/rundmail/zielgruppen",
+				get(campaign::list_audiences).post(campaign::save_audience),
+			)
+			.route("/rundmail/zielgruppen/{id}", delete(campaign::delete_audience))
+			.route("/rundmail/vorschau", post(campaign::preview))
+			.route("/postausgang", get(outbox::list_unsent))
+			.route("/postausgang/resend", post(outbox::resend))
+			.route("/mail-vorlagen", get(mail_template::list_templates))
+			.route("/mail-vorlagen/vorschau", post(mail_template::preview))
+			.route(
+				"/mail-vorlagen/{name}",
+				post(mail_template::save_template).delete(mail_template::reset_template),
+			)
+			.route("/dokumente", get(library::list_documents))
+			.route(
+				"/dokumente/{name}",
+				put(library::upload_document)
+					.post(library::edit_document)
+					.delete(library::delete_document)
+					.layer(DefaultBodyLimit::max(library::MAX_SIZE)),
+			)
+			.route("/teilnehmer/merge", post(admin::merge_members))
 			.route("/betreuer/remove", post(admin::remove_supervisor))
 			.route("/betreuer/edit", post(admin::edit_supervisor))
+			.route("/betreuer/{id}", patch(admin::patch_supervisor))
+			.route("/papierkorb", get(trash::list))
+			.route("/papierkorb/restore", post(trash::restore))
 			.route("/user/list", get(admin::list_users))
 			.route("/user/reset_password", post(admin::reset_password))
 			.route("/user/create", post(admin::create_user))
 			.route("/imageLink", post(admin::create_image_link).delete(admin::delete_image_link))
 			.route("/imageLink/list", get(admin::list_image_links))
+			.route("/protokoll", get(audit::search))
+			.route("/saisons", get(season::list).post(season::save))
+			.route("/saisons/aktuell", post(season::set_current))
+			.route("/statistik", get(statistics::trends));
+		if args.dev {
+			// Mails of the file or memory transport
+			api_admin_routes = api_admin_routes
+				.route("/testmails", get(mail_transport::list_captured))
+				.route("/testmails/{id}", get(mail_transport::get_captured));
+		}
+		let api_admin_routes = api_admin_routes
+			.layer(axum::middleware::from_fn_with_state(state.clone(), audit::record))
 			.layer(axum::middleware::from_fn_with_state(
 				HasRolePredicate::new(state.clone(), auth::Roles::Admin, true),
 				has_role,
@@ -541,6 +618,7 @@ async fn main() -> Result<()> {
 			.route("/game", post(erwischt::create_game))
 			.route("/game/setCatch", post(erwischt::catch))
 			.route("/game/insert", post(erwischt::insert))
+			.layer(axum::middleware::from_fn_with_state(state.clone(), audit::record))
 			.layer(axum::middleware::from_fn_with_state(
 				HasRolePredicate::new(state.clone(), auth::Roles::Erwischt, true),
 				has_role,
@@ -551,9 +629,17 @@ async fn main() -> Result<()> {
 			.route("/login-nojs", post(auth::login_nojs))
 			.route("/logout", get(auth::logout))
 			.route("/menu", get(menu))
+			.route("/form-schema/{form}", get(form_schema::form_schema))
+			.route("/lager", get(season::dates))
 			.route("/signup-state", get(signup::signup_state))
 			.route("/signup", post(signup::signup))
 			.route("/signup-nojs", post(signup::signup_nojs))
+			.route("/signup-draft", post(draft::save_draft))
+			.route("/signup-draft/load", post(draft::load_draft))
+			.route("/returning-signup", post(returning::request_link))
+			.route("/returning-signup/data", post(returning::get_data))
+			.route("/cancel-info", post(cancel::cancel_info))
+			.route("/cancel", post(cancel::cancel))
 			.route("/signup-supervisor", post(signup_supervisor::signup))
 			.route("/signup-supervisor-nojs", post(signup_supervisor::signup_nojs))
 			.route("/resignup-supervisor", post(signup_supervisor::resignup))
@@ -600,6 +686,13 @@ async fn main() -> Result<()> {
 			);
 		}
 
+		app = app.merge(
+			Router::new()
+				.route("/dokumente/{name}", get(library::get_document))
+				.route("/dokumente/{name}/{version}", get(library::get_document_version))
+				.layer(etag_layer.clone()),
+		);
+
 		let rewrite_img_path_middleware =
 			tower::util::MapRequestLayer::new(move |req| rewrite_images_path(&image_dirs, req));
 
@@ -1,14 +1,17 @@
+use std::convert::TryInto;
 use std::io;
 use std::io::Write;
 
-use anyhow::Result;
+use anyhow::{Result, bail};
 use diesel::prelude::*;
 use diesel_async::RunQueryDsl;
 use scrypt::Scrypt;
 use scrypt::password_hash::PasswordHasher;
+use sha2::{Digest, Sha256};
+use time::{Date, OffsetDateTime};
 
 use crate::config::{Action, Config};
-use crate::db;
+use crate::{ISO_DATE_FORMAT, consent, db, dkim};
 
 fn ask_username() -> String {
 	print!("Enter username: ");
@@ -29,6 +32,10 @@ fn confirm(msg: &str) -> bool {
 pub(crate) async fn cmd_action(config: &Config, action: Action) -> Result<()> {
 	use crate::db::schema::users::dsl::*;
 
+	if let Action::Dkim = action {
+		return check_dkim(config);
+	}
+
 	let db = crate::db::Database::new(config)?;
 	let mut connection = db.get().await?;
 
@@ -74,7 +81,55 @@ pub(crate) async fn cmd_action(config: &Config, action: Action) -> Result<()> {
 				println!("Deleted {} user", count);
 			}
 		}
+		Action::Dkim => unreachable!(),
+		Action::AddDocument { document, version, file, url, valid_from } => {
+			use crate::db::schema::dokument_version;
+
+			if ![consent::AGB, consent::DATENSCHUTZ, consent::SELBSTERKLAERUNG]
+				.contains(&document.as_str())
+			{
+				bail!("Unknown document '{}'", document);
+			}
+			let valid_from = match valid_from {
+				Some(d) => Date::parse(&d, ISO_DATE_FORMAT)?,
+				None => OffsetDateTime::now_utc().date(),
+			};
+			let hash = format!("{:x}", Sha256::digest(std::fs::read(&file)?));
+
+			diesel::insert_into(dokument_version::table)
+				.values((
+					dokument_version::dokument.eq(&document),
+					dokument_version::version.eq(&version),
+					dokument_version::datei.eq(&url),
+					dokument_version::hash.eq(&hash),
+					dokument_version::gueltig_ab.eq(valid_from),
+				))
+				.execute(&mut connection)
+				.await?;
+			println!("Added {} version {} with hash {}", document, version, hash);
+			println!("Members and supervisors have to accept it again from {}", valid_from);
+		}
 	}
 
 	Ok(())
 }
+
+/// Print the DNS record for the DKIM key and check a signed test mail.
+fn check_dkim(config: &Config) -> Result<()> {
+	let Some(settings) = &config.dkim else {
+		bail!("No dkim section in the config");
+	};
+	let dkim = dkim::Dkim::new(config, settings)?;
+	println!("Add this TXT record to the DNS:\n");
+	println!("{} IN TXT {}\n", dkim.record_name(), dkim::quote_record(&dkim.record_value()?));
+
+	let mut email = lettre::Message::builder()
+		.from(config.sender_mail.clone().try_into()?)
+		.to(config.sender_mail.clone().try_into()?)
+		.subject("DKIM Test")
+		.body(String::from("Test"))?;
+	dkim.sign(&mut email);
+	dkim::verify(&email.formatted(), &dkim.public_key)?;
+	println!("Signed a test mail and checked the signature successfully");
+	Ok(())
+}
@@ -0,0 +1,283 @@
+//! Send mails in the background.
+//!
+//! Mails are stored in the `postausgang` table and sent by a worker task. If sending fails, the
+//! worker tries again later with exponential backoff. After [`MAX_ATTEMPTS`] the mail is given up
+//! and shown to admins, who can send it again.
+
+use std::sync::Arc;
+
+use anyhow::Result;
+use axum::{Json, extract};
+use diesel::prelude::*;
+use diesel_async::RunQueryDsl;
+use lettre::Address;
+use lettre::address::Envelope;
+use serde::{Deserialize, Serialize};
+use time::{Duration, OffsetDateTime, PrimitiveDateTime};
+use tracing::{error, info, warn};
+
+use crate::db::models::{opt_primitive_datetime, primitive_datetime};
+use crate::mail::Mail;
+use crate::mail_transport::Transport;
+use crate::{ExtractState, State, WebResult, db, digest};
+
+type DbResult<T> = anyhow::Result<T>;
+
+/// Give up after this many failed attempts.
+pub const MAX_ATTEMPTS: i32 = 10;
+/// Wait time after the first failed attempt, doubled after every attempt.
+const FIRST_RETRY: Duration = Duration::minutes(1);
+const MAX_RETRY: Duration = Duration::hours(6);
+/// Check for due mails at least this often.
+const POLL_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+
+#[derive(Clone, Debug, Queryable, Serialize)]
+pub struct OutboxEntry {
+	id: i32,
+	an: String,
+	betreff: String,
+	#[serde(with = "primitive_datetime")]
+	erstellt: PrimitiveDateTime,
+	versuche: i32,
+	#[serde(with = "primitive_datetime")]
+	naechster_versuch: PrimitiveDateTime,
+	fehler: Option<String>,
+	#[serde(with = "opt_primitive_datetime")]
+	gesendet: Option<PrimitiveDateTime>,
+	aufgegeben: bool,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct ResendData {
+	id: i32,
+}
+
+fn now() -> PrimitiveDateTime {
+	let now = OffsetDateTime::now_utc();
+	PrimitiveDateTime::new(now.date(), now.time())
+}
+
+/// Time to wait before the next attempt after `attempts` failed attempts.
+fn backoff(attempts: i32) -> Duration {
+	let factor = 1i32.checked_shl((attempts - 1).max(0) as u32).unwrap_or(i32::MAX);
+	FIRST_RETRY.checked_mul(factor).unwrap_or(MAX_RETRY).min(MAX_RETRY)
+}
+
+/// Store a mail in the outbox, returns the id of the entry.
+pub async fn store(db: &db::Database, email: &lettre::Message) -> Result<i32> {
+	use db::schema::postausgang;
+
+	let envelope = email.envelope();
+	let absender = envelope.from().map(|a| a.to_string()).unwrap_or_default();
+	let empfaenger =
+		serde_json::to_string(&envelope.to().iter().map(|a| a.to_string()).collect::<Vec<_>>())?;
+	let header = |name| email.headers().get_raw(name).unwrap_or_default().to_string();
+
+	Ok(diesel::insert_into(postausgang::table)
+		.values((
+			postausgang::absender.eq(absender),
+			postausgang::empfaenger.eq(empfaenger),
+			postausgang::an.eq(header("To")),
+			postausgang::betreff.eq(header("Subject")),
+			postausgang::nachricht.eq(email.formatted()),
+			postausgang::message_id.eq(email.headers().get_raw("Message-ID")),
+		))
+		.returning(postausgang::id)
+		.get_result(&mut db.get().await?)
+		.await?)
+}
+
+/// Send all mails that are due, returns the time of the next retry.
+///
+/// Mails that are given up are added to the summary for the additional receivers.
+async fn send_due(
+	db: &db::Database, mail: &Mail, mailer: &Transport,
+) -> DbResult<Option<PrimitiveDateTime>> {
+	use db::schema::postausgang;
+
+	let mut connection = db.get().await?;
+	let open = postausgang::gesendet.is_null().and(postausgang::aufgegeben.eq(false));
+	let due = postausgang::table
+		.filter(open.and(postausgang::naechster_versuch.le(now())))
+		.order(postausgang::id)
+		.select((
+			postausgang::id,
+			postausgang::absender,
+			postausgang::empfaenger,
+			postausgang::nachricht,
+			postausgang::versuche,
+			postausgang::an,
+			postausgang::betreff,
+		))
+		.load::<(i32, String, String, Vec<u8>, i32, String, String)>(&mut connection)
+		.await?;
+
+	for (id, absender, empfaenger, nachricht, versuche, an, betreff) in due {
+		let res = async {
+			let to = serde_json::from_str::<Vec<String>>(&empfaenger)?
+				.iter()
+				.map(|a| a.parse::<Address>())
+				.collect::<Result<Vec<_>, _>>()?;
+			let from = if absender.is_empty() { None } else { Some(absender.parse()?) };
+			let envelope = Envelope::new(from, to)?;
+			mailer.send_raw(&envelope, &nachricht).await
+		}
+		.await;
+
+		match res {
+			Ok(()) => {
+				diesel::update(postausgang::table.find(id))
+					.set((
+						postausgang::gesendet.eq(now()),
+						postausgang::versuche.eq(versuche + 1),
+						postausgang::fehler.eq(None::<String>),
+					))
+					.execute(&mut connection)
+					.await?;
+			}
+			Err(error) => {
+				let versuche = versuche + 1;
+				let aufgegeben = versuche >= MAX_ATTEMPTS;
+				if aufgegeben {
+					error!(id, %error, "Giving up sending e-mail");
+					mail.add_to_digest(
+						None,
+						digest::Art::Fehler,
+						format!("„{betreff}“ an {an}: {error}"),
+					)
+					.await;
+				} else {
+					warn!(id, versuche, %error, "Failed to send e-mail, retrying later");
+				}
+				diesel::update(postausgang::table.find(id))
+					.set((
+						postausgang::versuche.eq(versuche),
+						postausgang::naechster_versuch.eq(now() + backoff(versuche)),
+						postausgang::fehler.eq(error.to_string()),
+						postausgang::aufgegeben.eq(aufgegeben),
+					))
+					.execute(&mut connection)
+					.await?;
+			}
+		}
+	}
+
+	Ok(postausgang::table
+		.filter(open)
+		.select(diesel::dsl::min(postausgang::naechster_versuch))
+		.first::<Option<PrimitiveDateTime>>(&mut connection)
+		.await?)
+}
+
+/// Send mails from the outbox until the server stops.
+///
+/// The worker keeps one transport, so the SMTP connection is reused between mails.
+pub async fn run(state: Arc<State>) {
+	let mailer = match state.mail.mailer() {
+		Ok(m) => m,
+		Err(error) => {
+			error!(%error, "Failed to create mail transport, mails are not sent");
+			return;
+		}
+	};
+	info!("Starting mail outbox");
+
+	loop {
+		let wait = match send_due(&state.db, &state.mail, &mailer).await {
+			Ok(Some(next)) => {
+				let secs = (next - now()).whole_seconds().max(1) as u64;
+				POLL_INTERVAL.min(std::time::Duration::from_secs(secs))
+			}
+			Ok(None) => POLL_INTERVAL,
+			Err(error) => {
+				error!(%error, "Failed to send mails from outbox");
+				POLL_INTERVAL
+			}
+		};
+		tokio::select! {
+			_ = state.mail.queued() => {}
+			_ = tokio::time::sleep(wait) => {}
+		}
+	}
+}
+
+/// List all mails that are not sent yet, given up mails first.
+pub async fn list_unsent(extract::State(state): ExtractState) -> WebResult<Json<Vec<OutboxEntry>>> {
+	match async {
+		use db::schema::postausgang;
+
+		let entries = postausgang::table
+			.filter(postausgang::gesendet.is_null())
+			.order((postausgang::aufgegeben.desc(), postausgang::id))
+			.select((
+				postausgang::id,
+				postausgang::an,
+				postausgang::betreff,
+				postausgang::erstellt,
+				postausgang::versuche,
+				postausgang::naechster_versuch,
+				postausgang::fehler,
+				postausgang::gesendet,
+				postausgang::aufgegeben,
+			))
+			.load::<OutboxEntry>(&mut state.db.get().await?)
+			.await?;
+		DbResult::Ok(entries)
+	}
+	.await
+	{
+		Err(error) => {
+			warn!(%error, "Failed to list outbox");
+			crate::error_response(&state)
+		}
+		Ok(r) => Ok(Json(r)),
+	}
+}
+
+/// Send a mail again that was given up.
+pub async fn resend(
+	extract::State(state): ExtractState, Json(data): Json<ResendData>,
+) -> WebResult<&'static str> {
+	match async {
+		use db::schema::postausgang;
+
+		let r = diesel::update(postausgang::table.find(data.id))
+			.filter(postausgang::gesendet.is_null())
+			.set((
+				postausgang::aufgegeben.eq(false),
+				postausgang::versuche.eq(0),
+				postausgang::naechster_versuch.eq(now()),
+			))
+			.execute(&mut state.db.get().await?)
+			.await?;
+		if r == 0 {
+			anyhow::bail!("Mail not found or already sent");
+		}
+		DbResult::Ok(())
+	}
+	.await
+	{
+		Err(error) => {
+			warn!(%error, "Failed to resend mail");
+			crate::error_response(&state)
+		}
+		Ok(()) => {
+			state.mail.notify_queued();
+			Ok("Success")
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn exponential_backoff() {
+		assert_eq!(backoff(1), Duration::minutes(1));
+		assert_eq!(backoff(2), Duration::minutes(2));
+		assert_eq!(backoff(5), Duration::minutes(16));
+		assert_eq!(backoff(MAX_ATTEMPTS), MAX_RETRY);
+		assert_eq!(backoff(100), MAX_RETRY);
+	}
+}
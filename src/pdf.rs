@@ -0,0 +1,286 @@
+//! Generate the signup confirmation that parents sign and hand in at the bus.
+//!
+//! The PDF only uses the standard Helvetica font, so no font has to be embedded. Text is
+//! encoded with WinAnsiEncoding, which covers German umlauts.
+
+use pdf_writer::{Content, Finish, Name, Pdf, Rect, Ref, Str};
+use time::OffsetDateTime;
+
+use crate::db::models::{Gender, Teilnehmer};
+use crate::{GERMAN_DATE_FORMAT, season};
+
+/// A4 in points
+const PAGE_WIDTH: f32 = 595.0;
+const PAGE_HEIGHT: f32 = 842.0;
+const MARGIN: f32 = 50.0;
+/// Start of the values in a table row
+const VALUE_X: f32 = 210.0;
+const FONT_SIZE: f32 = 10.0;
+const LINE_HEIGHT: f32 = 14.0;
+
+const FONT: Name = Name(b"F1");
+const FONT_BOLD: Name = Name(b"F2");
+
+const BANK_RECEIVER: &str = "Sportverein Freie Turnerschaft München-Gern e.V.";
+const BANK_IBAN: &str = "DE13 7015 0000 0902 2401 00";
+const BANK_BIC: &str = "SSKMDEMMXXX";
+const BANK_NAME: &str = "Stadtsparkasse München";
+
+/// Convert text to WinAnsiEncoding, unknown characters are replaced by `?`.
+fn encode(text: &str) -> Vec<u8> {
+	text.chars()
+		.map(|c| match c {
+			'\u{20}'..='\u{7e}' | '\u{a0}'..='\u{ff}' => c as u8,
+			'€' => 0x80,
+			'„' => 0x84,
+			'–' => 0x96,
+			'“' => 0x93,
+			'”' => 0x94,
+			'\t' => b' ',
+			_ => b'?',
+		})
+		.collect()
+}
+
+/// Split text into lines that fit into `width` points.
+///
+/// Uses the average width of a Helvetica character, which is good enough for a form.
+fn wrap(text: &str, width: f32) -> Vec<String> {
+	let max_chars = (width / (FONT_SIZE * 0.5)) as usize;
+	let mut lines = Vec::new();
+	for paragraph in text.lines() {
+		let mut line = String::new();
+		for word in paragraph.split_whitespace() {
+			if !line.is_empty() && line.chars().count() + 1 + word.chars().count() > max_chars {
+				lines.push(std::mem::take(&mut line));
+			}
+			if !line.is_empty() {
+				line.push(' ');
+			}
+			line.push_str(word);
+		}
+		lines.push(line);
+	}
+	if lines.is_empty() {
+		lines.push(String::new());
+	}
+	lines
+}
+
+fn yes_no(b: bool) -> &'static str { if b { "Ja" } else { "Nein" } }
+
+fn or_none(s: &str) -> &str { if s.trim().is_empty() { "-" } else { s.trim() } }
+
+/// Writes text from top to bottom and starts a new page when the current one is full.
+struct Layout {
+	pages: Vec<Content>,
+	content: Content,
+	y: f32,
+}
+
+impl Layout {
+	fn new() -> Self {
+		Self { pages: Vec::new(), content: Content::new(), y: PAGE_HEIGHT - MARGIN }
+	}
+
+	/// Start a new page if less than `height` is left on the current page.
+	fn reserve(&mut self, height: f32) {
+		if self.y - height < MARGIN {
+			self.pages.push(std::mem::replace(&mut self.content, Content::new()));
+			self.y = PAGE_HEIGHT - MARGIN;
+		}
+	}
+
+	fn text_at(&mut self, x: f32, font: Name, size: f32, text: &str) {
+		self.content
+			.begin_text()
+			.set_font(font, size)
+			.next_line(x, self.y)
+			.show(Str(&encode(text)))
+			.end_text();
+	}
+
+	fn heading(&mut self, text: &str) {
+		self.reserve(LINE_HEIGHT * 3.0);
+		self.y -= LINE_HEIGHT;
+		self.text_at(MARGIN, FONT_BOLD, 12.0, text);
+		self.y -= LINE_HEIGHT * 1.2;
+	}
+
+	/// A table row with a label and a value that can span multiple lines.
+	fn row(&mut self, label: &str, value: &str) {
+		let lines = wrap(value, PAGE_WIDTH - MARGIN - VALUE_X);
+		for (i, line) in lines.iter().enumerate() {
+			self.reserve(LINE_HEIGHT);
+			if i == 0 {
+				self.text_at(MARGIN, FONT_BOLD, FONT_SIZE, label);
+			}
+			self.text_at(VALUE_X, FONT, FONT_SIZE, line);
+			self.y -= LINE_HEIGHT;
+		}
+	}
+
+	fn paragraph(&mut self, text: &str) {
+		for line in wrap(text, PAGE_WIDTH - 2.0 * MARGIN) {
+			self.reserve(LINE_HEIGHT);
+			self.text_at(MARGIN, FONT, FONT_SIZE, &line);
+			self.y -= LINE_HEIGHT;
+		}
+	}
+
+	/// Lines to sign on with a description below.
+	fn signature(&mut self) {
+		self.reserve(LINE_HEIGHT * 6.0);
+		self.y -= LINE_HEIGHT * 4.0;
+		let middle = PAGE_WIDTH / 2.0;
+		self.content
+			.set_line_width(0.5)
+			.move_to(MARGIN, self.y)
+			.line_to(middle - 20.0, self.y)
+			.move_to(middle, self.y)
+			.line_to(PAGE_WIDTH - MARGIN, self.y)
+			.stroke();
+		self.y -= LINE_HEIGHT;
+		self.text_at(MARGIN, FONT, 8.0, "Ort, Datum");
+		self.text_at(middle, FONT, 8.0, "Unterschrift eines Erziehungsberechtigten");
+		self.y -= LINE_HEIGHT;
+	}
+
+	fn finish(mut self) -> Vec<u8> {
+		self.pages.push(self.content);
+
+		let mut pdf = Pdf::new();
+		let catalog_id = Ref::new(1);
+		let page_tree_id = Ref::new(2);
+		let font_id = Ref::new(3);
+		let font_bold_id = Ref::new(4);
+		let page_ids =
+			(0..self.pages.len() as i32).map(|i| Ref::new(5 + 2 * i)).collect::<Vec<_>>();
+
+		pdf.catalog(catalog_id).pages(page_tree_id);
+		pdf.pages(page_tree_id).kids(page_ids.iter().copied()).count(page_ids.len() as i32);
+		pdf.type1_font(font_id)
+			.base_font(Name(b"Helvetica"))
+			.encoding_predefined(Name(b"WinAnsiEncoding"));
+		pdf.type1_font(font_bold_id)
+			.base_font(Name(b"Helvetica-Bold"))
+			.encoding_predefined(Name(b"WinAnsiEncoding"));
+
+		for (content, page_id) in self.pages.into_iter().zip(&page_ids) {
+			let content_id = Ref::new(page_id.get() + 1);
+			let mut page = pdf.page(*page_id);
+			page.media_box(Rect::new(0.0, 0.0, PAGE_WIDTH, PAGE_HEIGHT));
+			page.parent(page_tree_id);
+			page.contents(content_id);
+			let mut resources = page.resources();
+			let mut fonts = resources.fonts();
+			fonts.pair(FONT, font_id);
+			fonts.pair(FONT_BOLD, font_bold_id);
+			fonts.finish();
+			resources.finish();
+			page.finish();
+			pdf.stream(content_id, &content.finish());
+		}
+
+		pdf.finish()
+	}
+}
+
+/// Create the confirmation of a signup with all submitted data and a line to sign.
+///
+/// `preis` is the participation fee in Euro.
+pub fn signup_confirmation(member: &Teilnehmer, preis: i32) -> Vec<u8> {
+	let mut layout = Layout::new();
+	let year = season::beginn().year();
+	let today = OffsetDateTime::now_utc().date().format(GERMAN_DATE_FORMAT).unwrap();
+
+	layout.y -= 10.0;
+	layout.text_at(MARGIN, FONT_BOLD, 18.0, &format!("Anmeldung Zeltlager {year}"));
+	layout.y -= LINE_HEIGHT * 1.5;
+	layout.text_at(
+		MARGIN,
+		FONT,
+		FONT_SIZE,
+		&format!("Online angemeldet am {today}. Bitte unterschrieben am Bus abgeben."),
+	);
+	layout.y -= LINE_HEIGHT;
+
+	layout.heading("Teilnehmer");
+	layout.row("Name", &format!("{} {}", member.vorname, member.nachname));
+	layout.row("Geburtsdatum", &member.geburtsdatum.format(GERMAN_DATE_FORMAT).unwrap());
+	layout
+		.row("Geschlecht", if member.geschlecht == Gender::Male { "Männlich" } else { "Weiblich" });
+	layout.row("Schwimmer", yes_no(member.schwimmer));
+	layout.row("Vegetarier", yes_no(member.vegetarier));
+	layout.row("Eigene Anreise", yes_no(member.eigenanreise));
+
+	layout.heading("Erziehungsberechtigte");
+	layout.row("Name", &member.eltern_name);
+	layout.row("E-Mail", &member.eltern_mail);
+	layout.row("Handynummer", &member.eltern_handynummer);
+	layout.row(
+		"Adresse",
+		&format!(
+			"{} {}\n{} {}\n{}",
+			member.strasse, member.hausnummer, member.plz, member.ort, member.land
+		),
+	);
+
+	layout.heading("Gesundheit");
+	layout.row("Krankenversicherung", &member.krankenversicherung);
+	layout.row("Tetanus-Impfung", yes_no(member.tetanus_impfung));
+	layout.row("Allergien", or_none(&member.allergien));
+	layout.row("Unverträglichkeiten", or_none(&member.unvertraeglichkeiten));
+	layout.row("Medikamente", or_none(&member.medikamente));
+	layout.row("Krankheiten", or_none(&member.krankheiten));
+
+	if !member.kommentar.trim().is_empty() {
+		layout.heading("Kommentar");
+		layout.paragraph(&member.kommentar);
+	}
+
+	layout.heading("Bezahlung");
+	layout.paragraph(&format!(
+		"Bitte überweisen Sie {preis},00 Euro an die folgende Bankverbindung. Nur durch die \
+		 Überweisung ist Ihr Kind verbindlich angemeldet."
+	));
+	layout.y -= LINE_HEIGHT * 0.5;
+	layout.row("Empfänger", BANK_RECEIVER);
+	layout.row("IBAN", BANK_IBAN);
+	layout.row("BIC/SWIFT", BANK_BIC);
+	layout.row("Bankname", BANK_NAME);
+	layout.row(
+		"Verwendungszweck",
+		&format!("{} {} Zeltlager {year}", member.vorname, member.nachname),
+	);
+
+	layout.heading("Einverständnis");
+	layout.paragraph(
+		"Ich bestätige, dass die Angaben vollständig und richtig sind, und melde mein Kind \
+		 verbindlich zum Zeltlager an. Die Allgemeinen Geschäftsbedingungen habe ich gelesen und \
+		 akzeptiert.",
+	);
+	layout.signature();
+
+	layout.finish()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn encode_umlauts() {
+		assert_eq!(encode("Grüße €"), b"Gr\xfc\xdfe \x80");
+		assert_eq!(encode("🏕"), b"?");
+	}
+
+	#[test]
+	fn wrap_long_text() {
+		let text = "Wort ".repeat(100);
+		let lines = wrap(&text, 200.0);
+		assert!(lines.len() > 1);
+		assert!(lines.iter().all(|l| l.len() <= 40));
+		assert_eq!(wrap("", 200.0), vec![String::new()]);
+	}
+}
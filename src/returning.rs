@@ -0,0 +1,216 @@
+//! Prefill the signup for families that come back every year.
+//!
+//! Parents can agree at the signup to keep their data. When the camp is removed, the data of
+//! these participants is kept in `rueckkehrer`, everything else is deleted. Next year, parents
+//! get a link by mail that prefills the signup form for all their children.
+//!
+//! Requesting the link sends a mail without a login, so requests are rate limited per IP address
+//! like the mails of signup drafts and a family gets at most one link per [`MAIL_COOLDOWN`].
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+
+use axum::extract::ConnectInfo;
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::{Form, Json, extract};
+use diesel::prelude::*;
+use diesel_async::{AsyncPgConnection, RunQueryDsl};
+use rand::RngExt;
+use serde::{Deserialize, Serialize};
+use time::{Duration, OffsetDateTime, PrimitiveDateTime};
+use tracing::{error, warn};
+
+use crate::db::models::FullTeilnehmer;
+use crate::i18n::Sprache;
+use crate::{ExtractState, db};
+
+type DbResult<T> = anyhow::Result<T>;
+
+/// Minimum time between two mails with a link to the same family.
+const MAIL_COOLDOWN: Duration = Duration::minutes(10);
+
+#[derive(Clone, Debug, Deserialize)]
+pub(crate) struct ReturningDataRequest {
+	token: String,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub(crate) struct ReturningResult {
+	error: Option<String>,
+}
+
+/// Keep the data of all members of a camp whose parents agreed to it and delete all older data.
+///
+/// Called before the members are removed at the end of a camp.
+pub async fn keep_members(connection: &mut AsyncPgConnection, lager: i32) -> DbResult<()> {
+	use db::schema::{rueckkehrer, teilnehmer};
+
+	let members = teilnehmer::table
+		.filter(
+			teilnehmer::daten_behalten
+				.and(teilnehmer::lager.eq(lager))
+				.and(teilnehmer::geloescht.is_null()),
+		)
+		.load::<FullTeilnehmer>(connection)
+		.await?;
+	let rows = members
+		.iter()
+		.map(|m| {
+			(
+				rueckkehrer::eltern_mail.eq(m.eltern_mail.trim()),
+				rueckkehrer::daten.eq(serde_json::to_string(&m.returning_form_data()).unwrap()),
+			)
+		})
+		.collect::<Vec<_>>();
+
+	diesel::delete(rueckkehrer::table).execute(connection).await?;
+	if !rows.is_empty() {
+		diesel::insert_into(rueckkehrer::table).values(&rows).execute(connection).await?;
+	}
+	Ok(())
+}
+
+/// Send a link to prefill the signup with last year's data.
+///
+/// Returns success if the mail address is unknown or got a link shortly before, so this does
+/// not show who signed up last year.
+pub async fn request_link(
+	extract::State(state): ExtractState, headers: HeaderMap,
+	ConnectInfo(addr): ConnectInfo<SocketAddr>, Form(body): Form<HashMap<String, String>>,
+) -> (StatusCode, Json<ReturningResult>) {
+	let err = |msg: &str| {
+		(StatusCode::INTERNAL_SERVER_ERROR, Json(ReturningResult { error: Some(msg.into()) }))
+	};
+
+	// Every request counts, whether a mail is sent must not be visible
+	if let Err(error) = crate::auth::rate_limit(&state, &headers, addr, crate::auth::MAIL).await {
+		warn!(%error, "Rate limit for returning family links");
+		return (
+			StatusCode::TOO_MANY_REQUESTS,
+			Json(ReturningResult {
+				error: Some("Zu viele Anfragen. Bitte versuchen Sie es später erneut.".into()),
+			}),
+		);
+	}
+
+	let Some(mail) = body.get("eltern_mail").filter(|m| !m.trim().is_empty()) else {
+		return (
+			StatusCode::BAD_REQUEST,
+			Json(ReturningResult { error: Some("Bitte geben Sie Ihre E-Mail-Adresse an.".into()) }),
+		);
+	};
+
+	let token = {
+		let mut rng = rand::rng();
+		(0..24).map(|_| rng.sample(rand::distr::Alphanumeric) as char).collect::<String>()
+	};
+	let children = match async {
+		use db::schema::rueckkehrer;
+
+		let mut connection = state.db.get().await?;
+		let rows = rueckkehrer::table
+			.filter(db::lower(db::trim(rueckkehrer::eltern_mail)).eq(mail.trim().to_lowercase()))
+			.order(rueckkehrer::id)
+			.select((rueckkehrer::id, rueckkehrer::daten, rueckkehrer::token_zeit))
+			.load::<(i32, String, Option<PrimitiveDateTime>)>(&mut connection)
+			.await?;
+		let since = OffsetDateTime::now_utc() - MAIL_COOLDOWN;
+		let since = PrimitiveDateTime::new(since.date(), since.time());
+		if rows.iter().any(|r| r.2.is_some_and(|t| t > since)) {
+			return DbResult::Ok(None);
+		}
+		if rows.is_empty() {
+			return Ok(Some(Vec::new()));
+		}
+
+		diesel::update(rueckkehrer::table)
+			.filter(rueckkehrer::id.eq_any(rows.iter().map(|r| r.0)))
+			.set((rueckkehrer::token.eq(&token), rueckkehrer::token_zeit.eq(diesel::dsl::now)))
+			.execute(&mut connection)
+			.await?;
+
+		Ok(Some(
+			rows.into_iter()
+				.map(|r| serde_json::from_str::<HashMap<String, String>>(&r.1))
+				.collect::<Result<Vec<_>, _>>()?,
+		))
+	}
+	.await
+	{
+		Err(error) => {
+			error!(%error, "Failed to get returning family");
+			return err("Es ist leider ein Fehler beim Suchen der E-Mailadresse aufgetreten");
+		}
+		Ok(None) => {
+			warn!(mail, "Returning family link was sent shortly before");
+			return (StatusCode::OK, Json(ReturningResult { error: None }));
+		}
+		Ok(Some(children)) => children,
+	};
+
+	if children.is_empty() {
+		warn!(mail, "Failed to find returning family by mail");
+		return (StatusCode::OK, Json(ReturningResult { error: None }));
+	}
+
+	let get = |key: &str| children[0].get(key).map(|s| s.as_str()).unwrap_or_default();
+	let names = children
+		.iter()
+		.map(|c| c.get("vorname").map(|s| s.as_str()).unwrap_or_default())
+		.collect::<Vec<_>>();
+	let link = state.config.link(&format!("/anmeldung?familie={token}"));
+	let sprache = Sprache::from_code(get("sprache"));
+	match state.mail.send_returning_family(sprache, get("eltern_name"), mail, &names, &link).await {
+		Err(error) => {
+			error!(mail, %error, "Error sending e-mail");
+			err("Es ist leider ein Fehler beim Versenden der E-Mail aufgetreten")
+		}
+		Ok(()) => (StatusCode::OK, Json(ReturningResult { error: None })),
+	}
+}
+
+/// Get last year's data of all children of a family.
+///
+/// The link can be used for a day, so siblings can be signed up one after the other.
+pub async fn get_data(
+	extract::State(state): ExtractState, Json(request): Json<ReturningDataRequest>,
+) -> Response {
+	let err = |msg: &str| {
+		(StatusCode::BAD_REQUEST, Json(ReturningResult { error: Some(msg.into()) })).into_response()
+	};
+
+	match async {
+		use db::schema::rueckkehrer;
+
+		let since = OffsetDateTime::now_utc() - Duration::days(1);
+		let since_primitive = PrimitiveDateTime::new(since.date(), since.time());
+		let rows = rueckkehrer::table
+			.filter(
+				rueckkehrer::token
+					.eq(&request.token)
+					.and(rueckkehrer::token_zeit.gt(since_primitive)),
+			)
+			.order(rueckkehrer::id)
+			.select(rueckkehrer::daten)
+			.load::<String>(&mut state.db.get().await?)
+			.await?;
+		let children = rows
+			.iter()
+			.map(|r| serde_json::from_str::<HashMap<String, String>>(r))
+			.collect::<Result<Vec<_>, _>>()?;
+		DbResult::Ok(children)
+	}
+	.await
+	{
+		Err(error) => {
+			error!(%error, "Failed to get returning family by token");
+			err("Es ist leider ein Fehler beim Laden der Daten aufgetreten")
+		}
+		Ok(children) if children.is_empty() => {
+			warn!("Failed to find returning family by token");
+			err("Daten konnten nicht vorausgefüllt werden, der Link ist abgelaufen")
+		}
+		Ok(children) => Json(children).into_response(),
+	}
+}
@@ -0,0 +1,381 @@
+//! Camps of several seasons.
+//!
+//! Members, Erwischt games and supervisor participations belong to a camp. One camp is the
+//! current one, signups and the admin lists use it. Old camps stay as an archive when
+//! [`crate::admin::remove_lager`] removes the personal data of a season.
+//!
+//! The current camp is kept in memory, so changing its dates in the admin area applies to age
+//! checks, deadlines and mails without a restart.
+
+use anyhow::{Error, bail};
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::{Extension, Json, extract};
+use diesel::prelude::*;
+use diesel_async::{
+	AsyncConnection, AsyncPgConnection, RunQueryDsl, scoped_futures::ScopedFutureExt,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::sync::{LazyLock, RwLock};
+
+use time::{Date, Duration, OffsetDateTime};
+use tracing::{info, warn};
+
+use crate::audit::{self, Audit};
+use crate::config::Config;
+use crate::db::models::{Lager, NewLager};
+use crate::{ExtractState, ISO_DATE_FORMAT, WebResult, db};
+
+type DbResult<T> = anyhow::Result<T>;
+
+/// Start of the first camp.
+static DEFAULT_BEGINN: LazyLock<Date> = LazyLock::new(|| {
+	Date::parse(include_str!("../frontend/lager-start.txt").trim(), ISO_DATE_FORMAT).unwrap()
+});
+/// The first camp ends this many days after it starts.
+const DEFAULT_DAYS: i64 = 11;
+
+static CURRENT: RwLock<Option<Lager>> = RwLock::new(None);
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct SaveData {
+	/// Create a new camp if not set.
+	id: Option<i32>,
+	#[serde(flatten)]
+	lager: NewLager,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct CurrentData {
+	id: i32,
+}
+
+/// Dates of the current camp for the website.
+#[derive(Clone, Debug, Serialize)]
+pub struct Dates {
+	name: String,
+	#[serde(with = "crate::db::models::date")]
+	beginn: Date,
+	#[serde(with = "crate::db::models::date")]
+	ende: Date,
+	preis: i32,
+}
+
+/// A camp in the admin list.
+#[derive(Clone, Debug, Serialize)]
+pub struct ListedLager {
+	#[serde(flatten)]
+	lager: Lager,
+	teilnehmer: i64,
+	betreuer: i64,
+	erwischt_spiele: i64,
+}
+
+/// The current camp.
+///
+/// Panics if it was not loaded with [`init`].
+pub fn current() -> Lager { CURRENT.read().unwrap().clone().expect("Current camp not loaded") }
+
+/// Start of the current camp.
+pub fn beginn() -> Date {
+	CURRENT.read().unwrap().as_ref().map(|l| l.beginn).unwrap_or(*DEFAULT_BEGINN)
+}
+
+/// Last day of the current camp.
+pub fn ende() -> Date {
+	CURRENT
+		.read()
+		.unwrap()
+		.as_ref()
+		.map(|l| l.ende)
+		.unwrap_or(*DEFAULT_BEGINN + Duration::days(DEFAULT_DAYS))
+}
+
+/// Participation fee of the current camp in Euro.
+pub fn preis() -> i32 {
+	CURRENT.read().unwrap().as_ref().map(|l| l.preis).unwrap_or(crate::config::DEFAULT_FEE as i32)
+}
+
+fn store(lager: Lager) { *CURRENT.write().unwrap() = Some(lager); }
+
+/// Load the current camp, create it from the config on the first start.
+pub async fn init(db: &db::Database, config: &Config) -> DbResult<()> {
+	let current = load_or_create(db, config).await?;
+	info!(name = current.name, beginn = %current.beginn, ende = %current.ende, "Current camp");
+	store(current);
+	Ok(())
+}
+
+async fn load_or_create(db: &db::Database, config: &Config) -> DbResult<Lager> {
+	use db::schema::{betreuer, betreuer_teilnahme, erwischt_game, lager, teilnehmer};
+
+	let mut connection = db.get().await?;
+	if let Some(current) =
+		lager::table.filter(lager::aktuell).get_result::<Lager>(&mut connection).await.optional()?
+	{
+		return Ok(current);
+	}
+
+	connection
+		.transaction::<_, Error, _>(|con| {
+			async move {
+				let current = diesel::insert_into(lager::table)
+					.values((
+						lager::name.eq(format!("Zeltlager {}", DEFAULT_BEGINN.year())),
+						lager::beginn.eq(*DEFAULT_BEGINN),
+						lager::ende.eq(*DEFAULT_BEGINN + Duration::days(DEFAULT_DAYS)),
+						lager::kapazitaet.eq(config.max_members as i32),
+						lager::preis.eq(config.fee() as i32),
+						lager::aktuell.eq(true),
+					))
+					.get_result::<Lager>(con)
+					.await?;
+				info!(name = current.name, "Created first camp");
+
+				// Existing data belongs to the first camp
+				diesel::update(teilnehmer::table.filter(teilnehmer::lager.is_null()))
+					.set(teilnehmer::lager.eq(current.id))
+					.execute(con)
+					.await?;
+				diesel::update(erwischt_game::table.filter(erwischt_game::lager.is_null()))
+					.set(erwischt_game::lager.eq(current.id))
+					.execute(con)
+					.await?;
+				let supervisors = betreuer::table
+					.filter(betreuer::anmeldedatum.ge(signed_up_since()))
+					.select(betreuer::id)
+					.load::<i32>(con)
+					.await?;
+				let participations = supervisors
+					.into_iter()
+					.map(|id| {
+						(
+							betreuer_teilnahme::betreuer.eq(id),
+							betreuer_teilnahme::lager.eq(current.id),
+						)
+					})
+					.collect::<Vec<_>>();
+				if !participations.is_empty() {
+					diesel::insert_into(betreuer_teilnahme::table)
+						.values(&participations)
+						.execute(con)
+						.await?;
+				}
+				Ok(current)
+			}
+			.scope_boxed()
+		})
+		.await
+}
+
+/// Supervisors that signed up after this time took part in the current or last camp.
+///
+/// Only used to assign existing supervisors to the first camp.
+fn signed_up_since() -> OffsetDateTime {
+	let mut date = DEFAULT_BEGINN.midnight().assume_utc();
+	let now = OffsetDateTime::now_utc();
+	if date > now {
+		// Start from next time, subtract a year to allow signups from last time.
+		date -= Duration::days(365);
+	}
+
+	// Add two weeks to get the end of the lager, subtract one year to get the date of last year.
+	date - Duration::days(365) + Duration::weeks(2)
+}
+
+/// Supervisors that take part in the current or the last camp.
+pub async fn active_supervisors(
+	connection: &mut AsyncPgConnection, current: &Lager,
+) -> DbResult<Vec<i32>> {
+	use db::schema::{betreuer_teilnahme, lager};
+
+	let mut camps = lager::table
+		.filter(lager::beginn.lt(current.beginn))
+		.order(lager::beginn.desc())
+		.select(lager::id)
+		.limit(1)
+		.load::<i32>(connection)
+		.await?;
+	camps.push(current.id);
+	Ok(betreuer_teilnahme::table
+		.filter(betreuer_teilnahme::lager.eq_any(camps))
+		.select(betreuer_teilnahme::betreuer)
+		.distinct()
+		.load::<i32>(connection)
+		.await?)
+}
+
+/// All camps with the number of participants, the newest first.
+pub async fn list(extract::State(state): ExtractState) -> WebResult<Json<Vec<ListedLager>>> {
+	match async {
+		use db::schema::{betreuer_teilnahme, erwischt_game, lager, teilnehmer};
+
+		let mut connection = state.db.get().await?;
+		let camps = lager::table.order(lager::beginn.desc()).load::<Lager>(&mut connection).await?;
+		let mut res = Vec::with_capacity(camps.len());
+		for lager in camps {
+			let teilnehmer = teilnehmer::table
+				.filter(teilnehmer::lager.eq(lager.id).and(teilnehmer::geloescht.is_null()))
+				.count()
+				.get_result(&mut connection)
+				.await?;
+			let betreuer = betreuer_teilnahme::table
+				.filter(betreuer_teilnahme::lager.eq(lager.id))
+				.count()
+				.get_result(&mut connection)
+				.await?;
+			let erwischt_spiele = erwischt_game::table
+				.filter(erwischt_game::lager.eq(lager.id))
+				.count()
+				.get_result(&mut connection)
+				.await?;
+			res.push(ListedLager { lager, teilnehmer, betreuer, erwischt_spiele });
+		}
+		DbResult::Ok(res)
+	}
+	.await
+	{
+		Err(error) => {
+			warn!(%error, "Failed to list camps");
+			crate::error_response(&state)
+		}
+		Ok(r) => Ok(Json(r)),
+	}
+}
+
+/// Check the entered data of a camp before saving it.
+fn check(lager: &NewLager) -> anyhow::Result<()> {
+	if lager.beginn > lager.ende {
+		bail!("Das Lager kann nicht vor dem Beginn enden");
+	}
+	Ok(())
+}
+
+/// Create or change a camp.
+pub(crate) async fn save(
+	extract::State(state): ExtractState, Extension(audit): Extension<Audit>,
+	Json(data): Json<SaveData>,
+) -> WebResult<Json<Lager>> {
+	if let Err(error) = check(&data.lager) {
+		return Err((StatusCode::BAD_REQUEST, error.to_string()).into_response());
+	}
+
+	match async {
+		use db::schema::lager;
+
+		let mut connection = state.db.get().await?;
+		let (old, saved) = if let Some(id) = data.id {
+			let old = lager::table.find(id).get_result::<Lager>(&mut connection).await?;
+			let saved = diesel::update(lager::table.find(id))
+				.set(&data.lager)
+				.get_result::<Lager>(&mut connection)
+				.await?;
+			(audit::value(&old), saved)
+		} else {
+			let saved = diesel::insert_into(lager::table)
+				.values(&data.lager)
+				.get_result::<Lager>(&mut connection)
+				.await?;
+			(Value::Null, saved)
+		};
+		audit
+			.log(
+				&mut connection,
+				"lager",
+				Some(saved.id.to_string()),
+				if data.id.is_some() { "bearbeitet" } else { "erstellt" },
+				old,
+				audit::value(&saved),
+			)
+			.await?;
+		if saved.aktuell {
+			store(saved.clone());
+		}
+		DbResult::Ok(saved)
+	}
+	.await
+	{
+		Err(error) => {
+			warn!(%error, "Failed to save camp");
+			crate::error_response(&state)
+		}
+		Ok(r) => Ok(Json(r)),
+	}
+}
+
+/// Make a camp the current one.
+pub(crate) async fn set_current(
+	extract::State(state): ExtractState, Extension(audit): Extension<Audit>,
+	Json(data): Json<CurrentData>,
+) -> WebResult<Json<Lager>> {
+	match async {
+		use db::schema::lager;
+
+		let (old, audit) = (current().id, &audit);
+		let current = state
+			.db
+			.get()
+			.await?
+			.transaction::<_, Error, _>(|con| {
+				async move {
+					diesel::update(lager::table.filter(lager::aktuell))
+						.set(lager::aktuell.eq(false))
+						.execute(con)
+						.await?;
+					let current = diesel::update(lager::table.find(data.id))
+						.set(lager::aktuell.eq(true))
+						.get_result::<Lager>(con)
+						.await?;
+					audit
+						.log(
+							con,
+							"lager",
+							None,
+							"aktuell",
+							Value::from(old),
+							Value::from(current.id),
+						)
+						.await?;
+					Ok(current)
+				}
+				.scope_boxed()
+			})
+			.await?;
+		store(current.clone());
+		DbResult::Ok(current)
+	}
+	.await
+	{
+		Err(error) => {
+			warn!(%error, "Failed to set current camp");
+			crate::error_response(&state)
+		}
+		Ok(r) => Ok(Json(r)),
+	}
+}
+
+/// Name, dates and price of the current camp.
+pub async fn dates() -> Json<Dates> {
+	let lager = current();
+	Json(Dates { name: lager.name, beginn: lager.beginn, ende: lager.ende, preis: lager.preis })
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn check_dates() {
+		let lager = |beginn, ende| NewLager {
+			name: "Zeltlager".into(),
+			beginn: Date::parse(beginn, ISO_DATE_FORMAT).unwrap(),
+			ende: Date::parse(ende, ISO_DATE_FORMAT).unwrap(),
+			kapazitaet: 60,
+			preis: 250,
+		};
+		assert!(check(&lager("2026-08-01", "2026-08-12")).is_ok());
+		assert!(check(&lager("2026-08-01", "2026-08-01")).is_ok());
+		assert!(check(&lager("2026-08-12", "2026-08-01")).is_err());
+	}
+}
@@ -6,15 +6,42 @@ use axum::body::Body;
 use axum::http::StatusCode;
 use axum::response::{IntoResponse, Response};
 use axum::{Form, Json, extract};
-use diesel_async::RunQueryDsl;
+use diesel::ExpressionMethods;
+use diesel_async::scoped_futures::ScopedFutureExt;
+use diesel_async::{AsyncConnection, RunQueryDsl};
 use serde::Serialize;
+use time::PrimitiveDateTime;
 use tracing::{error, warn};
 
-use crate::{ExtractState, State, WebResult, db};
+use crate::i18n::{self, Sprache};
+use crate::{ExtractState, State, WebResult, cancel, consent, db, form_schema};
 
-#[derive(Clone, Debug, Serialize)]
-struct SignupResult {
-	error: Option<db::FormError>,
+/// Result of a signup, returned as json or rendered for the nojs forms.
+#[derive(Clone, Debug, Default, Serialize)]
+pub(crate) struct SignupResult {
+	/// The first error, kept for older clients.
+	pub error: Option<db::FormError>,
+	/// All errors, e.g. every invalid field of a form.
+	pub errors: Vec<db::FormError>,
+}
+
+impl SignupResult {
+	pub fn error(error: impl Into<db::FormError>) -> Self {
+		let error = error.into();
+		Self { error: Some(error.clone()), errors: vec![error] }
+	}
+
+	pub fn errors(errors: Vec<db::FormError>) -> Self {
+		Self { error: <[_]>::first(&errors).cloned(), errors }
+	}
+
+	/// Translate the error messages.
+	fn translate(mut self, sprache: Sprache) -> Self {
+		for e in self.error.iter_mut().chain(&mut self.errors) {
+			e.message = i18n::translate(sprache, &e.message);
+		}
+		self
+	}
 }
 
 #[derive(Clone, Debug, Serialize)]
@@ -24,83 +51,124 @@ pub(crate) struct SignupState {
 }
 
 /// Write an email and show a success site.
-async fn signup_mail(state: &State, member: db::models::Teilnehmer) -> (StatusCode, SignupResult) {
-	match state.mail.send_member_signup(&member).await {
+async fn signup_mail(
+	state: &State, member: db::models::Teilnehmer, cancel_link: Option<String>,
+) -> (StatusCode, SignupResult) {
+	match state.mail.send_member_signup(&member, cancel_link.as_deref()).await {
 		Err(error) => {
 			error!(mail = member.eltern_mail, %error, "Error sending e-mail");
 		}
 		Ok(()) => {
 			// Signup successful
-			return (StatusCode::OK, SignupResult { error: None });
+			return (StatusCode::OK, SignupResult::default());
 		}
 	}
 
-	(StatusCode::INTERNAL_SERVER_ERROR, SignupResult {
-		error: Some(
-			format!(
-				"Ihre Daten wurden erfolgreich gespeichert.\nEs ist leider ein Fehler beim E-Mail \
-				 senden aufgetreten.\n{}",
-				state.config.error_message
-			)
-			.into(),
-		),
-	})
+	(
+		StatusCode::INTERNAL_SERVER_ERROR,
+		SignupResult::error(format!(
+			"Ihre Daten wurden erfolgreich gespeichert.\nEs ist leider ein Fehler beim E-Mail \
+			 senden aufgetreten.\n{}",
+			state.config.error_message
+		)),
+	)
 }
 
 pub async fn signup_state(extract::State(state): ExtractState) -> WebResult<Json<SignupState>> {
-	match state.db.count_members().await {
+	let lager = crate::season::current();
+	match state.db.count_members(lager.id).await {
 		Err(error) => {
 			error!(%error, "Failed to get current member count");
 			crate::error_response(&state)
 		}
-		Ok(count) => Ok(Json(SignupState { is_full: count >= state.config.max_members })),
+		Ok(count) => Ok(Json(SignupState { is_full: count >= lager.kapazitaet.into() })),
 	}
 }
 
+/// Save a signup, errors are in the language of the form.
 async fn signup_internal(
 	state: &State, body: HashMap<String, String>,
 ) -> (StatusCode, SignupResult) {
+	let sprache = Sprache::from_code(body.get("sprache").map(String::as_str).unwrap_or_default());
+	let (status, result) = signup_member(state, body).await;
+	(status, result.translate(sprache))
+}
+
+async fn signup_member(
+	state: &State, mut body: HashMap<String, String>,
+) -> (StatusCode, SignupResult) {
+	// The draft is deleted once the signup is saved
+	let draft = body.remove("entwurf").filter(|t| !t.is_empty());
+
 	// Get the body of the request
 	let mut member = match db::models::Teilnehmer::from_hashmap(body) {
 		Ok(member) => member,
-		Err(error) => {
-			warn!(?error, "Error handling form content");
-			return (StatusCode::BAD_REQUEST, SignupResult { error: Some(error) });
+		Err(errors) => {
+			warn!(?errors, "Error handling form content");
+			return (StatusCode::BAD_REQUEST, SignupResult::errors(errors));
 		}
 	};
 
 	// Remove spaces
 	member.trim();
 
-	let count = match state.db.count_members().await {
+	let lager = crate::season::current();
+	let count = match state.db.count_members(lager.id).await {
 		Err(error) => {
 			warn!(%error, "Error inserting into database");
-			return (StatusCode::INTERNAL_SERVER_ERROR, SignupResult {
-				error: Some(
-					format!(
-						"Es ist ein Datenbank-Fehler aufgetreten.\n{}",
-						state.config.error_message
-					)
-					.into(),
-				),
-			});
+			return (
+				StatusCode::INTERNAL_SERVER_ERROR,
+				SignupResult::error(format!(
+					"Es ist ein Datenbank-Fehler aufgetreten.\n{}",
+					state.config.error_message
+				)),
+			);
 		}
 		Ok(count) => count,
 	};
 
 	if state.config.test_mail.as_ref().map(|m| m == &member.eltern_mail).unwrap_or(false) {
 		// Don't insert test signup into database
-		return signup_mail(state, member).await;
+		return signup_mail(state, member, None).await;
+	}
+
+	// Check if the child is already signed up, e.g. when the first signup mail failed.
+	match state.db.find_duplicate_member(&member, lager.id).await {
+		Err(error) => {
+			warn!(%error, "Error searching for duplicate member");
+			return (
+				StatusCode::INTERNAL_SERVER_ERROR,
+				SignupResult::error(format!(
+					"Es ist ein Datenbank-Fehler aufgetreten.\n{}",
+					state.config.error_message
+				)),
+			);
+		}
+		Ok(Some(existing)) => {
+			warn!(mail = member.eltern_mail, id = existing.id, "Member is already signed up");
+			return (
+				StatusCode::BAD_REQUEST,
+				SignupResult::error(format!(
+					"{} {} ist bereits zum Zeltlager angemeldet, eine zweite Anmeldung ist nicht \
+					 nötig.\nFalls Sie keine Bestätigungsmail bekommen haben, schauen Sie bitte \
+					 in Ihren Spam-Ordner oder schreiben Sie uns.",
+					existing.vorname, existing.nachname
+				)),
+			);
+		}
+		Ok(None) => {}
 	}
+
 	// Check if too many members are already registered, then call `signup_mail`.
-	if count >= state.config.max_members {
+	if count >= lager.kapazitaet.into() {
 		// Show error
 		warn!(mail = member.eltern_mail, "Already too many members registered");
-		return (StatusCode::BAD_REQUEST, SignupResult {
-			error: Some(
-				"Während Ihrer Anmeldung ist das Zeltlager leider schon voll geworden.".into(),
+		return (
+			StatusCode::BAD_REQUEST,
+			SignupResult::error(
+				"Während Ihrer Anmeldung ist das Zeltlager leider schon voll geworden.",
 			),
-		});
+		);
 	}
 
 	if let Some(log_file) = &state.config.log_file {
@@ -128,36 +196,59 @@ async fn signup_internal(
 		Ok(c) => c,
 		Err(error) => {
 			warn!(%error, "Error getting database connection");
-			return (StatusCode::INTERNAL_SERVER_ERROR, SignupResult {
-				error: Some(
-					format!(
-						"Es ist ein Datenbank-Fehler aufgetreten.\n{}",
-						state.config.error_message
-					)
-					.into(),
-				),
-			});
+			return (
+				StatusCode::INTERNAL_SERVER_ERROR,
+				SignupResult::error(format!(
+					"Es ist ein Datenbank-Fehler aufgetreten.\n{}",
+					state.config.error_message
+				)),
+			);
 		}
 	};
 
-	match diesel::insert_into(db::schema::teilnehmer::table)
-		.values(&member)
-		.execute(&mut connection)
+	match connection
+		.transaction::<_, diesel::result::Error, _>(|con| {
+			let (member, lager) = (&member, lager.id);
+			async move {
+				let (id, anmeldedatum) = diesel::insert_into(db::schema::teilnehmer::table)
+					.values((member, db::schema::teilnehmer::lager.eq(lager)))
+					.returning((db::schema::teilnehmer::id, db::schema::teilnehmer::anmeldedatum))
+					.get_result::<(i32, PrimitiveDateTime)>(con)
+					.await?;
+				consent::record(con, consent::Signer::Member(id), consent::AGB_DOCUMENTS).await?;
+				Ok((id, anmeldedatum))
+			}
+			.scope_boxed()
+		})
 		.await
 	{
 		Err(error) => {
 			warn!(%error, "Error inserting into database");
-			(StatusCode::INTERNAL_SERVER_ERROR, SignupResult {
-				error: Some(
-					format!(
-						"Es ist ein Datenbank-Fehler aufgetreten.\n{}",
-						state.config.error_message
-					)
-					.into(),
-				),
-			})
+			(
+				StatusCode::INTERNAL_SERVER_ERROR,
+				SignupResult::error(format!(
+					"Es ist ein Datenbank-Fehler aufgetreten.\n{}",
+					state.config.error_message
+				)),
+			)
+		}
+		Ok((id, anmeldedatum)) => {
+			if let Some(draft) = draft {
+				if let Err(error) = state.db.delete_signup_draft(&draft).await {
+					warn!(%error, "Failed to delete signup draft");
+				}
+			}
+			let cancel_link =
+				state.config.cancellation.as_ref().and(state.config.link_secret.as_ref()).map(
+					|secret| {
+						state.config.link(&format!(
+							"/abmeldung?token={}",
+							cancel::create_token(secret, id, &anmeldedatum)
+						))
+					},
+				);
+			signup_mail(state, member, cancel_link).await
 		}
-		Ok(_) => signup_mail(state, member).await,
 	}
 }
 
@@ -171,9 +262,16 @@ pub async fn signup(
 pub async fn signup_nojs(
 	extract::State(state): ExtractState, Form(body): Form<HashMap<String, String>>,
 ) -> Response {
-	let (status, result) = signup_internal(&state, body).await;
-	if let Some(error) = result.error {
-		(status, error.message).into_response()
+	let (status, result) = signup_internal(&state, body.clone()).await;
+	if !result.errors.is_empty() {
+		form_schema::render_error_page(
+			status,
+			"Anmeldung",
+			"/api/signup-nojs",
+			&db::fields::member(),
+			&body,
+			&result.errors,
+		)
 	} else {
 		debug_assert_eq!(status, StatusCode::OK);
 		Response::builder()
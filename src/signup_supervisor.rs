@@ -14,15 +14,11 @@ use time::{Date, Duration, OffsetDateTime, PrimitiveDateTime};
 use tracing::{error, warn};
 
 use crate::db::models::{self, Gender, date, opt_date};
-use crate::{ExtractState, State, db};
+use crate::signup::SignupResult;
+use crate::{ExtractState, State, db, form_schema};
 
 type DbResult<T> = anyhow::Result<T>;
 
-#[derive(Clone, Debug, Serialize)]
-struct SignupResult {
-	error: Option<db::FormError>,
-}
-
 #[derive(Clone, Debug, Serialize)]
 pub(crate) struct ResignupResult {
 	error: Option<String>,
@@ -68,9 +64,9 @@ async fn signup_internal(
 	// Get the body of the request
 	let supervisor = match db::models::Supervisor::from_hashmap(body.clone()) {
 		Ok(supervisor) => supervisor,
-		Err(error) => {
-			warn!(?error, "Error handling form content");
-			return (StatusCode::BAD_REQUEST, SignupResult { error: Some(error) });
+		Err(errors) => {
+			warn!(?errors, "Error handling form content");
+			return (StatusCode::BAD_REQUEST, SignupResult::errors(errors));
 		}
 	};
 	if let Some(log_file) = &state.config.log_file {
@@ -94,20 +90,18 @@ async fn signup_internal(
 		}
 	}
 
-	match state.db.signup_supervisor(&supervisor, false).await {
+	match state.db.signup_supervisor(&supervisor, false, crate::season::current().id).await {
 		Err(error) => {
 			warn!(%error, "Error inserting into database");
-			(StatusCode::INTERNAL_SERVER_ERROR, SignupResult {
-				error: Some(
-					format!(
-						"Es ist ein Datenbank-Fehler aufgetreten.\n{}",
-						state.config.error_message
-					)
-					.into(),
-				),
-			})
+			(
+				StatusCode::INTERNAL_SERVER_ERROR,
+				SignupResult::error(format!(
+					"Es ist ein Datenbank-Fehler aufgetreten.\n{}",
+					state.config.error_message
+				)),
+			)
 		}
-		Ok(()) => (StatusCode::OK, SignupResult { error: None }),
+		Ok(()) => (StatusCode::OK, SignupResult::default()),
 	}
 }
 
@@ -121,9 +115,16 @@ pub async fn signup(
 pub async fn signup_nojs(
 	extract::State(state): ExtractState, Form(body): Form<HashMap<String, String>>,
 ) -> Response {
-	let (status, result) = signup_internal(&state, body).await;
-	if let Some(error) = result.error {
-		Response::builder().status(status).body(Body::new(error.message)).unwrap()
+	let (status, result) = signup_internal(&state, body.clone()).await;
+	if !result.errors.is_empty() {
+		form_schema::render_error_page(
+			status,
+			"Betreuer-Anmeldung",
+			"/api/signup-supervisor-nojs",
+			&db::fields::supervisor(),
+			&body,
+			&result.errors,
+		)
 	} else {
 		debug_assert_eq!(status, StatusCode::OK);
 		Response::builder()
@@ -158,7 +159,7 @@ pub async fn resignup(
 		let mut connection = state.db.get().await?;
 
 		let supervisor = match betreuer::table
-			.filter(mail.eq(mail2))
+			.filter(mail.eq(mail2).and(geloescht.is_null()))
 			.first::<models::FullSupervisor>(&mut connection)
 			.await
 		{
@@ -221,7 +222,12 @@ pub async fn get_data(
 		let since = OffsetDateTime::now_utc() - Duration::days(1);
 		let since_primitive = PrimitiveDateTime::new(since.date(), since.time());
 		let supervisor = match betreuer::table
-			.filter(signup_token.eq(token).and(signup_token_time.gt(since_primitive)))
+			.filter(
+				signup_token
+					.eq(token)
+					.and(signup_token_time.gt(since_primitive))
+					.and(geloescht.is_null()),
+			)
 			.first::<models::FullSupervisor>(&mut connection)
 			.await
 		{
@@ -283,30 +289,30 @@ pub async fn get_data(
 async fn presignup_internal(
 	state: &State, mut body: HashMap<String, String>,
 ) -> (StatusCode, SignupResult) {
-	let err = |msg| (StatusCode::BAD_REQUEST, SignupResult { error: Some(msg) });
-	let internal_err =
-		|msg: String| (StatusCode::INTERNAL_SERVER_ERROR, SignupResult { error: Some(msg.into()) });
-
-	// Get the body of the request
-	let grund = match db::get_freetext_str!(body, "grund") {
-		Ok(res) => res,
-		Err(error) => {
-			warn!(?error, "Error handling form content");
-			return err(error);
-		}
-	};
-	let kommentar = match db::get_freetext_str!(body, "kommentar") {
-		Ok(res) => res,
-		Err(error) => {
-			warn!(?error, "Error handling form content");
-			return err(error);
+	let internal_err = |msg: String| (StatusCode::INTERNAL_SERVER_ERROR, SignupResult::error(msg));
+
+	// Get the body of the request, collect all errors
+	let mut errors = db::FormErrors::default();
+	let extra = db::fields::presignup()
+		.into_iter()
+		.filter(|f| db::fields::PRESIGNUP_EXTRA.contains(&f.id))
+		.collect::<Vec<_>>();
+	db::fields::check(&extra, &body, &mut errors);
+	let grund = errors.check(db::get_freetext_str!(body, "grund"));
+	let kommentar = errors.check(db::get_freetext_str!(body, "kommentar"));
+	let supervisor = match db::models::Supervisor::from_pre_hashmap(body) {
+		Ok(supervisor) => Some(supervisor),
+		Err(e) => {
+			e.into_iter().for_each(|e| errors.push(e));
+			None
 		}
 	};
-	let supervisor = match db::models::Supervisor::from_pre_hashmap(body) {
-		Ok(supervisor) => supervisor,
-		Err(error) => {
-			warn!(?error, "Error handling form content");
-			return err(error);
+	let (grund, kommentar, supervisor) = match errors.into_result((grund, kommentar, supervisor)) {
+		Ok((Some(grund), Some(kommentar), Some(supervisor))) => (grund, kommentar, supervisor),
+		Ok(_) => unreachable!("Missing values always produce an error"),
+		Err(errors) => {
+			warn!(?errors, "Error handling form content");
+			return (StatusCode::BAD_REQUEST, SignupResult::errors(errors));
 		}
 	};
 	if let Some(log_file) = &state.config.log_file {
@@ -338,7 +344,7 @@ async fn presignup_internal(
 
 		// Check if the e-mail already exists
 		match betreuer::table
-			.filter(mail.eq(&supervisor_mail))
+			.filter(mail.eq(&supervisor_mail).and(geloescht.is_null()))
 			.select(id)
 			.first::<i32>(&mut state.db.get().await?)
 			.await
@@ -352,7 +358,12 @@ async fn presignup_internal(
 	{
 		Err(error) => {
 			error!(%error, "Failed to get supervisor by mail");
-			return err("Es ist leider ein Fehler suchen der E-Mailadresse aufgetreten".into());
+			return (
+				StatusCode::BAD_REQUEST,
+				SignupResult::error(
+					"Es ist leider ein Fehler suchen der E-Mailadresse aufgetreten",
+				),
+			);
 		}
 		Ok(true) => {
 			// Already exists, send mail
@@ -360,12 +371,14 @@ async fn presignup_internal(
 			if let Err(error) = state.mail.send_supervisor_presignup_failed(&supervisor).await {
 				error!(%error, "Error sending presignup failed e-mail");
 			}
-			return (StatusCode::OK, SignupResult { error: None });
+			return (StatusCode::OK, SignupResult::default());
 		}
 		Ok(false) => {}
 	};
 
-	if let Err(error) = state.db.signup_supervisor(&supervisor, true).await {
+	if let Err(error) =
+		state.db.signup_supervisor(&supervisor, true, crate::season::current().id).await
+	{
 		warn!(%error, "Error inserting into database");
 		return internal_err(format!(
 			"Es ist ein Datenbank-Fehler aufgetreten.\n{}",
@@ -381,7 +394,7 @@ async fn presignup_internal(
 		}
 		Ok(()) => {
 			// Successful
-			(StatusCode::OK, SignupResult { error: None })
+			(StatusCode::OK, SignupResult::default())
 		}
 	}
 }
@@ -396,9 +409,16 @@ pub async fn presignup(
 pub async fn presignup_nojs(
 	extract::State(state): ExtractState, Form(body): Form<HashMap<String, String>>,
 ) -> Response {
-	let (status, result) = presignup_internal(&state, body).await;
-	if let Some(error) = result.error {
-		Response::builder().status(status).body(Body::new(error.message)).unwrap()
+	let (status, result) = presignup_internal(&state, body.clone()).await;
+	if !result.errors.is_empty() {
+		form_schema::render_error_page(
+			status,
+			"Betreuer-Anmeldung",
+			"/api/presignup-supervisor-nojs",
+			&db::fields::presignup(),
+			&body,
+			&result.errors,
+		)
 	} else {
 		debug_assert_eq!(status, StatusCode::OK);
 		Response::builder()
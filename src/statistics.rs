@@ -0,0 +1,294 @@
+//! Anonymized numbers of past camps.
+//!
+//! Before [`crate::admin::remove_lager`] removes the personal data of a season, it stores a
+//! snapshot with counts for the camp. The snapshots are kept permanently, so the youth board
+//! can compare several years. They contain no names, addresses or other personal data, ages,
+//! genders and regions with only a few participants are combined.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use axum::{Json, extract};
+use diesel::prelude::*;
+use diesel_async::{AsyncPgConnection, RunQueryDsl};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use time::{Date, PrimitiveDateTime};
+use tracing::{info, warn};
+
+use crate::db::models::{Gender, Lager, years_old};
+use crate::{ExtractState, WebResult, db};
+
+type DbResult<T> = anyhow::Result<T>;
+
+/// Groups with fewer participants are counted as [`OTHER_GROUP`].
+const MIN_GROUP_SIZE: usize = 3;
+const OTHER_GROUP: &str = "Andere";
+
+/// The counts of a camp.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq, Serialize)]
+pub struct Snapshot {
+	teilnehmer: usize,
+	/// Age at the start of the camp
+	alter: BTreeMap<String, usize>,
+	geschlecht: BTreeMap<String, usize>,
+	/// First two digits of the postal code in Germany, the country otherwise
+	regionen: BTreeMap<String, usize>,
+	/// Participants whose family was not known from the last camp
+	erstteilnehmer: usize,
+	/// Participants whose family agreed to keep their data last year
+	rueckkehrer: usize,
+	/// Time between signup and payment
+	zahlung: BTreeMap<String, usize>,
+	betreuer: i64,
+}
+
+/// A snapshot in the list of all years.
+#[derive(Clone, Debug, Serialize)]
+pub struct Trend {
+	lager: String,
+	#[serde(with = "crate::db::models::date")]
+	beginn: Date,
+	#[serde(flatten)]
+	daten: Snapshot,
+}
+
+/// The fields of a participant that are needed for the statistics.
+#[derive(Clone, Debug, Queryable)]
+struct Member {
+	id: i32,
+	geburtsdatum: Date,
+	geschlecht: Gender,
+	plz: String,
+	land: String,
+	eltern_mail: String,
+	anmeldedatum: PrimitiveDateTime,
+	bezahlt: bool,
+}
+
+fn region(member: &Member) -> String {
+	match member.plz.get(..2) {
+		Some(prefix) if member.land == "Deutschland" => format!("PLZ {prefix}"),
+		_ => member.land.clone(),
+	}
+}
+
+/// Combine groups with fewer than [`MIN_GROUP_SIZE`] participants, so single children cannot be
+/// identified.
+fn combine_small(groups: BTreeMap<String, usize>) -> BTreeMap<String, usize> {
+	let (mut res, small): (BTreeMap<_, _>, BTreeMap<_, _>) =
+		groups.into_iter().partition(|(_, count)| *count >= MIN_GROUP_SIZE);
+	let mut other = small.values().sum::<usize>();
+	// A small rest could be computed from the total, it takes the next smallest groups
+	while other > 0 && other < MIN_GROUP_SIZE {
+		let Some(name) = res.iter().min_by_key(|(_, count)| **count).map(|(name, _)| name.clone())
+		else {
+			break;
+		};
+		other += res.remove(&name).unwrap_or_default();
+	}
+	if other > 0 {
+		*res.entry(OTHER_GROUP.to_string()).or_default() += other;
+	}
+	res
+}
+
+fn payment(member: &Member, paid: &HashMap<i32, PrimitiveDateTime>) -> &'static str {
+	if !member.bezahlt {
+		return "nicht bezahlt";
+	}
+	// Payments before the audit log existed have no time
+	let Some(time) = paid.get(&member.id) else { return "unbekannt" };
+	match (*time - member.anmeldedatum).whole_days() {
+		..=7 => "bis 1 Woche",
+		8..=14 => "bis 2 Wochen",
+		15..=28 => "bis 4 Wochen",
+		_ => "später",
+	}
+}
+
+/// Count the participants of a camp.
+///
+/// `returning` contains the trimmed and lowercase parent mail addresses of returning families,
+/// `paid` the time at which a participant was marked as paid.
+fn summarize(
+	members: &[Member], beginn: Date, returning: &HashSet<String>,
+	paid: &HashMap<i32, PrimitiveDateTime>, betreuer: i64,
+) -> Snapshot {
+	let mut res = Snapshot { teilnehmer: members.len(), betreuer, ..Default::default() };
+	for member in members {
+		*res.alter.entry(years_old(member.geburtsdatum, &beginn).to_string()).or_default() += 1;
+		*res.geschlecht.entry(member.geschlecht.to_string()).or_default() += 1;
+		*res.regionen.entry(region(member)).or_default() += 1;
+		if returning.contains(&member.eltern_mail.trim().to_lowercase()) {
+			res.rueckkehrer += 1;
+		} else {
+			res.erstteilnehmer += 1;
+		}
+		*res.zahlung.entry(payment(member, paid).to_string()).or_default() += 1;
+	}
+	res.alter = combine_small(res.alter);
+	res.geschlecht = combine_small(res.geschlecht);
+	res.regionen = combine_small(res.regionen);
+	res
+}
+
+/// Store the numbers of a camp.
+///
+/// Has to run before the returning families are replaced and the participants are removed. Does
+/// nothing if the camp already has a snapshot.
+pub async fn snapshot(connection: &mut AsyncPgConnection, lager: &Lager) -> DbResult<()> {
+	use db::schema::{betreuer_teilnahme, protokoll, rueckkehrer, statistik, teilnehmer};
+
+	let members = teilnehmer::table
+		.filter(teilnehmer::lager.eq(lager.id).and(teilnehmer::geloescht.is_null()))
+		.select((
+			teilnehmer::id,
+			teilnehmer::geburtsdatum,
+			teilnehmer::geschlecht,
+			teilnehmer::plz,
+			teilnehmer::land,
+			teilnehmer::eltern_mail,
+			teilnehmer::anmeldedatum,
+			teilnehmer::bezahlt,
+		))
+		.load::<Member>(connection)
+		.await?;
+	let returning = rueckkehrer::table
+		.select(rueckkehrer::eltern_mail)
+		.load::<String>(connection)
+		.await?
+		.into_iter()
+		.map(|m| m.trim().to_lowercase())
+		.collect::<HashSet<_>>();
+
+	// The first change that marked a participant as paid
+	let ids = members.iter().map(|m| m.id.to_string()).collect::<Vec<_>>();
+	let changes = protokoll::table
+		.filter(protokoll::objekt.eq("teilnehmer"))
+		.filter(protokoll::objekt_id.eq_any(&ids))
+		.filter(protokoll::aenderung.like("%\"bezahlt\"%"))
+		.order(protokoll::zeit)
+		.select((protokoll::objekt_id, protokoll::zeit, protokoll::aenderung))
+		.load::<(Option<String>, PrimitiveDateTime, String)>(connection)
+		.await?;
+	let mut paid = HashMap::new();
+	for (id, zeit, aenderung) in changes {
+		let Some(id) = id.and_then(|id| id.parse::<i32>().ok()) else { continue };
+		let aenderung = serde_json::from_str::<Value>(&aenderung)?;
+		if aenderung["bezahlt"]["neu"] == Value::Bool(true) {
+			paid.entry(id).or_insert(zeit);
+		}
+	}
+
+	let betreuer = betreuer_teilnahme::table
+		.filter(betreuer_teilnahme::lager.eq(lager.id))
+		.count()
+		.get_result::<i64>(connection)
+		.await?;
+
+	let daten = summarize(&members, lager.beginn, &returning, &paid, betreuer);
+	diesel::insert_into(statistik::table)
+		.values((
+			statistik::lager.eq(lager.id),
+			statistik::daten.eq(serde_json::to_string(&daten)?),
+		))
+		.on_conflict(statistik::lager)
+		.do_nothing()
+		.execute(connection)
+		.await?;
+	info!(lager = lager.name, teilnehmer = daten.teilnehmer, "Stored camp statistics");
+	Ok(())
+}
+
+/// The snapshots of all camps, the oldest first.
+pub async fn trends(extract::State(state): ExtractState) -> WebResult<Json<Vec<Trend>>> {
+	match async {
+		use db::schema::{lager, statistik};
+
+		let mut connection = state.db.get().await?;
+		let rows = statistik::table
+			.inner_join(lager::table)
+			.order(lager::beginn)
+			.select((lager::name, lager::beginn, statistik::daten))
+			.load::<(String, Date, String)>(&mut connection)
+			.await?;
+		let mut res = Vec::with_capacity(rows.len());
+		for (lager, beginn, daten) in rows {
+			res.push(Trend { lager, beginn, daten: serde_json::from_str(&daten)? });
+		}
+		DbResult::Ok(res)
+	}
+	.await
+	{
+		Err(error) => {
+			warn!(%error, "Failed to load camp statistics");
+			crate::error_response(&state)
+		}
+		Ok(r) => Ok(Json(r)),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use time::macros::{date, datetime};
+
+	use super::*;
+
+	fn member(id: i32, plz: &str, mail: &str, bezahlt: bool) -> Member {
+		Member {
+			id,
+			geburtsdatum: date!(2015 - 08 - 01),
+			geschlecht: if id % 2 == 0 { Gender::Female } else { Gender::Male },
+			plz: plz.into(),
+			land: "Deutschland".into(),
+			eltern_mail: mail.into(),
+			anmeldedatum: datetime!(2026-03-01 10:00),
+			bezahlt,
+		}
+	}
+
+	#[test]
+	fn summarize_members() {
+		let members = [
+			member(1, "80331", "a@example.com", true),
+			member(2, "80333", "b@example.com", true),
+			member(3, "80335", "b@example.com", false),
+			member(4, "10115", "c@example.com", true),
+			member(5, "20095", "d@example.com", false),
+			member(6, "30159", "e@example.com", false),
+		];
+		let returning = HashSet::from(["b@example.com".to_string()]);
+		let paid =
+			HashMap::from([(1, datetime!(2026-03-05 12:00)), (2, datetime!(2026-04-20 9:00))]);
+		let res = summarize(&members, date!(2026 - 08 - 01), &returning, &paid, 5);
+
+		assert_eq!(res.teilnehmer, 6);
+		assert_eq!(res.alter, BTreeMap::from([("11".into(), 6)]));
+		assert_eq!(res.geschlecht, BTreeMap::from([("m".into(), 3), ("w".into(), 3)]));
+		assert_eq!(res.regionen, BTreeMap::from([("PLZ 80".into(), 3), ("Andere".into(), 3)]));
+		assert_eq!((res.erstteilnehmer, res.rueckkehrer), (4, 2));
+		assert_eq!(
+			res.zahlung,
+			BTreeMap::from([
+				("bis 1 Woche".into(), 1),
+				("später".into(), 1),
+				("nicht bezahlt".into(), 3),
+				("unbekannt".into(), 1),
+			])
+		);
+		assert_eq!(res.betreuer, 5);
+	}
+
+	#[test]
+	fn combine_small_groups() {
+		let groups = |g: &[(&str, usize)]| {
+			g.iter().map(|(k, v)| (k.to_string(), *v)).collect::<BTreeMap<_, _>>()
+		};
+		assert_eq!(combine_small(groups(&[("m", 10), ("w", 1)])), groups(&[("Andere", 11)]));
+		assert_eq!(
+			combine_small(groups(&[("8", 1), ("9", 4), ("10", 5)])),
+			groups(&[("10", 5), ("Andere", 5)])
+		);
+		assert_eq!(combine_small(groups(&[("8", 3), ("9", 4)])), groups(&[("8", 3), ("9", 4)]));
+	}
+}
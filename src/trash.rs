@@ -0,0 +1,242 @@
+//! Trash for deleted members, supervisors and cancellations.
+//!
+//! Deleting a member or supervisor in the admin area, and removing the data of a camp, only sets
+//! `geloescht`. Such entries are hidden from all lists and counts, but can be restored until they
+//! are purged after `trash_retention_days`.
+
+use std::sync::Arc;
+
+use anyhow::bail;
+use axum::{Extension, Json, extract};
+use diesel::prelude::*;
+use diesel_async::scoped_futures::ScopedFutureExt;
+use diesel_async::{AsyncConnection, RunQueryDsl};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use time::{Duration, OffsetDateTime, PrimitiveDateTime};
+use tracing::{error, info, warn};
+
+use crate::audit::Audit;
+use crate::db::models::{Cancellation, FullSupervisor, FullTeilnehmer, primitive_datetime};
+use crate::{ExtractState, State, WebResult, db};
+
+type DbResult<T> = anyhow::Result<T>;
+
+/// Purge the trash this often.
+const PURGE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60 * 60);
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Art {
+	Teilnehmer,
+	Betreuer,
+	Stornierung,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct RestoreData {
+	art: Art,
+	id: i32,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Trashed<T> {
+	#[serde(flatten)]
+	entry: T,
+	#[serde(with = "primitive_datetime")]
+	geloescht: PrimitiveDateTime,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Trash {
+	teilnehmer: Vec<Trashed<FullTeilnehmer>>,
+	betreuer: Vec<Trashed<FullSupervisor>>,
+	stornierung: Vec<Trashed<Cancellation>>,
+}
+
+/// Entries that were moved to the trash before this time get purged.
+fn purge_before(state: &State) -> PrimitiveDateTime {
+	let since = OffsetDateTime::now_utc() - Duration::days(state.config.trash_retention_days);
+	PrimitiveDateTime::new(since.date(), since.time())
+}
+
+/// All entries in the trash, the last deleted first.
+pub async fn list(extract::State(state): ExtractState) -> WebResult<Json<Trash>> {
+	match async {
+		use db::schema::{betreuer, stornierung, teilnehmer};
+
+		let mut connection = state.db.get().await?;
+		let teilnehmer = teilnehmer::table
+			.filter(teilnehmer::geloescht.is_not_null())
+			.order(teilnehmer::geloescht.desc())
+			.load::<FullTeilnehmer>(&mut connection)
+			.await?
+			.into_iter()
+			.filter_map(|entry| entry.geloescht.map(|geloescht| Trashed { entry, geloescht }))
+			.collect();
+		let betreuer = betreuer::table
+			.filter(betreuer::geloescht.is_not_null())
+			.order(betreuer::geloescht.desc())
+			.load::<FullSupervisor>(&mut connection)
+			.await?
+			.into_iter()
+			.filter_map(|entry| entry.geloescht.map(|geloescht| Trashed { entry, geloescht }))
+			.collect();
+		let stornierung = stornierung::table
+			.filter(stornierung::geloescht.is_not_null())
+			.order(stornierung::geloescht.desc())
+			.load::<Cancellation>(&mut connection)
+			.await?
+			.into_iter()
+			.filter_map(|entry| entry.geloescht.map(|geloescht| Trashed { entry, geloescht }))
+			.collect();
+		DbResult::Ok(Trash { teilnehmer, betreuer, stornierung })
+	}
+	.await
+	{
+		Err(error) => {
+			warn!(%error, "Failed to list trash");
+			crate::error_response(&state)
+		}
+		Ok(r) => Ok(Json(r)),
+	}
+}
+
+/// Move an entry out of the trash.
+pub(crate) async fn restore(
+	extract::State(state): ExtractState, Extension(audit): Extension<Audit>,
+	Json(data): Json<RestoreData>,
+) -> WebResult<&'static str> {
+	match async {
+		use db::schema::{betreuer, stornierung, teilnehmer};
+
+		let mut connection = state.db.get().await?;
+		let (objekt, count) =
+			match data.art {
+				Art::Teilnehmer => (
+					"teilnehmer",
+					diesel::update(teilnehmer::table.filter(
+						teilnehmer::id.eq(data.id).and(teilnehmer::geloescht.is_not_null()),
+					))
+					.set(teilnehmer::geloescht.eq(None::<PrimitiveDateTime>))
+					.execute(&mut connection)
+					.await?,
+				),
+				Art::Betreuer => {
+					(
+						"betreuer",
+						diesel::update(betreuer::table.filter(
+							betreuer::id.eq(data.id).and(betreuer::geloescht.is_not_null()),
+						))
+						.set(betreuer::geloescht.eq(None::<PrimitiveDateTime>))
+						.execute(&mut connection)
+						.await?,
+					)
+				}
+				Art::Stornierung => (
+					"stornierung",
+					diesel::update(stornierung::table.filter(
+						stornierung::id.eq(data.id).and(stornierung::geloescht.is_not_null()),
+					))
+					.set(stornierung::geloescht.eq(None::<PrimitiveDateTime>))
+					.execute(&mut connection)
+					.await?,
+				),
+			};
+		if count == 0 {
+			bail!("Entry not found in trash");
+		}
+		audit
+			.log(
+				&mut connection,
+				objekt,
+				Some(data.id.to_string()),
+				"wiederhergestellt",
+				json!({ "geloescht": true }),
+				json!({ "geloescht": false }),
+			)
+			.await
+	}
+	.await
+	{
+		Err(error) => {
+			warn!(%error, "Failed to restore entry");
+			crate::error_response(&state)
+		}
+		Ok(()) => Ok("Success"),
+	}
+}
+
+/// Delete entries from the trash that are older than the retention time.
+///
+/// Their entries in the audit log are redacted, see [`Audit::redact`].
+async fn purge(state: &State) -> DbResult<()> {
+	use db::schema::{betreuer, stornierung, teilnehmer};
+
+	let before = purge_before(state);
+	state
+		.db
+		.get()
+		.await?
+		.transaction::<_, anyhow::Error, _>(|con| {
+			async move {
+				let teilnehmer =
+					diesel::delete(teilnehmer::table.filter(teilnehmer::geloescht.lt(before)))
+						.returning(teilnehmer::id)
+						.get_results::<i32>(con)
+						.await?;
+				let betreuer =
+					diesel::delete(betreuer::table.filter(betreuer::geloescht.lt(before)))
+						.returning(betreuer::id)
+						.get_results::<i32>(con)
+						.await?;
+				let stornierung =
+					diesel::delete(stornierung::table.filter(stornierung::geloescht.lt(before)))
+						.returning(stornierung::id)
+						.get_results::<i32>(con)
+						.await?;
+				if teilnehmer.is_empty() && betreuer.is_empty() && stornierung.is_empty() {
+					return Ok(());
+				}
+
+				info!(
+					teilnehmer = teilnehmer.len(),
+					betreuer = betreuer.len(),
+					stornierung = stornierung.len(),
+					"Purged trash"
+				);
+				let audit = Audit::system();
+				let ids = |ids: &[i32]| ids.iter().map(i32::to_string).collect::<Vec<_>>();
+				audit.redact(con, "teilnehmer", &ids(&teilnehmer)).await?;
+				audit.redact(con, "betreuer", &ids(&betreuer)).await?;
+				audit.redact(con, "stornierung", &ids(&stornierung)).await?;
+				let unzustellbar = crate::bounce::remove_unused(con).await?;
+				audit
+					.log(
+						con,
+						"papierkorb",
+						None,
+						"geleert",
+						json!({
+							"teilnehmer": teilnehmer.len(),
+							"betreuer": betreuer.len(),
+							"stornierung": stornierung.len(),
+							"unzustellbar": unzustellbar,
+						}),
+						serde_json::Value::Null,
+					)
+					.await
+			}
+			.scope_boxed()
+		})
+		.await
+}
+
+pub async fn run(state: Arc<State>) {
+	loop {
+		if let Err(error) = purge(&state).await {
+			error!(%error, "Failed to purge trash");
+		}
+		tokio::time::sleep(PURGE_INTERVAL).await;
+	}
+}